@@ -19,7 +19,7 @@ def even_fib():
         b = temp
         i = i + 1
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         visitor.get_graph()
     }
@@ -31,7 +31,7 @@ def even_fib():
     yield n + 2
     yield n + 3
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         visitor.get_graph()
     }
@@ -1,6 +1,9 @@
 use std::collections::{BTreeMap, VecDeque};
 use tohdl_ir::graph::CFG;
-use tohdl_ir::{expr::VarExpr, graph::*};
+use tohdl_ir::{
+    expr::{Expr, Operator, UnaryOperator, VarExpr},
+    graph::*,
+};
 use tohdl_passes::{manager::PassManager, transform::*, BasicTransform};
 
 pub fn graph_to_python(mut graph: CFG) -> String {
@@ -39,6 +42,63 @@ pub fn graph_to_python(mut graph: CFG) -> String {
     result
 }
 
+pub trait ToPython {
+    fn to_python(&self) -> String;
+}
+
+impl ToPython for Expr {
+    fn to_python(&self) -> String {
+        match self {
+            Expr::Var(_) | Expr::Int(_) => format!("{}", self),
+            // Python's `and`/`or` return an operand, hardware produces 0 or 1
+            Expr::BinOp(left, Operator::And, right) => format!(
+                "int(bool({}) and bool({}))",
+                left.to_python(),
+                right.to_python()
+            ),
+            Expr::BinOp(left, Operator::Or, right) => format!(
+                "int(bool({}) or bool({}))",
+                left.to_python(),
+                right.to_python()
+            ),
+            // Comparisons produce 0 or 1 like in hardware, not `True` or `False`
+            Expr::BinOp(
+                left,
+                op @ (Operator::Lt
+                | Operator::Gt
+                | Operator::LtE
+                | Operator::GtE
+                | Operator::Eq
+                | Operator::NotEq),
+                right,
+            ) => format!("int({} {} {})", left.to_python(), op, right.to_python()),
+            // The IR truncates towards zero, `//` and `%` round towards negative infinity,
+            // so operands of different signs are negated to divide like ones of the same sign
+            Expr::BinOp(left, op @ (Operator::Div | Operator::Mod), right) => {
+                let (left, right) = (left.to_python(), right.to_python());
+                let op = if *op == Operator::Div { "//" } else { "%" };
+                format!(
+                    "({} {} {} if ({} < 0) == ({} < 0) else -(-{} {} {}))",
+                    left, op, right, left, right, left, op, right
+                )
+            }
+            Expr::BinOp(left, op, right) => {
+                format!("({} {} {})", left.to_python(), op, right.to_python())
+            }
+            Expr::UnaryOp(UnaryOperator::Not, operand) => {
+                format!("int(not {})", operand.to_python())
+            }
+            Expr::UnaryOp(op, operand) => format!("({}{})", op, operand.to_python()),
+            Expr::Mux(cond, then, else_) => format!(
+                "({} if {} else {})",
+                then.to_python(),
+                cond.to_python(),
+                else_.to_python()
+            ),
+        }
+    }
+}
+
 pub struct CodeGen {
     code: String,
     indent: usize,
@@ -74,7 +134,7 @@ impl CodeGen {
                 "{}{} = {}\n",
                 " ".repeat(self.indent),
                 lvalue,
-                node.rvalue
+                node.rvalue.to_python()
             ));
             for succ in self.graph.succs(idx).collect::<Vec<_>>() {
                 self.work(succ);
@@ -151,7 +211,7 @@ impl CodeGen {
                 *var = self.remove_separator(var);
             }
             self.code
-                .push_str(&format!("{}if {}:\n", " ".repeat(self.indent), node.cond));
+                .push_str(&format!("{}if {}:\n", " ".repeat(self.indent), node.cond.to_python()));
             let mut succs = self.graph.succs(idx).collect::<Vec<_>>();
             assert_eq!(succs.len(), 2);
 
@@ -182,7 +242,7 @@ impl CodeGen {
                 " ".repeat(self.indent),
                 node.values
                     .iter()
-                    .map(|arg| format!("{}", arg))
+                    .map(|arg| arg.to_python())
                     .collect::<Vec<String>>()
                     .join(", ")
             ));
@@ -201,7 +261,7 @@ impl CodeGen {
                 " ".repeat(self.indent),
                 node.values
                     .iter()
-                    .map(|arg| format!("{}", arg))
+                    .map(|arg| arg.to_python())
                     .collect::<Vec<String>>()
                     .join(", ")
             ));
@@ -221,7 +281,7 @@ impl CodeGen {
             .split(self.ssa_separator)
             .collect::<Vec<&str>>()
             .join("");
-        VarExpr::new(&processed)
+        var.with_name(&processed)
     }
 }
 
@@ -232,6 +292,29 @@ mod tests {
     use tohdl_ir::graph::CFG;
     use tohdl_passes::{manager::PassManager, transform::*, BasicTransform};
 
+    #[test]
+    fn truncating_division() {
+        let expr = Expr::BinOp(
+            Box::new(Expr::Var(VarExpr::new("n"))),
+            Operator::Div,
+            Box::new(Expr::Int(tohdl_ir::expr::IntExpr::new(-10))),
+        );
+        assert_eq!(
+            expr.to_python(),
+            "(n // -10 if (n < 0) == (-10 < 0) else -(-n // -10))"
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        let expr = Expr::BinOp(
+            Box::new(Expr::Var(VarExpr::new("a"))),
+            Operator::LtE,
+            Box::new(Expr::Var(VarExpr::new("b"))),
+        );
+        assert_eq!(expr.to_python(), "int(a <= b)");
+    }
+
     #[test]
     fn odd_fib() {
         let mut graph = make_odd_fib();
@@ -319,7 +402,7 @@ def even_fib(n):
     yield a
     yield b
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         visitor.get_graph()
     }
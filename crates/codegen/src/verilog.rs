@@ -9,60 +9,34 @@ mod helpers;
 pub use helpers::*;
 mod clean_assignments;
 pub use clean_assignments::*;
+mod lower;
+pub use lower::*;
+mod share;
+pub use share::*;
+mod sim;
+pub use sim::*;
+mod vcd;
+pub use vcd::*;
 use tohdl_ir::graph::CFG;
-use tohdl_passes::{
-    manager::PassManager, optimize::RemoveUnreadVars, transform::{
-        BraunEtAl, ExplicitReturn, FixBranch, InsertCallNodes, InsertFuncNodes, Nonblocking,
-    }, BasicTransform, ContextfulTransfrom, TransformResultType
-};
+use tohdl_passes::ContextfulTransfrom;
 
-pub fn graph_to_verilog(mut graph: CFG) -> String {
-    let mut manager = PassManager::log();
-    manager.add_pass(InsertFuncNodes::transform);
-    manager.add_pass(InsertCallNodes::transform);
-    manager.add_pass(BraunEtAl::transform);
-    manager.apply(&mut graph);
+pub fn graph_to_verilog(graph: CFG) -> String {
+    graph_to_verilog_with(graph, &LowerOptions::default())
+}
 
-    // graph.write_dot("mybug");
-    let mut lower = tohdl_passes::transform::LowerToFsm::default();
-    let result = lower.apply_timed(&mut graph);
-    println!("{result}");
+pub fn graph_to_verilog_with(graph: CFG, options: &LowerOptions) -> String {
+    let Lowered {
+        mut context,
+        states: subgraphs,
+        next_states,
+    } = lower_with(graph, options);
 
     let mut states = vec![];
-
-    let signals = Signals::new();
-    let mut context = Context::new(
-        graph.name.as_str(),
-        graph.get_inputs().cloned().collect(),
-        signals,
-    );
-
-    // Write all new subgraphs to files
-    for (i, subgraph) in lower.get_subgraphs().iter().enumerate() {
-        let mut subgraph = subgraph.clone();
-        // subgraph.write_dot(format!("{}_{}.dot", std::stringify!(graph_to_verilog), i).as_str());
-        let max_memory = {
-            let mut pass = crate::verilog::UseMemory::default();
-            let result = pass.apply_timed(&mut subgraph);
-            println!("{result}");
-            pass.max_memory()
-        };
-
-        let mut manager = PassManager::debug(format!("subgraph_{i}"));
-        manager.add_pass(Nonblocking::transform);
-        manager.add_pass(RemoveLoadsEtc::transform);
-        manager.add_pass(RemoveUnreadVars::transform);
-        manager.add_pass(FixBranch::transform);
-        manager.add_pass(ExplicitReturn::transform);
-        manager.apply(&mut subgraph);
-
-        context.memories.count = std::cmp::max(context.memories.count, max_memory);
-
-        let mut codegen = SingleStateLogic::new(lower.get_external_funcs(i));
+    for (mut subgraph, next_states) in subgraphs.into_iter().zip(next_states) {
+        let mut codegen = SingleStateLogic::new(next_states);
         let result = codegen.apply_timed_contextful(&mut subgraph, &mut context);
         println!("{result}");
         states.push(codegen);
-
     }
 
     let module = new_create_module(states, &context);
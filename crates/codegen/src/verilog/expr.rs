@@ -1,4 +1,4 @@
-use tohdl_ir::expr::Expr;
+use tohdl_ir::expr::{BigInt, Expr, IntExpr, Operator, UnaryOperator, VarExpr};
 
 pub trait ToVerilog {
     fn to_verilog(&self) -> String;
@@ -7,9 +7,313 @@ pub trait ToVerilog {
 impl ToVerilog for Expr {
     fn to_verilog(&self) -> String {
         match self {
-            Expr::Var(_) => format!("$signed({})", self),
-            Expr::Int(_) => format!("$signed({})", self),
+            Expr::Var(var) => var.to_verilog(),
+            Expr::Int(int) => int.to_verilog(),
+            // Logical operators and comparisons produce a single unsigned bit,
+            // which `$signed` would turn into -1, so widen to an integer 0 or 1
+            Expr::BinOp(
+                left,
+                op @ (Operator::And
+                | Operator::Or
+                | Operator::Lt
+                | Operator::Gt
+                | Operator::LtE
+                | Operator::GtE
+                | Operator::Eq
+                | Operator::NotEq),
+                right,
+            ) => format!(
+                "$signed(({} {} {}) ? 1 : 0)",
+                left.to_verilog(),
+                op,
+                right.to_verilog()
+            ),
+            // `>>` shifts in zeros even when signed, Python shifts in the sign
+            Expr::BinOp(left, Operator::RShift, right) => {
+                format!("$signed({} >>> {})", left.to_verilog(), right.to_verilog())
+            }
             Expr::BinOp(left, op, right) => format!("$signed({} {} {})", left.to_verilog(), op, right.to_verilog()),
+            Expr::UnaryOp(UnaryOperator::Not, operand) => {
+                format!("$signed((!{}) ? 1 : 0)", operand.to_verilog())
+            }
+            Expr::UnaryOp(op, operand) => format!("$signed({}{})", op, operand.to_verilog()),
+            Expr::Mux(cond, then, else_) => format!(
+                "$signed({} ? {} : {})",
+                cond.to_verilog(),
+                then.to_verilog(),
+                else_.to_verilog()
+            ),
         }
     }
 }
+
+/// Self-determined width of `expr` as emitted by [ToVerilog], where every expression is signed.
+/// Operands are extended to the width of their operator, which its result wraps around
+pub fn verilog_width(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var(VarExpr { size, signed, .. }) | Expr::Int(IntExpr { size, signed, .. }) => {
+            match signed {
+                true => std::cmp::max(*size, 32),
+                false => std::cmp::max(size + 1, 32),
+            }
+        }
+        Expr::BinOp(
+            _,
+            Operator::And
+            | Operator::Or
+            | Operator::Lt
+            | Operator::Gt
+            | Operator::LtE
+            | Operator::GtE
+            | Operator::Eq
+            | Operator::NotEq,
+            _,
+        ) => 32,
+        Expr::BinOp(left, Operator::LShift | Operator::RShift, _) => verilog_width(left),
+        Expr::BinOp(left, _, right) | Expr::Mux(_, left, right) => {
+            std::cmp::max(verilog_width(left), verilog_width(right))
+        }
+        Expr::UnaryOp(UnaryOperator::Not, _) => 32,
+        Expr::UnaryOp(_, operand) => verilog_width(operand),
+    }
+}
+
+/// Comparisons and logical operators evaluate their operands at the width of those alone
+fn self_determined(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::BinOp(
+            _,
+            Operator::And
+                | Operator::Or
+                | Operator::Lt
+                | Operator::Gt
+                | Operator::LtE
+                | Operator::GtE
+                | Operator::Eq
+                | Operator::NotEq,
+            _,
+        ) | Expr::UnaryOp(UnaryOperator::Not, _)
+    )
+}
+
+/// Width `expr` is evaluated at as part of an expression of `width` bits.
+/// Arithmetic takes the width of its destination when that is wider,
+/// so that `x:i64 = (a * b)` does not wrap around 32 bits
+pub fn context_width(expr: &Expr, width: usize) -> usize {
+    match self_determined(expr) {
+        true => verilog_width(expr),
+        false => std::cmp::max(verilog_width(expr), width),
+    }
+}
+
+/// Operands of `expr` evaluated at `width` bits, each with the width of its context.
+/// Shift amounts, conditions and the operands of [self_determined] operators have none
+pub fn operands(expr: &Expr, width: usize) -> Vec<(&Expr, usize)> {
+    let width = context_width(expr, width);
+    match expr {
+        Expr::Var(_) | Expr::Int(_) => vec![],
+        Expr::BinOp(left, _, right) if self_determined(expr) => vec![(left, 0), (right, 0)],
+        Expr::UnaryOp(UnaryOperator::Not, operand) => vec![(operand, 0)],
+        Expr::BinOp(left, Operator::LShift | Operator::RShift, right) => {
+            vec![(left, width), (right, 0)]
+        }
+        Expr::BinOp(left, _, right) => vec![(left, width), (right, width)],
+        Expr::UnaryOp(_, operand) => vec![(operand, width)],
+        Expr::Mux(cond, then, else_) => vec![(cond, 0), (then, width), (else_, width)],
+    }
+}
+
+/// Emits `expr` for a destination of `width` bits, see [context_width]
+pub fn to_verilog_at(expr: &Expr, width: usize) -> String {
+    let width = context_width(expr, width);
+    if width == verilog_width(expr) {
+        return expr.to_verilog();
+    }
+    let at = |operand: &Expr| to_verilog_at(operand, width);
+    match expr {
+        Expr::Var(var) if var.signed => format!(
+            "$signed({{{{{}{{{}[{}]}}}}, {}[{}:0]}})",
+            width - var.size,
+            var,
+            var.size - 1,
+            var,
+            var.size - 1
+        ),
+        Expr::Var(var) => format!(
+            "$signed({{{}'b0, {}[{}:0]}})",
+            width - var.size,
+            var,
+            var.size - 1
+        ),
+        Expr::Int(int) => IntExpr::sized(int.value.clone(), width, true).to_verilog(),
+        Expr::BinOp(left, Operator::LShift, right) => {
+            format!("$signed({} << {})", at(left), right.to_verilog())
+        }
+        Expr::BinOp(left, Operator::RShift, right) => {
+            format!("$signed({} >>> {})", at(left), right.to_verilog())
+        }
+        Expr::BinOp(left, op, right) => format!("$signed({} {} {})", at(left), op, at(right)),
+        Expr::UnaryOp(op, operand) => format!("$signed({}{})", op, at(operand)),
+        Expr::Mux(cond, then, else_) => format!(
+            "$signed({} ? {} : {})",
+            cond.to_verilog(),
+            at(then),
+            at(else_)
+        ),
+    }
+}
+
+/// Arithmetic is signed and at least 32 bits wide, as in Python,
+/// so narrower or unsigned variables are extended before use
+impl ToVerilog for VarExpr {
+    fn to_verilog(&self) -> String {
+        let bits = format!("{}[{}:0]", self, self.size - 1);
+        match (self.signed, self.size) {
+            (true, 32) => format!("$signed({})", self),
+            (true, size) if size > 32 => format!("$signed({})", bits),
+            (true, size) => format!(
+                "$signed({{{{{}{{{}[{}]}}}}, {}}})",
+                32 - size,
+                self,
+                size - 1,
+                bits
+            ),
+            (false, size) => format!(
+                "$signed({{{}'b0, {}}})",
+                std::cmp::max(32 - size.min(32), 1),
+                bits
+            ),
+        }
+    }
+}
+
+/// Constants are extended like variables of the same type
+impl ToVerilog for IntExpr {
+    fn to_verilog(&self) -> String {
+        match (self.signed, self.size) {
+            // Unsized literals are 32 bits wide
+            (true, size) if size <= 32 => format!("$signed({})", self),
+            (true, _) => format!("$signed({})", sized_literal(self)),
+            (false, size) => format!(
+                "$signed({{{}'b0, {}}})",
+                std::cmp::max(32 - size.min(32), 1),
+                sized_literal(self)
+            ),
+        }
+    }
+}
+
+/// `-8'sd5` for signed constants and `32'hFFFF_FFFF` for unsigned ones
+fn sized_literal(int: &IntExpr) -> String {
+    if int.signed {
+        let sign = if int.value < BigInt::default() { "-" } else { "" };
+        format!("{}{}'sd{}", sign, int.size, int.value.magnitude())
+    } else {
+        let digits = int.value.to_str_radix(16).to_uppercase();
+        // Group digits in fours from the right
+        let grouped = digits
+            .chars()
+            .rev()
+            .collect::<Vec<_>>()
+            .chunks(4)
+            .map(|chunk| chunk.iter().rev().collect::<String>())
+            .rev()
+            .collect::<Vec<_>>()
+            .join("_");
+        format!("{}'h{}", int.size, grouped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tohdl_ir::expr::VarExpr;
+
+    #[test]
+    fn logical_ops() {
+        let expr = Expr::BinOp(
+            Box::new(Expr::UnaryOp(
+                UnaryOperator::Not,
+                Box::new(Expr::Var(VarExpr::new("a"))),
+            )),
+            Operator::Or,
+            Box::new(Expr::BinOp(
+                Box::new(Expr::Var(VarExpr::new("b"))),
+                Operator::NotEq,
+                Box::new(Expr::UnaryOp(
+                    UnaryOperator::Invert,
+                    Box::new(Expr::Var(VarExpr::new("c"))),
+                )),
+            )),
+        );
+        // println!("{}", expr.to_verilog());
+        assert_eq!(
+            expr.to_verilog(),
+            "$signed(($signed((!$signed(a)) ? 1 : 0) || $signed(($signed(b) != $signed(~$signed(c))) ? 1 : 0)) ? 1 : 0)"
+        );
+    }
+
+    #[test]
+    fn shifts() {
+        let shift = |op| {
+            Expr::BinOp(
+                Box::new(Expr::Var(VarExpr::new("a"))),
+                op,
+                Box::new(Expr::Int(IntExpr::new(2))),
+            )
+            .to_verilog()
+        };
+        assert_eq!(shift(Operator::RShift), "$signed($signed(a) >>> $signed(2))");
+        assert_eq!(shift(Operator::LShift), "$signed($signed(a) << $signed(2))");
+    }
+
+    #[test]
+    fn sized_vars() {
+        let var = |size, signed| {
+            VarExpr::builder()
+                .name("a")
+                .size(size)
+                .signed(signed)
+                .build()
+                .to_verilog()
+        };
+        assert_eq!(var(32, true), "$signed(a)");
+        assert_eq!(var(8, true), "$signed({{24{a[7]}}, a[7:0]})");
+        assert_eq!(var(8, false), "$signed({24'b0, a[7:0]})");
+        assert_eq!(var(32, false), "$signed({1'b0, a[31:0]})");
+        assert_eq!(var(48, true), "$signed(a[47:0])");
+    }
+
+    #[test]
+    fn wide_destinations() {
+        let a = || Box::new(Expr::Var(VarExpr::new("a")));
+        let product = Expr::BinOp(a(), Operator::Mul, Box::new(Expr::Int(IntExpr::new(3))));
+        assert_eq!(to_verilog_at(&product, 32), product.to_verilog());
+        assert_eq!(
+            to_verilog_at(&product, 40),
+            "$signed($signed({{8{a[31]}}, a[31:0]}) * $signed(40'sd3))"
+        );
+        // Compared at its own width
+        let compared = Expr::BinOp(Box::new(product), Operator::Lt, a());
+        assert_eq!(to_verilog_at(&compared, 40), compared.to_verilog());
+    }
+
+    #[test]
+    fn sized_ints() {
+        assert_eq!(IntExpr::new(5).to_verilog(), "$signed(5)");
+        assert_eq!(IntExpr::sized(-5, 8, true).to_verilog(), "$signed(-5)");
+        assert_eq!(
+            IntExpr::sized(-5, 40, true).to_verilog(),
+            "$signed(-40'sd5)"
+        );
+        assert_eq!(
+            IntExpr::new(0xFFFF_FFFFu32).to_verilog(),
+            "$signed({1'b0, 32'hFFFF_FFFF})"
+        );
+        assert_eq!(
+            IntExpr::sized(0x1FFFF, 20, false).to_verilog(),
+            "$signed({12'b0, 20'h1_FFFF})"
+        );
+    }
+}
@@ -1,16 +1,17 @@
 use tohdl_ir::expr::VarExpr;
 use vast::v05::ast::{self as v, Sequential};
 
-use super::{module::Context, SingleStateLogic};
+use super::{expr::to_verilog_at, module::Context, SingleStateLogic};
 
 /// Creates memories and variables stored in reg
 fn create_reg_defs(context: &Context) -> Vec<v::Stmt> {
     (0..context.memories.count)
         .map(|i| {
-            v::Stmt::new_decl(v::Decl::new_int(&format!(
-                "{}{}",
-                context.memories.prefix, i
-            )))
+            let name = format!("{}{}", context.memories.prefix, i);
+            match context.memories.size(i) {
+                32 => v::Stmt::new_decl(v::Decl::new_int(&name)),
+                size => v::Stmt::new_decl(v::Decl::new_reg(&name, size as u64)),
+            }
         })
         .chain(std::iter::once(v::Stmt::new_decl(v::Decl::new_reg(
             &format!("{}", context.states.variable),
@@ -19,6 +20,21 @@ fn create_reg_defs(context: &Context) -> Vec<v::Stmt> {
         .collect()
 }
 
+/// Declares the shared subexpression wires, then assigns them
+fn create_wire_defs(context: &Context) -> Vec<v::Stmt> {
+    let decls = context.wires.values.iter().map(|(var, _)| {
+        v::Stmt::RawStr(format!("logic signed [{}:0] {};", var.size - 1, var))
+    });
+    let assigns = context.wires.values.iter().map(|(var, expr)| {
+        let mut expr = expr.clone();
+        for var in expr.get_vars_iter_mut() {
+            *var = var.with_name(&var.name.replace('.', ""));
+        }
+        v::Stmt::RawStr(format!("assign {} = {};", var, to_verilog_at(&expr, var.size)))
+    });
+    decls.chain(assigns).collect()
+}
+
 // Creates localparams for states
 fn create_state_defs(case_count: usize, context: &Context) -> Vec<v::Stmt> {
     (0..case_count)
@@ -141,6 +157,7 @@ fn new_create_fsm(context: &Context, case: v::Case) -> v::SequentialIfElse {
 
 pub fn new_create_module(states: Vec<SingleStateLogic>, context: &Context) -> v::Module {
     let memories = create_reg_defs(context);
+    let wires = create_wire_defs(context);
     let mut case = v::Case::new(v::Expr::new_ref(context.states.variable.to_string()));
     let case_count = {
         let done = create_done_state(context);
@@ -164,6 +181,7 @@ pub fn new_create_module(states: Vec<SingleStateLogic>, context: &Context) -> v:
         .into_iter()
         .chain(state_defs)
         .chain(memories)
+        .chain(wires)
         .chain(std::iter::once(v::Stmt::from(fsm)));
 
     let mut module = v::Module::new(&context.name);
@@ -174,7 +192,10 @@ pub fn new_create_module(states: Vec<SingleStateLogic>, context: &Context) -> v:
         module.add_output_reg(&format!("{}", output), output.size as u64);
     }
     for i in 0..context.io.output_count {
-        module.add_output_reg(&format!("{}{}", context.io.output_prefix, i), 32);
+        module.add_output_reg(
+            &format!("{}{}", context.io.output_prefix, i),
+            context.io.output_type(i).size as u64,
+        );
     }
     for stmt in body {
         module.add_stmt(stmt);
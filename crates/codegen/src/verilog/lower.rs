@@ -0,0 +1,124 @@
+//! Lowers a graph to FSM states, each state being the nonblocking logic of one clock cycle
+
+use std::collections::BTreeMap;
+
+use tohdl_ir::graph::{Node, NodeIndex, ReturnNode, YieldNode, CFG};
+use tohdl_passes::{
+    manager::PassManager,
+    optimize::{
+        EliminateDeadCode, InferWidths, PropagateConstants, ReduceStrength, RemoveUnreadVars,
+    },
+    transform::{
+        BraunEtAl, ExplicitReturn, FixBranch, InsertCallNodes, InsertFuncNodes, IterativeDivision,
+        LowerToFsm, Nonblocking, PipelineLoops, Pipelining, Schedule, Timing,
+    },
+    BasicTransform, ContextfulTransfrom,
+};
+
+use super::{memory::RemoveLoadsEtc, share_subexprs, Context, Signals, UseMemory};
+
+/// States of a module, as emitted by [crate::verilog::graph_to_verilog]
+pub struct Lowered {
+    pub context: Context,
+    pub states: Vec<CFG>,
+    /// State that each [super::NextStateNode] of a state goes to
+    pub next_states: Vec<BTreeMap<NodeIndex, usize>>,
+}
+
+/// Optional lowerings, trading clock cycles for area
+#[derive(Debug, Default, Clone)]
+pub struct LowerOptions {
+    /// Divides by non-constants with a multi-cycle divider, one quotient bit per state
+    pub iterative_division: bool,
+    /// Breaks states whose combinational paths would be longer than the period
+    pub timing: Option<Timing>,
+    /// Overlaps the iterations of innermost loops, starting one every this many states
+    pub pipeline: Option<usize>,
+}
+
+pub fn lower(graph: CFG) -> Lowered {
+    lower_with(graph, &LowerOptions::default())
+}
+
+pub fn lower_with(mut graph: CFG, options: &LowerOptions) -> Lowered {
+    let mut manager = PassManager::log();
+    manager.add_pass(InsertFuncNodes::transform);
+    manager.add_pass(InsertCallNodes::transform);
+    manager.add_pass(BraunEtAl::transform);
+    manager.add_pass(PropagateConstants::transform);
+    manager.add_pass(EliminateDeadCode::transform);
+    manager.add_pass(InferWidths::transform);
+    manager.add_pass(ReduceStrength::transform);
+    if options.iterative_division {
+        manager.add_pass(IterativeDivision::transform);
+    }
+    manager.apply(&mut graph);
+
+    // graph.write_dot("mybug");
+    let mut lower = LowerToFsm::default();
+    let mut breaks = vec![];
+    if let Some(timing) = &options.timing {
+        let mut timing = timing.clone();
+        let result = Schedule::default().apply_timed_contextful(&mut graph, &mut timing);
+        println!("{result}");
+        breaks = timing.breaks;
+    }
+    if let Some(ii) = options.pipeline {
+        let mut pipelining = Pipelining::new(ii, breaks);
+        let result = PipelineLoops::default().apply_timed_contextful(&mut graph, &mut pipelining);
+        println!("{result}");
+        breaks = pipelining.breaks;
+    }
+    lower.recommended_breakpoints = breaks;
+    let result = lower.apply_timed(&mut graph);
+    println!("{result}");
+
+    let mut context = Context::new(
+        graph.name.as_str(),
+        graph.get_inputs().cloned().collect(),
+        Signals::new(),
+    );
+    context.io.output_types = graph.outputs.clone();
+
+    let mut states = vec![];
+    let mut next_states = vec![];
+    for (i, subgraph) in lower.get_subgraphs().iter().enumerate() {
+        let mut subgraph = subgraph.clone();
+        // subgraph.write_dot(format!("{}_{}.dot", std::stringify!(graph_to_verilog), i).as_str());
+        let max_memory = {
+            let mut pass = UseMemory::default();
+            let result = pass.apply_timed(&mut subgraph);
+            println!("{result}");
+            context.memories.use_sizes(pass.memory_sizes());
+            pass.max_memory()
+        };
+
+        let mut manager = PassManager::debug(format!("subgraph_{i}"));
+        manager.add_pass(Nonblocking::transform);
+        manager.add_pass(RemoveLoadsEtc::transform);
+        manager.add_pass(RemoveUnreadVars::transform);
+        manager.add_pass(FixBranch::transform);
+        manager.add_pass(ExplicitReturn::transform);
+        manager.apply(&mut subgraph);
+
+        context.memories.count = std::cmp::max(context.memories.count, max_memory);
+        for idx in subgraph.nodes() {
+            let node = subgraph.get_node(idx);
+            let count = match (YieldNode::concrete(node), ReturnNode::concrete(node)) {
+                (Some(YieldNode { values }), _) | (_, Some(ReturnNode { values })) => values.len(),
+                _ => 0,
+            };
+            context.io.output_count = std::cmp::max(context.io.output_count, count);
+        }
+
+        states.push(subgraph);
+        next_states.push(lower.get_external_funcs(i));
+    }
+    share_subexprs(&mut states, &mut context);
+
+    Lowered {
+        context,
+        states,
+        next_states,
+    }
+}
@@ -5,11 +5,12 @@
 use tohdl_ir::expr::*;
 
 use tohdl_ir::graph::*;
+use tohdl_ir::json::Registry;
 use tohdl_passes::BasicTransform;
 use tohdl_passes::TransformResultType;
 
 /// Special assignment that cannot be removed
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
 pub struct LoadNode {
     pub lvalue: VarExpr,
     pub rvalue: Expr,
@@ -46,7 +47,7 @@ impl DataFlow for LoadNode {
 }
 
 /// Special assignment that cannot be removed
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
 pub struct StoreNode {
     pub lvalue: VarExpr,
     pub rvalue: Expr,
@@ -83,7 +84,7 @@ impl DataFlow for StoreNode {
 }
 
 /// State transition node
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
 pub struct NextStateNode {}
 
 impl std::fmt::Display for NextStateNode {
@@ -94,12 +95,21 @@ impl std::fmt::Display for NextStateNode {
 
 impl DataFlow for NextStateNode {}
 
+/// Adds the nodes only used during code generation to a JSON registry
+pub fn register_nodes(registry: &mut Registry) {
+    registry.register_node::<LoadNode>("load");
+    registry.register_node::<StoreNode>("store");
+    registry.register_node::<NextStateNode>("next_state");
+}
+
 /// Root and leaf func and call nodes do not make sense in context of Verilog
 /// This pass replaces them with load and store nodes to registers
 #[derive(Default)]
 pub struct UseMemory {
     result: TransformResultType,
     max_memory: usize,
+    /// Widest variable stored in each memory
+    memory_sizes: Vec<usize>,
 }
 
 impl BasicTransform for UseMemory {
@@ -114,6 +124,17 @@ impl UseMemory {
         self.max_memory
     }
 
+    pub fn memory_sizes(&self) -> &[usize] {
+        &self.memory_sizes
+    }
+
+    fn use_memory_size(&mut self, i: usize, var: &VarExpr) {
+        if self.memory_sizes.len() <= i {
+            self.memory_sizes.resize(i + 1, 0);
+        }
+        self.memory_sizes[i] = std::cmp::max(self.memory_sizes[i], var.size);
+    }
+
     pub(crate) fn make_func_and_calls_use_mem(&mut self, graph: &mut CFG) {
         for idx in graph.nodes().collect::<Vec<_>>() {
             let preds = graph.preds(idx).collect::<Vec<_>>();
@@ -128,6 +149,7 @@ impl UseMemory {
                 self.max_memory = std::cmp::max(self.max_memory, params.len());
                 for (i, param) in params.iter().enumerate() {
                     if use_mem {
+                        self.use_memory_size(i, param);
                         graph.insert_node_after(
                             LoadNode {
                                 lvalue: param.clone(),
@@ -135,6 +157,8 @@ impl UseMemory {
                                     VarExpr::builder()
                                         .name(&format!("mem_{}", i))
                                         .type_(VarType::Pointer(Box::new(VarType::Int)))
+                                        .size(param.size)
+                                        .signed(param.signed)
                                         .build(),
                                 ),
                             },
@@ -161,11 +185,14 @@ impl UseMemory {
                 self.max_memory = std::cmp::max(self.max_memory, args.len());
                 for (i, arg) in args.iter().enumerate() {
                     if use_mem {
+                        self.use_memory_size(i, arg);
                         graph.insert_node_before(
                             StoreNode {
                                 lvalue: VarExpr::builder()
                                     .name(&format!("mem_{}", i))
                                     .type_(VarType::Pointer(Box::new(VarType::Int)))
+                                    .size(arg.size)
+                                    .signed(arg.signed)
                                     .build(),
                                 rvalue: Expr::Var(arg.clone()),
                             },
@@ -240,3 +267,33 @@ impl BasicTransform for RemoveLoadsEtc {
         &self.result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let mut graph = CFG::default();
+        let load = graph.add_node(LoadNode {
+            lvalue: VarExpr::new("a"),
+            rvalue: Expr::Var(VarExpr::new("mem_0")),
+        });
+        let store = graph.add_node(StoreNode {
+            lvalue: VarExpr::new("mem_0"),
+            rvalue: Expr::Var(VarExpr::new("a")),
+        });
+        let next = graph.add_node(NextStateNode {});
+        graph.add_edge(load, store, NoneEdge.into());
+        graph.add_edge(store, next, NoneEdge.into());
+
+        let mut registry = Registry::default();
+        assert!(graph.to_json(&registry).is_err());
+        register_nodes(&mut registry);
+        let json = graph.to_json(&registry).unwrap();
+        // println!("{}", json);
+        let graph = CFG::from_json(&json, &registry).unwrap();
+        assert!(LoadNode::downcastable(graph.get_node(load)));
+        assert!(NextStateNode::downcastable(graph.get_node(next)));
+    }
+}
@@ -1,4 +1,4 @@
-use tohdl_ir::expr::VarExpr;
+use tohdl_ir::expr::{Expr, IntType, VarExpr};
 use typed_builder::TypedBuilder;
 use vast::v17::ast::{self as v, Sequential};
 
@@ -50,6 +50,7 @@ pub struct Context {
     pub signals: Signals,
     pub states: States,
     pub memories: Memories,
+    pub wires: Wires,
 }
 
 impl Context {
@@ -60,6 +61,7 @@ impl Context {
             signals,
             states: States::default(),
             memories: Memories::default(),
+            wires: Wires::default(),
         }
     }
 }
@@ -72,6 +74,15 @@ pub struct InputOutput {
     pub output_count: usize,
     #[builder(default="__output_".into())]
     pub output_prefix: String,
+    /// Types of the output ports, see `CFG::outputs`
+    #[builder(default)]
+    pub output_types: Vec<IntType>,
+}
+
+impl InputOutput {
+    pub fn output_type(&self, position: usize) -> IntType {
+        self.output_types.get(position).copied().unwrap_or_default()
+    }
 }
 
 #[derive(Debug)]
@@ -103,6 +114,8 @@ impl Default for States {
 pub struct Memories {
     pub prefix: String,
     pub count: usize,
+    /// Width of each memory, memories past the end are 32 bits wide
+    pub sizes: Vec<usize>,
 }
 
 impl Default for Memories {
@@ -110,6 +123,43 @@ impl Default for Memories {
         Self {
             prefix: "mem_".into(),
             count: 0,
+            sizes: vec![],
+        }
+    }
+}
+
+impl Memories {
+    pub fn size(&self, i: usize) -> usize {
+        match self.sizes.get(i) {
+            Some(&size) if size > 0 => size,
+            _ => 32,
+        }
+    }
+
+    /// Widens each memory to fit `sizes`
+    pub fn use_sizes(&mut self, sizes: &[usize]) {
+        if self.sizes.len() < sizes.len() {
+            self.sizes.resize(sizes.len(), 0);
+        }
+        for (size, &other) in self.sizes.iter_mut().zip(sizes) {
+            *size = std::cmp::max(*size, other);
+        }
+    }
+}
+
+/// Combinational wires of subexpressions shared between states, see [super::share_subexprs]
+#[derive(Debug)]
+pub struct Wires {
+    pub prefix: String,
+    /// Wire and the expression assigned to it, in order of creation
+    pub values: Vec<(VarExpr, Expr)>,
+}
+
+impl Default for Wires {
+    fn default() -> Self {
+        Self {
+            prefix: "__shared_".into(),
+            values: vec![],
         }
     }
 }
@@ -167,7 +217,7 @@ def memory():
     yield c
     yield b
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
         let graph = visitor.get_graph();
         let res = graph_to_verilog(graph);
         // println!("{res}")
@@ -184,7 +234,7 @@ def p2vrange(start: int, stop: int, step: int) -> int:
         yield start
         start += step
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
         let graph = visitor.get_graph();
         let res = graph_to_verilog(graph);
         println!("{res}")
@@ -200,7 +250,7 @@ def multiplier(multiplicand: int, multiplier: int) -> int:
         multiplier -= 1
     yield product
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
         let graph = visitor.get_graph();
         let res = graph_to_verilog(graph);
         println!("{res}")
@@ -212,9 +262,23 @@ def multiplier(multiplicand: int, multiplier: int) -> int:
 def adder(a: int, b: int) -> int:
     yield a + b
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
         let graph = visitor.get_graph();
         let res = graph_to_verilog(graph);
         println!("{res}")
     }
+
+    #[test]
+    fn sized() {
+        let code = r#"
+def sized(a: u8, b: i4) -> u9:
+    yield a + b
+"#;
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
+        let graph = visitor.get_graph();
+        let res = graph_to_verilog(graph);
+        // println!("{res}");
+        assert!(res.contains("[7:0] a"));
+        assert!(res.contains("[8:0] __output_0"));
+    }
 }
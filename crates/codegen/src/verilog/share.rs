@@ -0,0 +1,198 @@
+//! Shares subexpressions repeated within and across states through combinational wires.
+//! After [tohdl_passes::transform::Nonblocking], the expressions of a state only read
+//! registers as they were at the start of the cycle, so a continuously assigned wire
+//! has the value the expression would have had in any state, and no state is added
+
+use std::collections::BTreeMap;
+
+use tohdl_ir::{expr::*, graph::*};
+
+use super::{
+    expr::{context_width, operands},
+    Context,
+};
+
+/// Number of operators in `expr`
+fn operators(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var(_) | Expr::Int(_) => 0,
+        Expr::BinOp(left, _, right) => 1 + operators(left) + operators(right),
+        Expr::UnaryOp(_, operand) => 1 + operators(operand),
+        Expr::Mux(cond, then, else_) => 1 + operators(cond) + operators(then) + operators(else_),
+    }
+}
+
+/// Expressions of the node at `idx`, each with the width of its destination, see [context_width]
+fn destinations<'a>(
+    state: &'a mut CFG,
+    idx: NodeIndex,
+    context: &Context,
+) -> Vec<(&'a mut Expr, usize)> {
+    let node = state.get_node(idx);
+    let lvalue = node.defined_vars().keys().next().map(|var| var.size);
+    let outputs = YieldNode::downcastable(node) || ReturnNode::downcastable(node);
+    let exprs = state.get_node_mut(idx).referenced_exprs_mut();
+    exprs
+        .into_iter()
+        .enumerate()
+        .map(|(i, expr)| match lvalue {
+            Some(size) => (expr, size),
+            None if outputs => (expr, context.io.output_type(i).size),
+            None => (expr, 0),
+        })
+        .collect()
+}
+
+/// Counts every operator subexpression of `expr` at the width it is evaluated at,
+/// constant ones are left to synthesis
+fn number(expr: &Expr, width: usize, table: &mut BTreeMap<(Expr, usize), usize>) {
+    if matches!(expr, Expr::Var(_) | Expr::Int(_)) {
+        return;
+    }
+    if expr.get_vars_iter().next().is_some() {
+        let key = (expr.clone(), context_width(expr, width));
+        *table.entry(key).or_default() += 1;
+    }
+    for (operand, width) in operands(expr, width) {
+        number(operand, width, table);
+    }
+}
+
+fn replace(expr: &mut Expr, width: usize, target: &(Expr, usize), var: &VarExpr) {
+    if *expr == target.0 && context_width(expr, width) == target.1 {
+        *expr = Expr::Var(var.clone());
+        return;
+    }
+    let widths = operands(expr, width)
+        .into_iter()
+        .map(|(_, width)| width)
+        .collect::<Vec<_>>();
+    let children = match expr {
+        Expr::Var(_) | Expr::Int(_) => vec![],
+        Expr::BinOp(left, _, right) => vec![left, right],
+        Expr::UnaryOp(_, operand) => vec![operand],
+        Expr::Mux(cond, then, else_) => vec![cond, then, else_],
+    };
+    for (child, width) in children.into_iter().zip(widths) {
+        replace(child, width, target, var);
+    }
+}
+
+/// Replaces each subexpression found more than once in `states` with a wire in `context`,
+/// largest first, so that the subexpressions of a shared one are only shared
+/// if they are also used elsewhere.
+/// A wire is as wide as Verilog evaluates its expression where it is read,
+/// only the same expression evaluated at the same width is shared,
+/// so reading it instead of the expression changes no operator's width
+pub fn share_subexprs(states: &mut [CFG], context: &mut Context) {
+    loop {
+        let mut table = BTreeMap::new();
+        for state in states.iter_mut() {
+            for idx in state.nodes().collect::<Vec<_>>() {
+                for (expr, width) in destinations(state, idx, context) {
+                    number(expr, width, &mut table);
+                }
+            }
+        }
+        for (var, expr) in &context.wires.values {
+            number(expr, var.size, &mut table);
+        }
+
+        let Some(target) = table
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(target, _)| target)
+            .max_by_key(|(expr, _)| operators(expr))
+        else {
+            return;
+        };
+        let size = target.1;
+        let var = VarExpr::builder()
+            .name(format!(
+                "{}{}",
+                context.wires.prefix,
+                context.wires.values.len()
+            ))
+            .size(size)
+            .signed(true)
+            .build();
+        for state in states.iter_mut() {
+            for idx in state.nodes().collect::<Vec<_>>() {
+                for (value, width) in destinations(state, idx, context) {
+                    replace(value, width, &target, &var);
+                }
+            }
+        }
+        for (wire, value) in &mut context.wires.values {
+            replace(value, wire.size, &target, &var);
+        }
+        context.wires.values.push((var, target.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verilog::{expr::verilog_width, lower, Simulator};
+    use tohdl_ir::interpret::interpret;
+
+    #[test]
+    fn across_states() {
+        let text = "cfg shared
+entry %0
+
+%0: func(a, b)
+%1: yield ((a + b), ((a + b) * (a + b)))
+%2: yield (((a + b) * (a + b)))
+%3: return ()
+";
+        let lowered = lower(CFG::from_text(text).unwrap());
+        let wires = &lowered.context.wires.values;
+        // println!("{:?}", wires);
+        assert!(!wires.is_empty());
+        for (var, expr) in wires {
+            assert_eq!(var.size, verilog_width(expr));
+            assert!(operators(expr) > 0);
+        }
+
+        let mut sim = Simulator::from_lowered(lowered);
+        let trace = sim.run(&[BigInt::from(2), BigInt::from(3)], 100).unwrap();
+        assert_eq!(trace.yields[0], vec![BigInt::from(5), BigInt::from(25)]);
+        assert_eq!(trace.yields[1][0], BigInt::from(25));
+    }
+
+    #[test]
+    fn wide_destination() {
+        // The product is evaluated at 64 bits for `x` and `y`, not shared with the compared one
+        let text = "cfg shared
+entry %0
+outputs i64, i64
+
+%0: func(a, b)
+%1: x:i64 = (a * b)
+%2: y:i64 = ((a * b) + 1)
+%3: yield (((a * b) < 0))
+%4: yield (x:i64, y:i64)
+%5: return ()
+";
+        let graph = CFG::from_text(text).unwrap();
+        let lowered = lower(graph.clone());
+        let wires = &lowered.context.wires.values;
+        // println!("{:?}", wires);
+        assert_eq!(wires.len(), 1);
+        assert_eq!(wires[0].0.size, 64);
+
+        let inputs = [BigInt::from(46341), BigInt::from(46341)];
+        let mut sim = Simulator::from_lowered(lowered);
+        let trace = sim.run(&inputs, 100).unwrap();
+        assert_eq!(trace.yields[0][0], BigInt::from(1));
+        assert_eq!(
+            trace.yields[1],
+            vec![BigInt::from(2147488281i64), BigInt::from(2147488282i64)]
+        );
+        assert_eq!(
+            trace.yields[1],
+            interpret(&graph, &inputs).unwrap().yields[1]
+        );
+    }
+}
@@ -0,0 +1,860 @@
+//! Cycle-accurate simulation of the module made by [super::graph_to_verilog].
+//!
+//! Steps the lowered states with the same start/reset/ready/valid logic as [super::new_create_module],
+//! all assignments within a cycle being nonblocking.
+//! Registers hold raw bits, and each read extends them to the type of the variable read,
+//! as the generated Verilog does.
+
+use std::collections::BTreeMap;
+
+use tohdl_ir::{expr::*, graph::*};
+
+use super::{
+    expr::{context_width, operands, verilog_width},
+    lower, Lowered, NextStateNode, StoreNode, Vcd,
+};
+
+/// Pattern driven on `__ready`, back-pressure from the consumer
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum Ready {
+    #[default]
+    Always,
+    /// Repeats the pattern, one entry per cycle
+    Repeat(Vec<bool>),
+    /// High with the given percent chance each cycle, from a seeded xorshift generator
+    Random { seed: u64, percent: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimError {
+    pub cycle: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cycle {}: {}", self.cycle, self.message)
+    }
+}
+
+impl std::error::Error for SimError {}
+
+/// Values handed to the consumer, with the cycle of the clock edge they were taken at
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SimTrace {
+    pub yields: Vec<Vec<BigInt>>,
+    pub cycles: Vec<usize>,
+    pub returned: Vec<BigInt>,
+    pub done_cycle: usize,
+}
+
+struct Signal {
+    size: usize,
+    /// Raw bits, `None` until first assigned
+    value: Option<BigInt>,
+    vcd: usize,
+}
+
+pub struct Simulator {
+    lowered: Lowered,
+    signals: BTreeMap<String, Signal>,
+    ready: Ready,
+    rng: u64,
+    cycle: usize,
+    vcd: Vcd,
+}
+
+impl Simulator {
+    pub fn new(graph: CFG) -> Self {
+        Self::from_lowered(lower(graph))
+    }
+
+    pub fn from_lowered(lowered: Lowered) -> Self {
+        let context = &lowered.context;
+        let mut sizes = vec![("__clock".to_string(), 1)];
+        for var in context.io.inputs.iter().chain(context.signals.values()) {
+            sizes.push((var.name.clone(), var.size));
+        }
+        for i in 0..context.io.output_count {
+            sizes.push((
+                format!("{}{}", context.io.output_prefix, i),
+                context.io.output_type(i).size,
+            ));
+        }
+        sizes.push((context.states.variable.clone(), 32));
+        for i in 0..context.memories.count {
+            sizes.push((
+                format!("{}{}", context.memories.prefix, i),
+                context.memories.size(i),
+            ));
+        }
+        // Variables local to a state
+        for state in &lowered.states {
+            for idx in state.nodes() {
+                for var in state.get_node(idx).declared_vars() {
+                    sizes.push((name(var), var.size));
+                }
+            }
+        }
+
+        let mut vcd = Vcd::new(context.name.clone());
+        let mut signals = BTreeMap::new();
+        for (name, size) in sizes {
+            if let std::collections::btree_map::Entry::Vacant(entry) = signals.entry(name) {
+                let vcd = vcd.add_var(entry.key(), size);
+                entry.insert(Signal {
+                    size,
+                    value: None,
+                    vcd,
+                });
+            }
+        }
+        let mut ret = Self {
+            lowered,
+            signals,
+            ready: Ready::default(),
+            rng: 0,
+            cycle: 0,
+            vcd,
+        };
+        ret.set("__clock", 0);
+        ret.set(&ret.lowered.context.signals.reset.name.clone(), 0);
+        ret.set(&ret.lowered.context.signals.start.name.clone(), 0);
+        ret.dump(0);
+        ret
+    }
+
+    pub fn with_ready(mut self, ready: Ready) -> Self {
+        if let Ready::Random { seed, .. } = ready {
+            // Xorshift gets stuck at zero
+            self.rng = std::cmp::max(seed, 1);
+        }
+        self.ready = ready;
+        self
+    }
+
+    /// Clock edges so far
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    /// Raw bits of a port or register, `None` if unknown
+    pub fn get(&self, name: &str) -> Option<&BigInt> {
+        self.signals.get(name)?.value.as_ref()
+    }
+
+    /// Drives an input port, taking effect at the next clock edge
+    pub fn set(&mut self, name: &str, value: impl Into<BigInt>) {
+        let signal = self
+            .signals
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("no signal {}", name));
+        signal.value = Some(IntExpr::sized(value, signal.size, false).value);
+    }
+
+    pub fn vcd(&self) -> &Vcd {
+        &self.vcd
+    }
+
+    pub fn write_vcd(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.vcd.to_string())
+    }
+
+    /// Starts the module with `inputs` and steps until it is done,
+    /// with the consumer taking each yield when `__ready` is high
+    pub fn run(&mut self, inputs: &[BigInt], max_cycles: usize) -> Result<SimTrace, SimError> {
+        let context = &self.lowered.context;
+        if inputs.len() != context.io.inputs.len() {
+            return Err(self.error(format!(
+                "{} inputs given to {} params",
+                inputs.len(),
+                context.io.inputs.len()
+            )));
+        }
+        for (input, value) in context.io.inputs.clone().iter().zip(inputs) {
+            self.set(&input.name, value.clone());
+        }
+        let signals = &self.lowered.context.signals;
+        let (start, ready, valid, done) = (
+            signals.start.name.clone(),
+            signals.ready.name.clone(),
+            signals.valid.name.clone(),
+            signals.done.name.clone(),
+        );
+
+        let mut trace = SimTrace::default();
+        self.set(&start, 1);
+        self.step()?;
+        self.set(&start, 0);
+        for _ in 0..max_cycles {
+            if self.high(&done) {
+                trace.returned = self.outputs();
+                trace.done_cycle = self.cycle;
+                return Ok(trace);
+            }
+            let taken = self.high(&valid);
+            self.drive_ready(&ready);
+            if taken && self.high(&ready) {
+                trace.yields.push(self.outputs());
+                trace.cycles.push(self.cycle);
+            }
+            self.step()?;
+        }
+        Err(self.error(format!("not done within {} cycles", max_cycles)))
+    }
+
+    /// One rising clock edge, with inputs as last set
+    pub fn step(&mut self) -> Result<(), SimError> {
+        let context = &self.lowered.context;
+        let signals = &context.signals;
+        let mut writes = vec![];
+        let write = |writes: &mut Vec<(String, Option<BigInt>)>, name: &str, value: usize| {
+            writes.push((name.to_string(), Some(BigInt::from(value))))
+        };
+        let state_count = self.lowered.states.len();
+        if self.high(&signals.start.name) {
+            write(&mut writes, &signals.valid.name, 0);
+            write(&mut writes, &signals.done.name, 0);
+            for (i, input) in context.io.inputs.iter().enumerate() {
+                writes.push((
+                    format!("{}{}", context.memories.prefix, i),
+                    self.get(&input.name).cloned(),
+                ));
+            }
+            write(&mut writes, &context.states.variable, 0);
+        } else if self.high(&signals.reset.name) {
+            write(&mut writes, &context.states.variable, state_count);
+            write(&mut writes, &signals.valid.name, 0);
+            write(&mut writes, &signals.done.name, 0);
+        } else if self.high(&signals.ready.name) || self.low(&signals.valid.name) {
+            write(&mut writes, &signals.valid.name, 0);
+            let state = self
+                .get(&context.states.variable)
+                .and_then(|state| usize::try_from(state).ok());
+            match state {
+                Some(state) if state == state_count + 1 => {
+                    write(&mut writes, &signals.done.name, 1);
+                    write(&mut writes, &signals.valid.name, 1);
+                    write(&mut writes, &context.states.variable, state_count);
+                }
+                Some(state) if state < state_count => {
+                    let graph = &self.lowered.states[state];
+                    self.exec(state, graph.get_entry(), &mut writes)
+                        .map_err(|message| self.error(format!("state {}: {}", state, message)))?;
+                }
+                // Start state or unknown, no case matches
+                _ => {}
+            }
+        }
+
+        // Nonblocking, the last write to a signal wins
+        for (name, value) in writes {
+            let signal = self.signals.get_mut(&name).unwrap();
+            let size = signal.size;
+            signal.value = value.map(|value| IntExpr::sized(value, size, false).value);
+        }
+        self.cycle += 1;
+        self.set("__clock", 1);
+        self.dump(self.cycle as u64 * 10 - 5);
+        self.set("__clock", 0);
+        self.dump(self.cycle as u64 * 10);
+        Ok(())
+    }
+
+    fn error(&self, message: String) -> SimError {
+        SimError {
+            cycle: self.cycle,
+            message,
+        }
+    }
+
+    fn high(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|value| value != &BigInt::default())
+    }
+
+    fn low(&self, name: &str) -> bool {
+        self.get(name) == Some(&BigInt::default())
+    }
+
+    fn drive_ready(&mut self, name: &str) {
+        let value = match &self.ready {
+            Ready::Always => true,
+            Ready::Repeat(pattern) if pattern.is_empty() => true,
+            Ready::Repeat(pattern) => pattern[self.cycle % pattern.len()],
+            Ready::Random { percent, .. } => {
+                let percent = *percent as u64;
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 7;
+                self.rng ^= self.rng << 17;
+                self.rng % 100 < percent
+            }
+        };
+        self.set(name, value as u8);
+        self.dump(self.cycle as u64 * 10);
+    }
+
+    /// Output ports with the output types
+    fn outputs(&self) -> Vec<BigInt> {
+        let io = &self.lowered.context.io;
+        (0..io.output_count)
+            .map(|i| {
+                let int_type = io.output_type(i);
+                let value = self
+                    .get(&format!("{}{}", io.output_prefix, i))
+                    .cloned()
+                    .unwrap_or_default();
+                IntExpr::sized(value, int_type.size, int_type.signed).value
+            })
+            .collect()
+    }
+
+    fn dump(&mut self, time: u64) {
+        for signal in self.signals.values() {
+            self.vcd.change(time, signal.vcd, signal.value.as_ref());
+        }
+    }
+
+    /// Value of `var` read as its own type
+    fn read(&self, var: &VarExpr) -> Result<BigInt, String> {
+        let wires = &self.lowered.context.wires.values;
+        if let Some((wire, expr)) = wires.iter().find(|(wire, _)| wire.name == var.name) {
+            let value = IntExpr::sized(self.eval(expr, wire.size)?, wire.size, wire.signed).value;
+            return Ok(IntExpr::sized(value, var.size, var.signed).value);
+        }
+        let value = self
+            .get(&name(var))
+            .ok_or_else(|| format!("read of unknown {}", name(var)))?;
+        Ok(IntExpr::sized(value.clone(), var.size, var.signed).value)
+    }
+
+    /// Value of `expr` as the generated Verilog computes it for a destination of `width` bits,
+    /// each operator wrapping around its [context_width]
+    fn eval(&self, expr: &Expr, width: usize) -> Result<BigInt, String> {
+        let zero = BigInt::default();
+        let truth = |value: bool| BigInt::from(value as u8);
+        let width = context_width(expr, width);
+        let widths = operands(expr, width)
+            .into_iter()
+            .map(|(_, width)| width)
+            .collect::<Vec<_>>();
+        let value = match expr {
+            Expr::Var(var) => self.read(var)?,
+            Expr::Int(int) => int.value.clone(),
+            Expr::BinOp(left, op, right) => {
+                let right_width = verilog_width(right);
+                let (left, right) = (self.eval(left, widths[0])?, self.eval(right, widths[1])?);
+                match op {
+                    Operator::Add => left + right,
+                    Operator::Sub => left - right,
+                    Operator::Mul => left * right,
+                    Operator::Div | Operator::Mod if right == zero => {
+                        return Err("division by zero".to_string())
+                    }
+                    Operator::Div => left / right,
+                    Operator::Mod => left % right,
+                    Operator::Lt => truth(left < right),
+                    Operator::Gt => truth(left > right),
+                    Operator::LtE => truth(left <= right),
+                    Operator::GtE => truth(left >= right),
+                    Operator::Eq => truth(left == right),
+                    Operator::NotEq => truth(left != right),
+                    // Shift amounts are unsigned, shifting out every bit past the width
+                    Operator::LShift | Operator::RShift => {
+                        let amount = IntExpr::sized(right, right_width, false).value;
+                        let amount = usize::try_from(amount).unwrap_or(usize::MAX).min(width);
+                        if *op == Operator::LShift {
+                            left << amount
+                        } else {
+                            left >> amount
+                        }
+                    }
+                    Operator::BitAnd => left & right,
+                    Operator::BitOr => left | right,
+                    Operator::BitXor => left ^ right,
+                    Operator::And => truth(left != zero && right != zero),
+                    Operator::Or => truth(left != zero || right != zero),
+                }
+            }
+            Expr::UnaryOp(op, operand) => {
+                let value = self.eval(operand, widths[0])?;
+                match op {
+                    UnaryOperator::Not => truth(value == zero),
+                    UnaryOperator::Neg => -value,
+                    UnaryOperator::Invert => !value,
+                }
+            }
+            Expr::Mux(cond, then, else_) => {
+                if self.eval(cond, widths[0])? != zero {
+                    self.eval(then, widths[1])?
+                } else {
+                    self.eval(else_, widths[2])?
+                }
+            }
+        };
+        Ok(IntExpr::sized(value, width, true).value)
+    }
+
+    /// Collects the writes of a state from `idx` on, as [super::SingleStateLogic] emits them
+    fn exec(
+        &self,
+        state: usize,
+        idx: NodeIndex,
+        writes: &mut Vec<(String, Option<BigInt>)>,
+    ) -> Result<(), String> {
+        let context = &self.lowered.context;
+        let graph = &self.lowered.states[state];
+        let node = graph.get_node(idx);
+        let outputs = |writes: &mut Vec<(String, Option<BigInt>)>, values: &[Expr]| {
+            for (i, value) in values.iter().enumerate() {
+                let width = context.io.output_type(i).size;
+                writes.push((
+                    format!("{}{}", context.io.output_prefix, i),
+                    Some(self.eval(value, width)?),
+                ));
+            }
+            Ok::<(), String>(())
+        };
+        if let Some(FuncNode { params }) = FuncNode::concrete(node) {
+            for (i, param) in params.iter().enumerate() {
+                let memory = format!("{}{}", context.memories.prefix, i);
+                writes.push((name(param), self.get(&memory).cloned()));
+            }
+        } else if let Some(AssignNode { lvalue, rvalue }) = AssignNode::concrete(node) {
+            writes.push((name(lvalue), Some(self.eval(rvalue, lvalue.size)?)));
+        } else if let Some(StoreNode { lvalue, rvalue }) = StoreNode::concrete(node) {
+            writes.push((name(lvalue), Some(self.eval(rvalue, lvalue.size)?)));
+        } else if let Some(BranchNode { cond }) = BranchNode::concrete(node) {
+            let taken = self.eval(cond, 0)? != BigInt::default();
+            let succ = graph
+                .succs(idx)
+                .find(|succ| {
+                    graph
+                        .get_edge(idx, *succ)
+                        .and_then(|edge| edge.downcast_ref::<BranchEdge>())
+                        .is_some_and(|edge| edge.condition == taken)
+                })
+                .ok_or_else(|| format!("branch {} has no {} successor", idx, taken))?;
+            return self.exec(state, succ, writes);
+        } else if let Some(YieldNode { values }) = YieldNode::concrete(node) {
+            writes.push((context.signals.valid.name.clone(), Some(BigInt::from(1))));
+            outputs(writes, values)?;
+        } else if let Some(ReturnNode { values }) = ReturnNode::concrete(node) {
+            outputs(writes, values)?;
+            let done = self.lowered.states.len() + 1;
+            writes.push((context.states.variable.clone(), Some(BigInt::from(done))));
+            return Ok(());
+        } else if NextStateNode::downcastable(node) {
+            let next = self.lowered.next_states[state]
+                .get(&idx)
+                .ok_or_else(|| format!("no next state for {}", idx))?;
+            writes.push((context.states.variable.clone(), Some(BigInt::from(*next))));
+            return Ok(());
+        } else if !CallNode::downcastable(node) {
+            return Err(format!("unexpected {}", node));
+        }
+        for succ in graph.succs(idx) {
+            self.exec(state, succ, writes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Register name of a variable, without the SSA separator
+fn name(var: &VarExpr) -> String {
+    var.name.split('.').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verilog::{lower_with, LowerOptions};
+    use tohdl_ir::interpret::interpret;
+    use tohdl_passes::{algorithms::inline_extern_func, transform::Timing};
+
+    const RANGE: &str = "cfg range
+entry %0
+
+%0: func(n)
+%1: i = 0
+    -> %2
+
+%2: if (i < n)
+    true -> %3
+    false -> %4
+
+%3: yield (i)
+%5: i = (i + 1)
+    -> %2
+
+%4: return ()
+";
+
+    fn ints(values: &[i64]) -> Vec<BigInt> {
+        values.iter().map(|v| BigInt::from(*v)).collect()
+    }
+
+    #[test]
+    fn range() {
+        let graph = CFG::from_text(RANGE).unwrap();
+        let expected = interpret(&graph, &ints(&[4])).unwrap();
+        let mut sim = Simulator::new(graph);
+        let trace = sim.run(&ints(&[4]), 100).unwrap();
+        assert_eq!(trace.yields, expected.yields);
+        // println!("{:?}", trace.cycles);
+        assert!(trace.cycles.windows(2).all(|w| w[0] < w[1]));
+        let vcd = sim.vcd().to_string();
+        // println!("{}", vcd);
+        assert!(vcd.contains(" __state $end"));
+        assert!(vcd.contains(" mem_0 $end"));
+    }
+
+    #[test]
+    fn back_pressure() {
+        let graph = CFG::from_text(RANGE).unwrap();
+        let fast = Simulator::new(graph.clone()).run(&ints(&[5]), 100).unwrap();
+        for ready in [
+            Ready::Repeat(vec![false, true, false]),
+            Ready::Random {
+                seed: 7,
+                percent: 30,
+            },
+        ] {
+            let slow = Simulator::new(graph.clone())
+                .with_ready(ready)
+                .run(&ints(&[5]), 1000)
+                .unwrap();
+            assert_eq!(slow.yields, fast.yields);
+            assert!(slow.done_cycle > fast.done_cycle);
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let graph = CFG::from_text(RANGE).unwrap();
+        let mut sim = Simulator::new(graph);
+        sim.set("n", 3);
+        sim.set("__start", 1);
+        sim.step().unwrap();
+        sim.set("__start", 0);
+        sim.set("__ready", 1);
+        sim.step().unwrap();
+        sim.set("__reset", 1);
+        sim.step().unwrap();
+        sim.set("__reset", 0);
+        for _ in 0..10 {
+            sim.step().unwrap();
+        }
+        assert_eq!(sim.get("__done"), Some(&BigInt::from(0)));
+        assert_eq!(sim.get("__valid"), Some(&BigInt::from(0)));
+    }
+
+    #[test]
+    fn verilog_widths() {
+        // Products wrap around 32 bits before they are compared, unlike in the interpreter
+        let graph = CFG::from_text(
+            "cfg widths
+entry %0
+
+%0: func(a, b)
+%1: yield (((a * b) < 0), (a >> 40), (-a), (a < b))
+%2: return ()
+",
+        )
+        .unwrap();
+        let trace = Simulator::new(graph.clone())
+            .run(&ints(&[46341, 46341]), 100)
+            .unwrap();
+        assert_eq!(trace.yields, vec![ints(&[1, 0, -46341, 0])]);
+        let trace = Simulator::new(graph)
+            .run(&ints(&[-2147483648, 3]), 100)
+            .unwrap();
+        assert_eq!(trace.yields, vec![ints(&[1, -1, -2147483648, 1])]);
+    }
+
+    #[test]
+    fn narrow_constants() {
+        // Extended to 32 bits like a variable of the same type, so the shift does not wrap at 8
+        let graph = CFG::from_text(
+            "cfg narrow
+entry %0
+
+%0: func(n)
+%1: yield ((100:i8 << n), ((-100:i8) << n), (100:i8 + 100:i8))
+%2: return ()
+",
+        )
+        .unwrap();
+        let inputs = ints(&[1]);
+        let trace = Simulator::new(graph.clone()).run(&inputs, 100).unwrap();
+        assert_eq!(trace.yields, vec![ints(&[200, -200, 200])]);
+        assert_eq!(trace.yields, interpret(&graph, &inputs).unwrap().yields);
+    }
+
+    #[test]
+    fn narrow_constant_vars() {
+        // Propagated as constants of the same type, which must not narrow the shifts
+        let graph = CFG::from_text(
+            "cfg narrow
+entry %0
+
+%0: func(n)
+%1: x:i8 = 100
+%2: y:i8 = (-100)
+%3: z:u8 = 200
+%4: yield ((x:i8 << n), (y:i8 << n), (z:u8 << n))
+%5: return ()
+",
+        )
+        .unwrap();
+        let lowered = lower(graph.clone());
+        for state in &lowered.states {
+            for idx in state.nodes() {
+                assert!(AssignNode::concrete(state.get_node(idx)).is_none());
+            }
+        }
+        let inputs = ints(&[1]);
+        let trace = Simulator::from_lowered(lowered).run(&inputs, 100).unwrap();
+        assert_eq!(trace.yields, vec![ints(&[200, -200, 400])]);
+        assert_eq!(trace.yields, interpret(&graph, &inputs).unwrap().yields);
+    }
+
+    #[test]
+    fn constant_division() {
+        // Reduced to shifts and multiplies, which must not wrap at Verilog widths
+        let graph = CFG::from_text(
+            "cfg divide
+entry %0
+
+%0: func(a)
+%1: yield ((a / 10), (a % 10), (a / (-3)), (a % (-3)), (a / 8), (a % (-8)))
+%2: return ()
+",
+        )
+        .unwrap();
+        let mut lowered = lower(graph.clone());
+        for state in &mut lowered.states {
+            for idx in state.nodes().collect::<Vec<_>>() {
+                for expr in state.get_node_mut(idx).referenced_exprs_mut() {
+                    assert!(!expr.to_string().contains('/'));
+                }
+            }
+        }
+        for a in [-2147483648, -2147483647, -17, -1, 0, 9, 2147483647] {
+            let inputs = ints(&[a]);
+            let expected = interpret(&graph, &inputs).unwrap();
+            let trace = Simulator::new(graph.clone()).run(&inputs, 100).unwrap();
+            assert_eq!(trace.yields, expected.yields);
+        }
+    }
+
+    /// Inlines the external node of `caller` with `callee`
+    fn inline(caller: &str, callee: &str) -> CFG {
+        let mut graph = CFG::from_text(caller).unwrap();
+        let idx = graph
+            .nodes()
+            .find(|idx| ExternalNode::downcastable(graph.get_node(*idx)))
+            .unwrap();
+        inline_extern_func(idx, &mut graph, &CFG::from_text(callee).unwrap());
+        graph
+    }
+
+    #[test]
+    fn generators() {
+        // Two yields, so the end of a loop over it resumes through the `__resume` dispatch
+        let callee = "cfg gen
+entry %0
+
+%0: func(m)
+%1: j = 0
+    -> %2
+
+%2: if (j < m)
+    true -> %3
+    false -> %6
+
+%3: yield (j)
+%4: yield ((j * 10))
+%5: j = (j + 1)
+    -> %2
+
+%6: return ()
+";
+        let for_in = "cfg for_in
+entry %0
+
+%0: func(n)
+%1: s = 0
+%2: call(n)
+%3: iterate(gen)
+    true -> %4
+    false -> %7
+
+%4: func(x)
+%5: s = (s + x)
+%6: yield (s)
+    -> %3
+
+%7: return (s)
+";
+        let graph = inline(for_in, callee);
+        for n in [0, 1, 3] {
+            let inputs = ints(&[n]);
+            let trace = Simulator::new(graph.clone()).run(&inputs, 1000).unwrap();
+            let expected = interpret(&graph, &inputs).unwrap();
+            assert_eq!(trace.yields, expected.yields);
+            assert_eq!(trace.returned, expected.returned);
+        }
+        let trace = Simulator::new(graph).run(&ints(&[2]), 1000).unwrap();
+        assert_eq!(
+            trace.yields,
+            vec![ints(&[0]), ints(&[0]), ints(&[1]), ints(&[11])]
+        );
+        assert_eq!(trace.returned, ints(&[11]));
+
+        let yield_from = "cfg yield_from
+entry %0
+
+%0: func(n)
+%1: call(n)
+%2: external(gen)
+%3: func()
+%4: return ()
+";
+        let graph = inline(yield_from, callee);
+        let trace = Simulator::new(graph).run(&ints(&[2]), 1000).unwrap();
+        assert_eq!(
+            trace.yields,
+            vec![ints(&[0]), ints(&[0]), ints(&[1]), ints(&[10])]
+        );
+    }
+
+    #[test]
+    fn iterative_division() {
+        let graph = CFG::from_text(
+            "cfg divide
+entry %0
+
+%0: func(a, b)
+%1: yield ((a / b), (a % b))
+%2: return ()
+",
+        )
+        .unwrap();
+        let options = LowerOptions {
+            iterative_division: true,
+            ..Default::default()
+        };
+        for inputs in [
+            [100, 7],
+            [-100, 7],
+            [7, -100],
+            [-2147483648, -3],
+            [100, -2147483648],
+            [-2147483648, -2147483648],
+        ] {
+            let inputs = ints(&inputs);
+            let expected = interpret(&graph, &inputs).unwrap();
+            let fast = Simulator::new(graph.clone()).run(&inputs, 100).unwrap();
+            let slow = Simulator::from_lowered(lower_with(graph.clone(), &options))
+                .run(&inputs, 1000)
+                .unwrap();
+            assert_eq!(slow.yields, expected.yields);
+            assert!(slow.done_cycle > fast.done_cycle + 32);
+        }
+    }
+
+    #[test]
+    fn timing() {
+        let graph = CFG::from_text(
+            "cfg chain
+entry %0
+
+%0: func(a, b)
+%1: x = (a * b)
+%2: y = ((x * a) + 1)
+%3: z = (y * b)
+%4: yield ((z + x))
+%5: return ()
+",
+        )
+        .unwrap();
+        let options = LowerOptions {
+            timing: Some(Timing::new(40)),
+            ..Default::default()
+        };
+        let inputs = ints(&[3, -5]);
+        let expected = interpret(&graph, &inputs).unwrap();
+        let fast = Simulator::new(graph.clone()).run(&inputs, 100).unwrap();
+        let slow = Simulator::from_lowered(lower_with(graph, &options))
+            .run(&inputs, 100)
+            .unwrap();
+        assert_eq!(slow.yields, expected.yields);
+        // A state for each product
+        assert_eq!(slow.done_cycle, fast.done_cycle + 2);
+    }
+
+    #[test]
+    fn pipeline() {
+        // Two products in a row only fit a period in two states
+        let graph = CFG::from_text(
+            "cfg cubes
+entry %0
+
+%0: func(n)
+%1: i = 0
+    -> %2
+
+%2: if (i < n)
+    true -> %3
+    false -> %7
+
+%3: x = ((i * i) + 1)
+%4: i = (i + 1)
+%5: y = (x * x)
+%6: yield (y)
+    -> %2
+
+%7: return (i)
+",
+        )
+        .unwrap();
+        let timing = LowerOptions {
+            timing: Some(Timing::new(36)),
+            ..Default::default()
+        };
+        let pipelined = LowerOptions {
+            pipeline: Some(1),
+            ..timing.clone()
+        };
+        let inputs = ints(&[6]);
+        let expected = interpret(&graph, &inputs).unwrap();
+        let slow = Simulator::from_lowered(lower_with(graph.clone(), &timing))
+            .run(&inputs, 1000)
+            .unwrap();
+        let fast = Simulator::from_lowered(lower_with(graph.clone(), &pipelined))
+            .run(&inputs, 1000)
+            .unwrap();
+        assert_eq!(slow.yields, expected.yields);
+        assert_eq!(fast.yields, expected.yields);
+        assert_eq!(fast.returned, expected.returned);
+        // A yield every cycle instead of every other
+        assert!(fast.cycles.windows(2).all(|w| w[1] == w[0] + 1));
+        assert!(fast.done_cycle + 5 <= slow.done_cycle);
+
+        for ready in [
+            Ready::Repeat(vec![false, true, false]),
+            Ready::Random {
+                seed: 7,
+                percent: 30,
+            },
+        ] {
+            let stalled = Simulator::from_lowered(lower_with(graph.clone(), &pipelined))
+                .with_ready(ready)
+                .run(&inputs, 1000)
+                .unwrap();
+            assert_eq!(stalled.yields, expected.yields);
+            assert!(stalled.done_cycle > fast.done_cycle);
+        }
+    }
+}
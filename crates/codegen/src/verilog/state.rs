@@ -12,7 +12,7 @@ use vast::v05::ast::{self as v, Sequential};
 
 use super::{
     memory::{LoadNode, NextStateNode, StoreNode},
-    module::Context, expr::ToVerilog,
+    module::Context, expr::{to_verilog_at, ToVerilog},
 };
 
 #[derive(Default)]
@@ -49,7 +49,7 @@ impl SingleStateLogic {
             .split(self.ssa_separator)
             .collect::<Vec<&str>>()
             .join("");
-        VarExpr::new(&processed)
+        var.with_name(&processed)
     }
     fn do_state(&mut self, graph: &mut CFG, context: &mut Context, body: &mut Vec<Sequential>, idx: NodeIndex) {
         let node = &mut graph.get_node_mut(idx).clone();
@@ -60,7 +60,7 @@ impl SingleStateLogic {
             }
             body.push(v::Sequential::new_nonblk_assign(
                 v::Expr::new_ref(lvalue.to_string()),
-                v::Expr::new_ref(node.rvalue.to_verilog()),
+                v::Expr::new_ref(to_verilog_at(&node.rvalue, lvalue.size)),
             ));
             for succ in graph.succs(idx).collect::<Vec<_>>() {
                 self.do_state(graph, context, body, succ);
@@ -72,7 +72,7 @@ impl SingleStateLogic {
             }
             body.push(v::Sequential::new_nonblk_assign(
                 v::Expr::new_ref(lvalue.to_string()),
-                v::Expr::new_ref(node.rvalue.to_verilog()),
+                v::Expr::new_ref(to_verilog_at(&node.rvalue, lvalue.size)),
             ));
             for succ in graph.succs(idx).collect::<Vec<_>>() {
                 self.do_state(graph, context, body, succ);
@@ -84,7 +84,7 @@ impl SingleStateLogic {
             }
             body.push(v::Sequential::new_nonblk_assign(
                 v::Expr::new_ref(lvalue.to_string()),
-                v::Expr::new_ref(node.rvalue.to_verilog()),
+                v::Expr::new_ref(to_verilog_at(&node.rvalue, lvalue.size)),
             ));
             for succ in graph.succs(idx).collect::<Vec<_>>() {
                 self.do_state(graph, context, body, succ);
@@ -161,7 +161,7 @@ impl SingleStateLogic {
             for (i, value) in node.values.iter().enumerate() {
                 body.push(v::Sequential::new_nonblk_assign(
                     v::Expr::new_ref(&format!("{}{}", context.io.output_prefix, i)),
-                    v::Expr::new_ref(value.to_verilog()),
+                    v::Expr::new_ref(to_verilog_at(value, context.io.output_type(i).size)),
                 ));
             }
             for succ in graph.succs(idx).collect::<Vec<_>>() {
@@ -177,7 +177,7 @@ impl SingleStateLogic {
             for (i, value) in node.values.iter().enumerate() {
                 body.push(v::Sequential::new_nonblk_assign(
                     v::Expr::new_ref(&format!("{}{}", context.io.output_prefix, i)),
-                    v::Expr::new_ref(value.to_verilog()),
+                    v::Expr::new_ref(to_verilog_at(value, context.io.output_type(i).size)),
                 ));
             }
             body.push(v::Sequential::new_nonblk_assign(
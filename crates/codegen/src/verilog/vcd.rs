@@ -0,0 +1,116 @@
+//! Value change dump of a simulation, readable by waveform viewers such as GTKWave
+
+use tohdl_ir::expr::BigInt;
+
+/// Var and its bits, `None` for unknown
+type Change = (usize, Option<BigInt>);
+
+struct Var {
+    name: String,
+    size: usize,
+    /// Last dumped value, `None` for unknown
+    last: Option<Option<BigInt>>,
+}
+
+pub struct Vcd {
+    module: String,
+    vars: Vec<Var>,
+    /// Changed vars and their bits at each time step
+    changes: Vec<(u64, Vec<Change>)>,
+}
+
+impl Vcd {
+    pub fn new<S: Into<String>>(module: S) -> Self {
+        Self {
+            module: module.into(),
+            vars: vec![],
+            changes: vec![],
+        }
+    }
+
+    /// Declares a signal, returning the id to record its changes with
+    pub fn add_var(&mut self, name: &str, size: usize) -> usize {
+        self.vars.push(Var {
+            name: name.to_string(),
+            size,
+            last: None,
+        });
+        self.vars.len() - 1
+    }
+
+    /// Records the value of a signal at `time`, nothing is written if it did not change.
+    /// Negative values are written as two's complement
+    pub fn change(&mut self, time: u64, var: usize, value: Option<&BigInt>) {
+        let modulus = BigInt::from(1) << self.vars[var].size;
+        let value = value.map(|value| ((value % &modulus) + &modulus) % &modulus);
+        if self.vars[var].last.as_ref() == Some(&value) {
+            return;
+        }
+        self.vars[var].last = Some(value.clone());
+        match self.changes.last_mut() {
+            Some((last, changes)) if *last == time => changes.push((var, value)),
+            _ => self.changes.push((time, vec![(var, value)])),
+        }
+    }
+
+    /// Printable identifier code of a var
+    fn id(mut var: usize) -> String {
+        let mut ret = String::new();
+        loop {
+            ret.push((b'!' + (var % 94) as u8) as char);
+            var /= 94;
+            if var == 0 {
+                return ret;
+            }
+        }
+    }
+
+    fn value(size: usize, value: &Option<BigInt>) -> String {
+        match value {
+            None if size == 1 => "x".into(),
+            None => "bx ".into(),
+            Some(value) if size == 1 => format!("{}", value),
+            Some(value) => format!("b{} ", value.to_str_radix(2)),
+        }
+    }
+}
+
+impl std::fmt::Display for Vcd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "$timescale 1ns $end")?;
+        writeln!(f, "$scope module {} $end", self.module)?;
+        for (i, var) in self.vars.iter().enumerate() {
+            writeln!(f, "$var reg {} {} {} $end", var.size, Vcd::id(i), var.name)?;
+        }
+        writeln!(f, "$upscope $end")?;
+        writeln!(f, "$enddefinitions $end")?;
+        for (time, changes) in &self.changes {
+            writeln!(f, "#{}", time)?;
+            for (var, value) in changes {
+                let size = self.vars[*var].size;
+                writeln!(f, "{}{}", Vcd::value(size, value), Vcd::id(*var))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump() {
+        let mut vcd = Vcd::new("top");
+        let clock = vcd.add_var("clock", 1);
+        let count = vcd.add_var("count", 4);
+        vcd.change(0, clock, None);
+        vcd.change(0, count, Some(&BigInt::from(-1)));
+        vcd.change(5, clock, Some(&BigInt::from(1)));
+        vcd.change(5, count, Some(&BigInt::from(15)));
+        let text = vcd.to_string();
+        // println!("{}", text);
+        assert!(text.contains("$var reg 4 \" count $end"));
+        assert!(text.ends_with("#0\nx!\nb1111 \"\n#5\n1!\n"));
+    }
+}
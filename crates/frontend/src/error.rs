@@ -0,0 +1,102 @@
+use rustpython_parser::source_code::{LineIndex, SourceCode};
+use rustpython_parser::text_size::TextRange;
+
+/// Category of a frontend diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompileErrorKind {
+    /// Source text is not valid Python
+    Parse,
+    /// Valid Python that is outside of the supported subset
+    UnsupportedSyntax,
+    /// Operator without an IR equivalent
+    UnsupportedOperator,
+    /// Constant that is not representable as an integer
+    UnsupportedConstant,
+    /// Assignment to something other than a plain name
+    InvalidTarget,
+}
+
+impl std::fmt::Display for CompileErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            CompileErrorKind::Parse => "parse error",
+            CompileErrorKind::UnsupportedSyntax => "unsupported syntax",
+            CompileErrorKind::UnsupportedOperator => "unsupported operator",
+            CompileErrorKind::UnsupportedConstant => "unsupported constant",
+            CompileErrorKind::InvalidTarget => "invalid assignment target",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// 1-indexed location range in the Python source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    pub(crate) fn from_range(source: &str, range: TextRange) -> Self {
+        let index = LineIndex::from_source_text(source);
+        let code = SourceCode::new(source, &index);
+        let start = code.source_location(range.start());
+        let end = code.source_location(range.end());
+        Self {
+            line: start.row.get(),
+            column: start.column.get(),
+            end_line: end.row.get(),
+            end_column: end.column.get(),
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Diagnostic produced while translating Python source into a CFG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub kind: CompileErrorKind,
+    pub message: String,
+    /// Name of the Python function being translated, empty if it is not known yet
+    pub function: String,
+    pub span: Span,
+}
+
+impl CompileError {
+    pub fn new(
+        kind: CompileErrorKind,
+        message: impl Into<String>,
+        function: impl Into<String>,
+        span: Span,
+    ) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            function: function.into(),
+            span,
+        }
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.function.is_empty() {
+            write!(f, "{}: {}: {}", self.span, self.kind, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}: {}: {}",
+                self.function, self.span, self.kind, self.message
+            )
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
@@ -1,4 +1,6 @@
+mod error;
 mod visitor;
+pub use error::{CompileError, CompileErrorKind, Span};
 pub use visitor::AstVisitor;
 
 #[cfg(test)]
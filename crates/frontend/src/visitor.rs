@@ -1,8 +1,20 @@
 use ast::*;
 use rustpython_parser::ast::Visitor;
+use rustpython_parser::text_size::TextRange;
 use rustpython_parser::{ast, Parse};
-use tohdl_ir::expr::VarExpr;
-use tohdl_ir::graph::{BranchEdge, Edge, FuncNode, Node, NodeIndex, NoneEdge, CFG};
+use std::collections::BTreeMap;
+use tohdl_ir::expr::{IntType, VarExpr};
+use tohdl_ir::graph::{BranchEdge, Edge, ExternalKind, FuncNode, Node, NodeIndex, NoneEdge, CFG};
+
+use crate::error::{CompileError, CompileErrorKind, Span};
+
+/// Loop that `break` and `continue` refer to
+struct LoopEntry {
+    /// Target of `continue`
+    header: NodeIndex,
+    /// Nodes that `break` out of the loop, connected to whatever follows it
+    breaks: Vec<StackEntry>,
+}
 
 #[derive(Debug, Clone)]
 struct StackEntry {
@@ -23,6 +35,16 @@ pub struct AstVisitor {
     graph: CFG,
     expr_stack: Vec<tohdl_ir::expr::Expr>,
     node_stack: Vec<StackEntry>,
+    source: String,
+    errors: Vec<CompileError>,
+    calls: Vec<(String, Span)>,
+    /// Number of `for` loops lowered so far, used to name their hidden variables
+    for_count: usize,
+    loop_stack: Vec<LoopEntry>,
+    /// Number of tuple assignments lowered so far, used to name their temporaries
+    tuple_count: usize,
+    /// Type of every variable seen so far, from its annotation or the default
+    var_types: BTreeMap<String, IntType>,
 }
 
 impl Default for AstVisitor {
@@ -31,6 +53,13 @@ impl Default for AstVisitor {
             graph: CFG::default(),
             expr_stack: vec![],
             node_stack: vec![],
+            source: String::new(),
+            errors: vec![],
+            calls: vec![],
+            for_count: 0,
+            loop_stack: vec![],
+            tuple_count: 0,
+            var_types: BTreeMap::new(),
         };
 
         // Initialize root func node
@@ -43,18 +72,59 @@ impl Default for AstVisitor {
 }
 
 impl AstVisitor {
-    pub fn from_text(text: &str) -> Self {
-        let mut ret = Self::default();
-        let ast = ast::Suite::parse(text, "<embedded>").unwrap();
+    /// Parses and visits the first function in `text`,
+    /// collecting every diagnostic found along the way
+    pub fn from_text(text: &str) -> Result<Self, Vec<CompileError>> {
+        let mut ret = Self {
+            source: text.to_owned(),
+            ..Default::default()
+        };
+        let ast = match ast::Suite::parse(text, "<embedded>") {
+            Ok(ast) => ast,
+            Err(e) => {
+                ret.error(
+                    CompileErrorKind::Parse,
+                    e.error.to_string(),
+                    TextRange::empty(e.offset),
+                );
+                return Err(ret.errors);
+            }
+        };
         // dbg!(&ast);
-        ret.visit_stmt(ast[0].clone());
-        ret
+        match ast.into_iter().next() {
+            Some(stmt @ Stmt::FunctionDef(_)) => ret.visit_stmt(stmt),
+            Some(stmt) => ret.error(
+                CompileErrorKind::UnsupportedSyntax,
+                "expected a function definition",
+                stmt.range(),
+            ),
+            None => ret.error(
+                CompileErrorKind::UnsupportedSyntax,
+                "expected a function definition",
+                TextRange::default(),
+            ),
+        }
+        if ret.errors.is_empty() {
+            Ok(ret)
+        } else {
+            Err(ret.errors)
+        }
     }
 
     pub fn get_graph(&self) -> CFG {
         self.graph.clone()
     }
 
+    /// Diagnostics collected so far
+    pub fn errors(&self) -> &[CompileError] {
+        &self.errors
+    }
+
+    /// Names of called functions and where they are called from
+    pub fn calls(&self) -> &[(String, Span)] {
+        &self.calls
+    }
+
     pub fn debug_status(&self) -> String {
         format!(
             "expr_stack {:?}, node_stack {:?}",
@@ -66,60 +136,567 @@ impl AstVisitor {
         // println!("{}", self.debug_status());
     }
 
-    pub fn binop_mapping(op: rustpython_ast::Operator) -> tohdl_ir::expr::Operator {
+    pub fn binop_mapping(op: rustpython_ast::Operator) -> Option<tohdl_ir::expr::Operator> {
         match op {
-            Operator::Add => tohdl_ir::expr::Operator::Add,
-            Operator::Sub => tohdl_ir::expr::Operator::Sub,
-            Operator::Mult => tohdl_ir::expr::Operator::Mul,
-            Operator::Div => tohdl_ir::expr::Operator::Div,
-            Operator::Mod => tohdl_ir::expr::Operator::Mod,
-            Operator::LShift => tohdl_ir::expr::Operator::LShift,
-            Operator::RShift => tohdl_ir::expr::Operator::RShift,
-            Operator::BitAnd => tohdl_ir::expr::Operator::BitAnd,
-            Operator::BitOr => tohdl_ir::expr::Operator::BitOr,
-            Operator::BitXor => tohdl_ir::expr::Operator::BitXor,
-            _ => todo!("{}", format!("{:?}", op)),
+            Operator::Add => Some(tohdl_ir::expr::Operator::Add),
+            Operator::Sub => Some(tohdl_ir::expr::Operator::Sub),
+            Operator::Mult => Some(tohdl_ir::expr::Operator::Mul),
+            Operator::Div => Some(tohdl_ir::expr::Operator::Div),
+            // Truncating, corrected by [AstVisitor::binop]
+            Operator::FloorDiv => Some(tohdl_ir::expr::Operator::Div),
+            Operator::Mod => Some(tohdl_ir::expr::Operator::Mod),
+            Operator::LShift => Some(tohdl_ir::expr::Operator::LShift),
+            Operator::RShift => Some(tohdl_ir::expr::Operator::RShift),
+            Operator::BitAnd => Some(tohdl_ir::expr::Operator::BitAnd),
+            Operator::BitOr => Some(tohdl_ir::expr::Operator::BitOr),
+            Operator::BitXor => Some(tohdl_ir::expr::Operator::BitXor),
+            _ => None,
         }
     }
-}
 
-impl Visitor for AstVisitor {
-    fn visit_expr_call(&mut self, node: ExprCall) {
-        {
-            let value = node.func;
-            self.visit_expr(*value);
+    /// `left op right` as Python computes it. The IR truncates `//` and `%` towards zero,
+    /// Python rounds towards negative infinity, which differs when the remainder is non-zero
+    /// and the operands have different signs
+    pub fn binop(
+        op: rustpython_ast::Operator,
+        left: tohdl_ir::expr::Expr,
+        right: tohdl_ir::expr::Expr,
+    ) -> Option<tohdl_ir::expr::Expr> {
+        use tohdl_ir::expr::{Expr as IrExpr, IntExpr, Operator as IrOperator};
+        let bin = |left: &IrExpr, op, right: &IrExpr| {
+            IrExpr::BinOp(Box::new(left.clone()), op, Box::new(right.clone()))
+        };
+        let truncated = bin(&left, AstVisitor::binop_mapping(op)?, &right);
+        if !matches!(op, Operator::FloorDiv | Operator::Mod) {
+            return Some(truncated);
         }
-        let func_name = self.expr_stack.pop().unwrap();
+        let zero = IrExpr::Int(IntExpr::new(0));
+        let inexact = bin(
+            &bin(&left, IrOperator::Mod, &right),
+            IrOperator::NotEq,
+            &zero,
+        );
+        let signs = bin(
+            &bin(&left, IrOperator::Lt, &zero),
+            IrOperator::NotEq,
+            &bin(&right, IrOperator::Lt, &zero),
+        );
+        let rounded = bin(&inexact, IrOperator::And, &signs);
+        Some(match op {
+            Operator::FloorDiv => bin(&truncated, IrOperator::Sub, &rounded),
+            _ => bin(
+                &truncated,
+                IrOperator::Add,
+                &IrExpr::Mux(Box::new(rounded), Box::new(right), Box::new(zero)),
+            ),
+        })
+    }
+
+    pub fn boolop_mapping(op: BoolOp) -> tohdl_ir::expr::Operator {
+        match op {
+            BoolOp::And => tohdl_ir::expr::Operator::And,
+            BoolOp::Or => tohdl_ir::expr::Operator::Or,
+        }
+    }
+
+    /// `None` for unary plus, which is the identity
+    pub fn unaryop_mapping(op: UnaryOp) -> Option<tohdl_ir::expr::UnaryOperator> {
+        match op {
+            UnaryOp::Not => Some(tohdl_ir::expr::UnaryOperator::Not),
+            UnaryOp::USub => Some(tohdl_ir::expr::UnaryOperator::Neg),
+            UnaryOp::Invert => Some(tohdl_ir::expr::UnaryOperator::Invert),
+            UnaryOp::UAdd => None,
+        }
+    }
+
+    /// Integer type named by an annotation, `u<N>` and `i<N>` are `N` bits wide
+    pub fn type_mapping(name: &str) -> Option<IntType> {
+        match name {
+            "int" => Some(IntType::default()),
+            "bool" => Some(IntType::new(1, false)),
+            _ => {
+                let signed = match name.chars().next()? {
+                    'u' => false,
+                    'i' => true,
+                    _ => return None,
+                };
+                match name[1..].parse::<usize>() {
+                    Ok(size) if size > 0 && !name[1..].starts_with('0') => {
+                        Some(IntType::new(size, signed))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub fn cmpop_mapping(op: CmpOp) -> Option<tohdl_ir::expr::Operator> {
+        match op {
+            CmpOp::Lt => Some(tohdl_ir::expr::Operator::Lt),
+            CmpOp::Gt => Some(tohdl_ir::expr::Operator::Gt),
+            CmpOp::LtE => Some(tohdl_ir::expr::Operator::LtE),
+            CmpOp::GtE => Some(tohdl_ir::expr::Operator::GtE),
+            CmpOp::Eq => Some(tohdl_ir::expr::Operator::Eq),
+            CmpOp::NotEq => Some(tohdl_ir::expr::Operator::NotEq),
+            _ => None,
+        }
+    }
+
+    /// Records a diagnostic at `range` in the source
+    fn error(&mut self, kind: CompileErrorKind, message: impl Into<String>, range: TextRange) {
+        let span = self.span(range);
+        self.errors.push(CompileError::new(
+            kind,
+            message,
+            self.graph.name.clone(),
+            span,
+        ));
+    }
+
+    fn span(&self, range: TextRange) -> Span {
+        if usize::from(range.end()) <= self.source.len() {
+            Span::from_range(&self.source, range)
+        } else {
+            Span::default()
+        }
+    }
+
+    /// First line of the source text at `range`, for use in diagnostics
+    fn snippet(&self, range: TextRange) -> String {
+        self.source
+            .get(std::ops::Range::<usize>::from(range))
+            .and_then(|s| s.lines().next())
+            .unwrap_or_default()
+            .to_owned()
+    }
+
+    /// Records an unsupported syntax diagnostic for the construct at `range`
+    fn unsupported(&mut self, what: &str, range: TextRange) {
+        let snippet = self.snippet(range);
+        let message = if snippet.is_empty() {
+            format!("{} is not supported", what)
+        } else {
+            format!("{} `{}` is not supported", what, snippet)
+        };
+        self.error(CompileErrorKind::UnsupportedSyntax, message, range);
+    }
+
+    /// Stand-in value so visiting can continue after an error
+    fn placeholder() -> tohdl_ir::expr::Expr {
+        tohdl_ir::expr::Expr::Int(tohdl_ir::expr::IntExpr::new(0))
+    }
+
+    /// Pops the most recent expression, tolerating an empty stack
+    /// if an earlier diagnostic did not push one
+    fn pop_expr(&mut self) -> tohdl_ir::expr::Expr {
+        self.expr_stack.pop().unwrap_or_else(Self::placeholder)
+    }
+
+    /// Connects every pending predecessor to `node`
+    fn link_pending(&mut self, node: NodeIndex) {
+        while let Some(prev) = self.node_stack.pop() {
+            self.graph.add_edge(prev.node, node, prev.edge_type);
+        }
+    }
+
+    /// Appends `lvalue = rvalue` after the pending predecessors
+    fn push_assign(&mut self, lvalue: VarExpr, rvalue: tohdl_ir::expr::Expr) {
+        let node = self
+            .graph
+            .add_node(tohdl_ir::graph::AssignNode { lvalue, rvalue });
+        self.link_pending(node);
+        self.node_stack.push((node, NoneEdge.into()).into());
+    }
+
+    /// Builds the loop structure shared by `while` and `for`,
+    /// `prelude` is emitted at the start of every iteration before `body`
+    fn visit_loop(
+        &mut self,
+        condition: tohdl_ir::expr::Expr,
+        body: Vec<Stmt>,
+        prelude: impl FnOnce(&mut Self),
+    ) {
+        let while_node = tohdl_ir::graph::BranchNode { cond: condition };
+        let while_node = self.graph.add_node(while_node);
+        self.link_pending(while_node);
+        self.node_stack
+            .push((while_node, BranchEdge::new(true).into()).into());
+
+        prelude(self);
+        self.visit_loop_body(while_node, body);
+
+        self.node_stack
+            .push((while_node, BranchEdge::new(false).into()).into());
+        self.node_stack
+            .extend(self.loop_stack.pop().unwrap().breaks);
+    }
+
+    /// Visits a loop body that jumps back to `header`,
+    /// leaving the loop's breaks on the loop stack
+    fn visit_loop_body(&mut self, header: NodeIndex, body: Vec<Stmt>) {
+        self.loop_stack.push(LoopEntry {
+            header,
+            breaks: vec![],
+        });
+        self.visit_body(body);
+        self.link_pending(header);
+    }
+
+    /// Visits the body of a loop that could not be lowered, only for its diagnostics.
+    /// What comes before the loop falls through to what comes after it
+    fn visit_unlowered_loop_body(&mut self, body: Vec<Stmt>) {
+        let pending = std::mem::take(&mut self.node_stack);
+        let header = self.graph.add_node(tohdl_ir::graph::BranchNode {
+            cond: Self::placeholder(),
+        });
+        self.node_stack
+            .push((header, BranchEdge::new(true).into()).into());
+        self.visit_loop_body(header, body);
+        self.loop_stack.pop();
+        self.node_stack = pending;
+    }
+
+    /// Visits statements in order, stopping after one that never falls through
+    fn visit_body(&mut self, body: Vec<Stmt>) {
+        for value in body {
+            self.visit_stmt(value);
+            if self.node_stack.is_empty() {
+                // Remaining statements are unreachable
+                break;
+            }
+        }
+    }
+
+    /// Creates the call and external nodes for a function call,
+    /// returning the function name and external node
+    fn add_external(&mut self, node: ExprCall, kind: ExternalKind) -> Option<(String, NodeIndex)> {
+        let func_name = match *node.func {
+            Expr::Name(name) => name.id.to_string(),
+            other => {
+                self.unsupported("call target", other.range());
+                String::new()
+            }
+        };
 
+        let depth = self.expr_stack.len();
+        let arg_ranges = node.args.iter().map(|arg| arg.range()).collect::<Vec<_>>();
         for value in node.args {
             self.visit_expr(value);
         }
-        if node.keywords.len() > 0 {
-            panic!("Keyword arguments are not supported {:?}", node.keywords);
+        for keyword in &node.keywords {
+            self.unsupported("keyword argument", keyword.range);
         }
 
         // Parse arguments passed
-        let args = self.expr_stack.iter().cloned().map(|x| match x {
-            tohdl_ir::expr::Expr::Var(v) => v,
-            _ => panic!("Unexpected expr in function args {:?}", x)
-        }).collect();
-        self.expr_stack.clear();
+        let mut args = vec![];
+        for (x, range) in self.expr_stack.split_off(depth).into_iter().zip(arg_ranges) {
+            match x {
+                tohdl_ir::expr::Expr::Var(v) => args.push(v),
+                _ => {
+                    let snippet = self.snippet(range);
+                    self.error(
+                        CompileErrorKind::UnsupportedSyntax,
+                        format!(
+                            "function argument `{}` must be a variable, assign it to one first",
+                            snippet
+                        ),
+                        range,
+                    );
+                }
+            }
+        }
+        if func_name.is_empty() {
+            return None;
+        }
+        let span = self.span(node.range);
+        self.calls.push((func_name.clone(), span));
 
         // Create call node
-        let call_node = tohdl_ir::graph::CallNode {
-            args,
-        };
+        let call_node = tohdl_ir::graph::CallNode { args };
         let call_node = self.graph.add_node(call_node);
-
-        while let Some(prev) = self.node_stack.pop() {
-            self.graph.add_edge(prev.node, call_node, prev.edge_type);
-        }
+        self.link_pending(call_node);
 
         // Create external node
-        let extern_node = tohdl_ir::graph::ExternalNode{name: func_name.to_string()};
+        let extern_node = tohdl_ir::graph::ExternalNode {
+            name: func_name.clone(),
+            kind,
+        };
         let extern_node = self.graph.add_node(extern_node);
         self.graph.add_edge(call_node, extern_node, NoneEdge.into());
 
+        Some((func_name, extern_node))
+    }
+
+    /// Lowers `for x in gen(...)` to an iterating external node,
+    /// whose true edge runs the body with the next yielded value
+    fn visit_for_generator(&mut self, node: StmtFor) {
+        let targets = self.visit_targets(*node.target);
+        let Expr::Call(call) = *node.iter else {
+            unreachable!()
+        };
+        let external = self.add_external(call, ExternalKind::Iterate);
+        let (Some(targets), Some((_, extern_node))) = (targets, external) else {
+            return self.visit_unlowered_loop_body(node.body);
+        };
+
+        let func_node = tohdl_ir::graph::FuncNode { params: targets };
+        let func_node = self.graph.add_node(func_node);
+        self.graph
+            .add_edge(extern_node, func_node, BranchEdge::new(true).into());
+        self.node_stack.push((func_node, NoneEdge.into()).into());
+
+        self.visit_loop_body(extern_node, node.body);
+
+        self.node_stack
+            .push((extern_node, BranchEdge::new(false).into()).into());
+        self.node_stack
+            .extend(self.loop_stack.pop().unwrap().breaks);
+    }
+
+    /// Arguments of `range(...)` as `(start, stop, step)`
+    fn range_args(&mut self, iter: Expr) -> Option<(Expr, Expr, Expr)> {
+        let range = iter.range();
+        let call = match iter {
+            Expr::Call(call) => call,
+            _ => {
+                self.unsupported("for loop over", range);
+                return None;
+            }
+        };
+        match call.func.as_ref() {
+            Expr::Name(name) if name.id.as_str() == "range" => {}
+            _ => {
+                self.unsupported("for loop over", range);
+                return None;
+            }
+        }
+        for keyword in &call.keywords {
+            self.unsupported("keyword argument", keyword.range);
+        }
+        let int = |value: i32| {
+            Expr::Constant(ExprConstant {
+                range: TextRange::default(),
+                value: Constant::Int(value.into()),
+                kind: None,
+            })
+        };
+        let mut args = call.args.into_iter();
+        match (args.next(), args.next(), args.next(), args.next()) {
+            (Some(stop), None, None, None) => Some((int(0), stop, int(1))),
+            (Some(start), Some(stop), None, None) => Some((start, stop, int(1))),
+            (Some(start), Some(stop), Some(step), None) => Some((start, stop, step)),
+            _ => {
+                self.error(
+                    CompileErrorKind::UnsupportedSyntax,
+                    "range expects one to three arguments",
+                    range,
+                );
+                None
+            }
+        }
+    }
+
+    /// Visits a target that may be a tuple of plain names
+    fn visit_targets(&mut self, expr: Expr) -> Option<Vec<VarExpr>> {
+        match expr {
+            Expr::Tuple(tuple) => tuple
+                .elts
+                .into_iter()
+                .map(|elt| self.visit_target(elt))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .collect(),
+            other => self.visit_target(other).map(|target| vec![target]),
+        }
+    }
+
+    /// Visits a value that may be a tuple, one expression per element
+    fn visit_values(&mut self, expr: Expr) -> Vec<tohdl_ir::expr::Expr> {
+        match expr {
+            Expr::Tuple(tuple) => tuple
+                .elts
+                .into_iter()
+                .map(|elt| {
+                    self.visit_expr(elt);
+                    self.pop_expr()
+                })
+                .collect(),
+            other => {
+                self.visit_expr(other);
+                vec![self.pop_expr()]
+            }
+        }
+    }
+
+    /// Assigns every value to its target as if all values were evaluated first
+    fn push_parallel_assign(
+        &mut self,
+        targets: Vec<VarExpr>,
+        mut values: Vec<tohdl_ir::expr::Expr>,
+    ) {
+        // Values that read an earlier target are saved to temporaries beforehand
+        let id = self.tuple_count;
+        let mut used_temps = false;
+        for (i, value) in values.iter_mut().enumerate() {
+            if value.get_vars_iter().any(|var| targets[..i].contains(var)) {
+                used_temps = true;
+                let temp_var = targets[i].with_name(&format!("__tuple{}_{}", id, i));
+                let value = std::mem::replace(value, tohdl_ir::expr::Expr::Var(temp_var.clone()));
+                self.push_assign(temp_var, value);
+            }
+        }
+        if used_temps {
+            self.tuple_count += 1;
+        }
+        for (target, value) in targets.into_iter().zip(values) {
+            self.push_assign(target, value);
+        }
+    }
+
+    /// Variable named `name` with its declared type
+    fn var(&mut self, name: &str) -> VarExpr {
+        let int_type = *self.var_types.entry(name.to_owned()).or_default();
+        let mut var = VarExpr::new(name);
+        var.set_int_type(int_type);
+        var
+    }
+
+    /// Gives `name` the type of `annotation`, which must happen before its first use
+    fn declare(&mut self, name: &str, annotation: &Expr) {
+        let Some(int_type) = self.annotation_type(annotation) else {
+            return;
+        };
+        match self.var_types.get(name) {
+            Some(existing) if *existing != int_type => self.error(
+                CompileErrorKind::UnsupportedSyntax,
+                format!(
+                    "`{}` is annotated as {} but was already used as {}",
+                    name, int_type, existing
+                ),
+                annotation.range(),
+            ),
+            _ => {
+                self.var_types.insert(name.to_owned(), int_type);
+            }
+        }
+    }
+
+    /// Integer type of an annotation such as `u8` or `types.i12`,
+    /// `None` for annotations that do not name one
+    fn annotation_type(&self, annotation: &Expr) -> Option<IntType> {
+        match annotation {
+            Expr::Name(name) => AstVisitor::type_mapping(name.id.as_str()),
+            Expr::Attribute(attr) => AstVisitor::type_mapping(attr.attr.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Types of the values yielded or returned by a function,
+    /// from `-> u8`, `-> tuple[u8, i4]` or `-> Generator[u8, None, None]`
+    fn output_types(&self, annotation: &Expr) -> Vec<IntType> {
+        match annotation {
+            Expr::Subscript(subscript) => {
+                let name = match subscript.value.as_ref() {
+                    Expr::Name(name) => name.id.as_str(),
+                    Expr::Attribute(attr) => attr.attr.as_str(),
+                    _ => return vec![],
+                };
+                let args = match subscript.slice.as_ref() {
+                    Expr::Tuple(tuple) => tuple.elts.iter().collect::<Vec<_>>(),
+                    other => vec![other],
+                };
+                match name {
+                    "tuple" | "Tuple" => args
+                        .into_iter()
+                        .map(|arg| self.annotation_type(arg).unwrap_or_default())
+                        .collect(),
+                    "Generator" | "Iterator" | "Iterable" => args
+                        .first()
+                        .map(|arg| self.output_types(arg))
+                        .unwrap_or_default(),
+                    _ => vec![],
+                }
+            }
+            other => self.annotation_type(other).into_iter().collect(),
+        }
+    }
+
+    /// Visits `expr`, reporting anything that isn't a plain name as an invalid target
+    fn visit_target(&mut self, expr: Expr) -> Option<VarExpr> {
+        match expr {
+            Expr::Name(name) => Some(self.var(name.id.as_str())),
+            other => {
+                let snippet = self.snippet(other.range());
+                self.error(
+                    CompileErrorKind::InvalidTarget,
+                    format!(
+                        "cannot assign to `{}`, only plain names are supported",
+                        snippet
+                    ),
+                    other.range(),
+                );
+                None
+            }
+        }
+    }
+}
+
+impl Visitor for AstVisitor {
+    fn visit_stmt(&mut self, node: Stmt) {
+        match node {
+            Stmt::FunctionDef(_)
+            | Stmt::Assign(_)
+            | Stmt::AugAssign(_)
+            | Stmt::AnnAssign(_)
+            | Stmt::If(_)
+            | Stmt::While(_)
+            | Stmt::For(_)
+            | Stmt::Return(_)
+            | Stmt::Expr(_)
+            | Stmt::Break(_)
+            | Stmt::Continue(_)
+            | Stmt::Pass(_) => self.generic_visit_stmt(node),
+            _ => self.unsupported("statement", node.range()),
+        }
+    }
+    fn visit_expr(&mut self, node: Expr) {
+        match node {
+            Expr::Call(_)
+            | Expr::BinOp(_)
+            | Expr::BoolOp(_)
+            | Expr::UnaryOp(_)
+            | Expr::IfExp(_)
+            | Expr::Compare(_)
+            | Expr::Name(_)
+            | Expr::Constant(_)
+            | Expr::Yield(_)
+            | Expr::YieldFrom(_)
+            | Expr::Tuple(_) => self.generic_visit_expr(node),
+            _ => {
+                self.unsupported("expression", node.range());
+                self.expr_stack.push(Self::placeholder());
+            }
+        }
+    }
+    fn visit_stmt_expr(&mut self, node: StmtExpr) {
+        // Docstrings and other bare strings are no-ops
+        if let Expr::Constant(ExprConstant {
+            value: Constant::Str(_),
+            ..
+        }) = *node.value
+        {
+            return;
+        }
+        let depth = self.expr_stack.len();
+        self.visit_expr(*node.value);
+        self.expr_stack.truncate(depth);
+    }
+    fn visit_expr_call(&mut self, node: ExprCall) {
+        let Some((func_name, extern_node)) = self.add_external(node, ExternalKind::Call) else {
+            self.expr_stack.push(Self::placeholder());
+            return;
+        };
+
         // Create func node
         let temp_var = tohdl_ir::expr::VarExpr::new(&format!("{}_0", func_name));
         let func_node = tohdl_ir::graph::FuncNode {
@@ -131,47 +708,92 @@ impl Visitor for AstVisitor {
         self.expr_stack.push(tohdl_ir::expr::Expr::Var(temp_var));
         self.node_stack.push((func_node, NoneEdge.into()).into());
     }
+    fn visit_expr_yield_from(&mut self, node: ExprYieldFrom) {
+        // Inlining an external call keeps the callee's yields,
+        // so the call's return value is the value of the `yield from`
+        match *node.value {
+            value @ Expr::Call(_) => self.visit_expr(value),
+            other => {
+                self.unsupported("yield from", other.range());
+                self.expr_stack.push(Self::placeholder());
+            }
+        }
+    }
     fn visit_expr_tuple(&mut self, node: ExprTuple) {
-        panic!("Tuples are unsupported {node:?}")
+        self.unsupported("tuple", node.range);
+        self.expr_stack.push(Self::placeholder());
     }
     fn visit_arguments(&mut self, node: Arguments) {
+        let mut names = vec![];
+        for arg in node.posonlyargs.into_iter().chain(node.args) {
+            match arg {
+                ArgWithDefault {
+                    range: _,
+                    def,
+                    default,
+                } => {
+                    if let Some(default) = default {
+                        self.unsupported("default argument", default.range());
+                    }
+                    if let Some(annotation) = &def.annotation {
+                        self.declare(def.arg.as_str(), annotation);
+                    }
+                    names.push(self.var(def.arg.as_str()))
+                }
+            }
+        }
+        if let Some(arg) = node.vararg {
+            self.unsupported("variadic argument", arg.range);
+        }
+        for arg in node.kwonlyargs {
+            self.unsupported("keyword-only argument", arg.def.range);
+        }
+        if let Some(arg) = node.kwarg {
+            self.unsupported("variadic keyword argument", arg.range);
+        }
         if let Some(FuncNode { params }) =
             FuncNode::concrete_mut(self.graph.get_node_mut(self.graph.get_entry()))
         {
-            for arg in node.args {
-                match arg {
-                    ArgWithDefault {
-                        range: _,
-                        def,
-                        default: _,
-                    } => params.push(VarExpr::new(&def.arg.to_string())),
-                }
-            }
+            params.extend(names);
         } else {
-            panic!("{node:?}")
+            panic!("Entry of {} is not a function node", self.graph.name)
         }
     }
     fn visit_stmt_function_def(&mut self, node: StmtFunctionDef) {
+        if !self.graph.name.is_empty() {
+            self.unsupported("nested function", node.range);
+            return;
+        }
         self.graph.name = node.name.as_str().to_owned();
-        self.generic_visit_stmt_function_def(node)
+        // Decorators have no effect on the generated hardware
+        if let Some(returns) = &node.returns {
+            self.graph.outputs = self.output_types(returns);
+        }
+        self.visit_arguments(*node.args);
+        self.visit_body(node.body);
     }
     fn visit_stmt_aug_assign(&mut self, node: StmtAugAssign) {
-        self.visit_expr(*node.target);
-        let target = match self.expr_stack.pop().unwrap() {
-            tohdl_ir::expr::Expr::Var(var) => var,
-            _ => todo!(),
-        };
+        let target = self.visit_target(*node.target);
+        if AstVisitor::binop_mapping(node.op).is_none() {
+            self.error(
+                CompileErrorKind::UnsupportedOperator,
+                format!("operator `{:?}` is not supported", node.op),
+                node.range,
+            );
+        }
         {
             let value = node.value;
             self.visit_expr(*value);
         }
-        let value = self.expr_stack.pop().unwrap();
-        let oper = AstVisitor::binop_mapping(node.op);
-        let value = tohdl_ir::expr::Expr::BinOp(
-            Box::new(tohdl_ir::expr::Expr::Var(target.clone())),
-            oper,
-            Box::new(value),
-        );
+        let value = self.pop_expr();
+        let Some(target) = target else {
+            return;
+        };
+        let Some(value) =
+            AstVisitor::binop(node.op, tohdl_ir::expr::Expr::Var(target.clone()), value)
+        else {
+            return;
+        };
         let node = tohdl_ir::graph::AssignNode {
             lvalue: target,
             rvalue: value,
@@ -183,163 +805,391 @@ impl Visitor for AstVisitor {
         }
         self.node_stack.push((node, NoneEdge.into()).into());
     }
-    fn visit_stmt_assign(&mut self, node: StmtAssign) {
-        for value in node.targets {
-            self.visit_expr(value);
+    fn visit_stmt_ann_assign(&mut self, node: StmtAnnAssign) {
+        if let Expr::Name(name) = node.target.as_ref() {
+            self.declare(name.id.as_str(), &node.annotation);
         }
-        let target = match self.expr_stack.pop().unwrap() {
-            tohdl_ir::expr::Expr::Var(var) => var,
-            _ => todo!(),
+        let target = self.visit_target(*node.target);
+        // A bare annotation only declares the variable
+        let Some(value) = node.value else {
+            return;
         };
-        {
-            let value = node.value;
-            self.visit_expr(*value);
+        self.visit_expr(*value);
+        let value = self.pop_expr();
+        if let Some(target) = target {
+            self.push_assign(target, value);
         }
-        let value = self.expr_stack.pop().unwrap();
-        let node = tohdl_ir::graph::AssignNode {
-            lvalue: target,
-            rvalue: value,
-        };
-        let node = self.graph.add_node(node);
-
-        while let Some(prev) = self.node_stack.pop() {
-            self.graph.add_edge(prev.node, node, prev.edge_type);
+    }
+    fn visit_stmt_assign(&mut self, node: StmtAssign) {
+        if node.targets.len() != 1 {
+            self.unsupported("chained assignment", node.range);
+        }
+        let targets = node
+            .targets
+            .into_iter()
+            .map(|value| self.visit_targets(value))
+            .last()
+            .flatten();
+        let value_range = node.value.range();
+        match (*node.value, targets) {
+            // Unpacking a function's returned values
+            (Expr::Call(call), Some(targets)) if targets.len() > 1 => {
+                if let Some((_, extern_node)) = self.add_external(call, ExternalKind::Call) {
+                    let func_node = tohdl_ir::graph::FuncNode { params: targets };
+                    let func_node = self.graph.add_node(func_node);
+                    self.graph.add_edge(extern_node, func_node, NoneEdge.into());
+                    self.node_stack.push((func_node, NoneEdge.into()).into());
+                }
+            }
+            (value, targets) => {
+                let values = self.visit_values(value);
+                let Some(targets) = targets else {
+                    return;
+                };
+                if targets.len() != values.len() {
+                    self.error(
+                        CompileErrorKind::InvalidTarget,
+                        format!(
+                            "cannot unpack {} values into {} targets",
+                            values.len(),
+                            targets.len()
+                        ),
+                        value_range,
+                    );
+                    return;
+                }
+                self.push_parallel_assign(targets, values);
+            }
         }
-        self.node_stack.push((node, NoneEdge.into()).into());
         self.print_debug_status();
     }
     fn visit_expr_bin_op(&mut self, node: ExprBinOp) {
-        let oper = AstVisitor::binop_mapping(node.op);
+        let op = node.op;
+        if AstVisitor::binop_mapping(op).is_none() {
+            self.error(
+                CompileErrorKind::UnsupportedOperator,
+                format!("operator `{:?}` is not supported", node.op),
+                node.range,
+            );
+        }
         self.generic_visit_expr_bin_op(node);
-        let right = self.expr_stack.pop().unwrap();
-        let left = self.expr_stack.pop().unwrap();
+        let right = self.pop_expr();
+        let left = self.pop_expr();
 
-        let expr = tohdl_ir::expr::Expr::BinOp(Box::new(left), oper, Box::new(right));
+        let expr = AstVisitor::binop(op, left, right).unwrap_or_else(Self::placeholder);
         self.expr_stack.push(expr);
     }
-    fn visit_expr_compare(&mut self, node: ExprCompare) {
-        let op = match node.ops[0] {
-            CmpOp::Lt => tohdl_ir::expr::Operator::Lt,
-            CmpOp::Gt => tohdl_ir::expr::Operator::Gt,
-            CmpOp::LtE => tohdl_ir::expr::Operator::LtE,
-            CmpOp::GtE => tohdl_ir::expr::Operator::GtE,
-            CmpOp::Eq => tohdl_ir::expr::Operator::Eq,
-            _ => todo!("{}", format!("{:?}", node.ops[0])),
+    fn visit_expr_bool_op(&mut self, node: ExprBoolOp) {
+        // Operands have no side effects, so both sides are always evaluated
+        let oper = AstVisitor::boolop_mapping(node.op);
+        let depth = self.expr_stack.len();
+        self.generic_visit_expr_bool_op(node);
+        let expr = self
+            .expr_stack
+            .drain(depth..)
+            .reduce(|left, right| {
+                tohdl_ir::expr::Expr::BinOp(Box::new(left), oper.clone(), Box::new(right))
+            })
+            .unwrap_or_else(Self::placeholder);
+        self.expr_stack.push(expr);
+    }
+    fn visit_expr_unary_op(&mut self, node: ExprUnaryOp) {
+        let oper = AstVisitor::unaryop_mapping(node.op);
+        self.generic_visit_expr_unary_op(node);
+        let operand = self.pop_expr();
+        let expr = match oper {
+            Some(oper) => tohdl_ir::expr::Expr::UnaryOp(oper, Box::new(operand)),
+            None => operand,
         };
+        self.expr_stack.push(expr);
+    }
+    fn visit_expr_if_exp(&mut self, node: ExprIfExp) {
+        // Both arms are evaluated and one is selected, which is only
+        // equivalent to Python if they do not call or yield
+        self.visit_expr(*node.test);
+        let nodes = self.graph.nodes().count();
+        self.visit_expr(*node.body);
+        self.visit_expr(*node.orelse);
+        if self.graph.nodes().count() != nodes {
+            self.unsupported("call or yield in a conditional expression", node.range);
+        }
+        let else_ = self.pop_expr();
+        let then = self.pop_expr();
+        let cond = self.pop_expr();
+        self.expr_stack.push(tohdl_ir::expr::Expr::Mux(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(else_),
+        ));
+    }
+    fn visit_expr_compare(&mut self, node: ExprCompare) {
+        let ops = node
+            .ops
+            .iter()
+            .map(|op| {
+                let oper = AstVisitor::cmpop_mapping(*op);
+                if oper.is_none() {
+                    self.error(
+                        CompileErrorKind::UnsupportedOperator,
+                        format!("comparison `{:?}` is not supported", op),
+                        node.range,
+                    );
+                }
+                oper
+            })
+            .collect::<Option<Vec<_>>>();
+        let depth = self.expr_stack.len();
         self.generic_visit_expr_compare(node);
-        let right = self.expr_stack.pop().unwrap();
-        let left = self.expr_stack.pop().unwrap();
-        let expr = tohdl_ir::expr::Expr::BinOp(Box::new(left), op, Box::new(right));
+        // `a < b < c` is `(a < b) and (b < c)`
+        let operands = self.expr_stack.split_off(depth);
+        let expr = match ops {
+            Some(ops) if operands.len() == ops.len() + 1 => ops
+                .into_iter()
+                .zip(operands.windows(2))
+                .map(|(op, pair)| {
+                    tohdl_ir::expr::Expr::BinOp(
+                        Box::new(pair[0].clone()),
+                        op,
+                        Box::new(pair[1].clone()),
+                    )
+                })
+                .reduce(|left, right| {
+                    tohdl_ir::expr::Expr::BinOp(
+                        Box::new(left),
+                        tohdl_ir::expr::Operator::And,
+                        Box::new(right),
+                    )
+                })
+                .unwrap_or_else(Self::placeholder),
+            _ => Self::placeholder(),
+        };
         self.expr_stack.push(expr);
     }
     fn visit_expr_name(&mut self, node: ExprName) {
-        self.expr_stack
-            .push(tohdl_ir::expr::Expr::Var(tohdl_ir::expr::VarExpr::new(
-                node.id.as_str(),
-            )));
+        let var = self.var(node.id.as_str());
+        self.expr_stack.push(tohdl_ir::expr::Expr::Var(var));
     }
     fn visit_expr_constant(&mut self, node: ExprConstant) {
-        match node.value {
-            Constant::Int(i) => {
-                self.expr_stack
-                    .push(tohdl_ir::expr::Expr::Int(tohdl_ir::expr::IntExpr::new(
-                        str::parse::<i32>(&i.to_string()).unwrap(),
-                    )))
+        let value = match node.value {
+            // Arbitrary precision, the constant is as wide as it needs to be
+            Constant::Int(i) => i
+                .to_string()
+                .parse::<tohdl_ir::expr::BigInt>()
+                .expect("Python integers are decimal"),
+            Constant::Bool(b) => (b as i32).into(),
+            _ => {
+                self.error(
+                    CompileErrorKind::UnsupportedConstant,
+                    format!(
+                        "constant `{}` is not supported, only integers are",
+                        self.snippet(node.range)
+                    ),
+                    node.range,
+                );
+                0.into()
             }
-            Constant::Str(_) => {}
-            _ => todo!("{node:?}"),
         };
+        self.expr_stack
+            .push(tohdl_ir::expr::Expr::Int(tohdl_ir::expr::IntExpr::new(
+                value,
+            )));
     }
     fn visit_stmt_if(&mut self, node: StmtIf) {
-        let prev = self.node_stack.pop().unwrap();
         {
             let value = node.test;
             self.visit_expr(*value);
         }
-        let condition = self.expr_stack.pop().unwrap();
+        let condition = self.pop_expr();
         // println!("condition {:?}", condition);
         self.print_debug_status();
         let ifelse = tohdl_ir::graph::BranchNode { cond: condition };
         let ifelse_node = self.graph.add_node(ifelse);
+        self.link_pending(ifelse_node);
         self.node_stack
             .push((ifelse_node, BranchEdge::new(true).into()).into());
 
-        for value in node.body {
-            self.visit_stmt(value);
-        }
-        let true_final = self.node_stack.pop().unwrap();
+        self.visit_body(node.body);
+        let true_finals = std::mem::take(&mut self.node_stack);
 
         // println!("before orelse");
         self.print_debug_status();
         self.node_stack
             .push((ifelse_node, BranchEdge::new(false).into()).into());
-        for value in node.orelse {
-            self.visit_stmt(value);
-        }
-        let false_final = self.node_stack.pop().unwrap();
-
-        self.graph.add_edge(prev.node, ifelse_node, prev.edge_type);
-
-        self.node_stack.push(true_final);
-        self.node_stack.push(false_final);
+        self.visit_body(node.orelse);
+        let false_finals = std::mem::replace(&mut self.node_stack, true_finals);
+        self.node_stack.extend(false_finals);
 
         // println!("post ifelse");
         self.print_debug_status();
     }
     fn visit_stmt_while(&mut self, node: StmtWhile) {
-        let prev = self.node_stack.pop().unwrap();
+        if let Some(value) = node.orelse.first() {
+            self.unsupported("while-else", value.range());
+        }
         {
             let value = node.test;
             self.visit_expr(*value);
         }
-        let condition = self.expr_stack.pop().unwrap();
+        let condition = self.pop_expr();
         // println!("condition {:?}", condition);
         self.print_debug_status();
-        let while_node = tohdl_ir::graph::BranchNode { cond: condition };
-        let while_node = self.graph.add_node(while_node);
-        self.node_stack
-            .push((while_node, BranchEdge::new(true).into()).into());
+        self.visit_loop(condition, node.body, |_| {});
 
-        for value in node.body {
-            self.visit_stmt(value);
+        // println!("post while");
+        self.print_debug_status();
+    }
+    fn visit_stmt_for(&mut self, node: StmtFor) {
+        if let Some(value) = node.orelse.first() {
+            self.unsupported("for-else", value.range());
         }
-        let true_final = self.node_stack.pop().unwrap();
-
-        self.graph.add_edge(prev.node, while_node, prev.edge_type);
-
-        self.graph
-            .add_edge(true_final.node, while_node, true_final.edge_type);
+        let is_generator = matches!(
+            node.iter.as_ref(),
+            Expr::Call(ExprCall { func, .. })
+                if !matches!(func.as_ref(), Expr::Name(name) if name.id.as_str() == "range")
+        );
+        if is_generator {
+            return self.visit_for_generator(node);
+        }
+        let target = self.visit_target(*node.target);
+        let Some((start, stop, step)) = self.range_args(*node.iter) else {
+            return self.visit_unlowered_loop_body(node.body);
+        };
+        let step_range = step.range();
+        let depth = self.expr_stack.len();
+        self.visit_expr(start);
+        self.visit_expr(stop);
+        self.visit_expr(step);
+        let step = self.pop_expr();
+        let stop = self.pop_expr();
+        let start = self.pop_expr();
+        self.expr_stack.truncate(depth);
+        let Some(target) = target else {
+            return self.visit_unlowered_loop_body(node.body);
+        };
 
-        self.node_stack
-            .push((while_node, BranchEdge::new(false).into()).into());
+        // Python evaluates the range once, so its bounds are stored in hidden variables
+        // and the target is reassigned from a hidden iterator every iteration
+        let id = self.for_count;
+        self.for_count += 1;
+        let iter = VarExpr::new(&format!("__for{}_iter", id));
+        self.push_assign(iter.clone(), start);
+        let stop = match stop {
+            tohdl_ir::expr::Expr::Int(_) => stop,
+            _ => {
+                let var = VarExpr::new(&format!("__for{}_stop", id));
+                self.push_assign(var.clone(), stop);
+                tohdl_ir::expr::Expr::Var(var)
+            }
+        };
+        let step = match step {
+            tohdl_ir::expr::Expr::Int(_) => step,
+            _ => {
+                let var = VarExpr::new(&format!("__for{}_step", id));
+                self.push_assign(var.clone(), step);
+                tohdl_ir::expr::Expr::Var(var)
+            }
+        };
 
-        // println!("post while");
-        self.print_debug_status();
+        use tohdl_ir::expr::{BigInt, Expr as IrExpr, IntExpr, Operator};
+        let compare = |op| {
+            Box::new(IrExpr::BinOp(
+                Box::new(IrExpr::Var(iter.clone())),
+                op,
+                Box::new(stop.clone()),
+            ))
+        };
+        let condition = match &step {
+            IrExpr::Int(IntExpr { value, .. }) if *value == BigInt::default() => {
+                self.error(
+                    CompileErrorKind::UnsupportedSyntax,
+                    "range step must not be zero",
+                    step_range,
+                );
+                return self.visit_unlowered_loop_body(node.body);
+            }
+            IrExpr::Int(IntExpr { value, .. }) if *value > BigInt::default() => {
+                *compare(Operator::Lt)
+            }
+            IrExpr::Int(_) => *compare(Operator::Gt),
+            _ => {
+                // (step > 0 & iter < stop) | (step < 0 & iter > stop)
+                let sign = |op| {
+                    Box::new(IrExpr::BinOp(
+                        Box::new(step.clone()),
+                        op,
+                        Box::new(IrExpr::Int(IntExpr::new(0))),
+                    ))
+                };
+                IrExpr::BinOp(
+                    Box::new(IrExpr::BinOp(
+                        sign(Operator::Gt),
+                        Operator::BitAnd,
+                        compare(Operator::Lt),
+                    )),
+                    Operator::BitOr,
+                    Box::new(IrExpr::BinOp(
+                        sign(Operator::Lt),
+                        Operator::BitAnd,
+                        compare(Operator::Gt),
+                    )),
+                )
+            }
+        };
+        let increment = IrExpr::BinOp(
+            Box::new(IrExpr::Var(iter.clone())),
+            Operator::Add,
+            Box::new(step),
+        );
+        self.visit_loop(condition, node.body, |visitor| {
+            visitor.push_assign(target, IrExpr::Var(iter.clone()));
+            visitor.push_assign(iter, increment);
+        });
     }
     fn visit_expr_yield(&mut self, node: ExprYield) {
-        let mut prevs = self.node_stack.clone();
-        self.node_stack.clear();
-        if let Some(value) = node.value {
-            self.visit_expr(*value);
-        }
-        let expr = self.expr_stack.pop().unwrap();
-        let yield_node = tohdl_ir::graph::YieldNode { values: vec![expr] };
+        let values = match node.value {
+            Some(value) => self.visit_values(*value),
+            None => vec![],
+        };
+        let yield_node = tohdl_ir::graph::YieldNode { values };
         let yield_node = self.graph.add_node(yield_node);
-        while let Some(prev) = prevs.pop() {
-            self.graph.add_edge(prev.node, yield_node, prev.edge_type);
-        }
+        self.link_pending(yield_node);
         self.node_stack.push((yield_node, NoneEdge.into()).into());
     }
     fn visit_stmt_return(&mut self, node: StmtReturn) {
-        let prev = self.node_stack.pop().unwrap();
-        if let Some(value) = node.value {
-            self.visit_expr(*value);
-        }
-        let expr = self.expr_stack.pop().unwrap();
-        let yield_node = tohdl_ir::graph::ReturnNode { values: vec![expr] };
+        let values = match node.value {
+            Some(value) => self.visit_values(*value),
+            None => vec![],
+        };
+        let yield_node = tohdl_ir::graph::ReturnNode { values };
         let yield_node = self.graph.add_node(yield_node);
-        self.graph.add_edge(prev.node, yield_node, prev.edge_type);
-        self.node_stack.push((yield_node, NoneEdge.into()).into());
+        self.link_pending(yield_node);
+    }
+    fn visit_stmt_break(&mut self, node: StmtBreak) {
+        let pending = std::mem::take(&mut self.node_stack);
+        match self.loop_stack.last_mut() {
+            Some(entry) => entry.breaks.extend(pending),
+            None => self.error(
+                CompileErrorKind::UnsupportedSyntax,
+                "`break` outside of a loop",
+                node.range,
+            ),
+        }
+    }
+    fn visit_stmt_continue(&mut self, node: StmtContinue) {
+        match self.loop_stack.last() {
+            Some(entry) => {
+                let header = entry.header;
+                self.link_pending(header);
+            }
+            None => {
+                self.node_stack.clear();
+                self.error(
+                    CompileErrorKind::UnsupportedSyntax,
+                    "`continue` outside of a loop",
+                    node.range,
+                );
+            }
+        }
     }
 }
 
@@ -420,4 +1270,266 @@ def func(n):
         // println!("graph {}", graph.to_dot());
         // graph.write_dot("visitor.dot")
     }
+
+    #[test]
+    fn collects_errors() {
+        let python_source = r#"
+def func(n, *rest):
+    x = 1.5
+    y = n ** 2
+    a[0] = n
+    z = f(n, key=1)
+    return x
+"#;
+        let errors = match AstVisitor::from_text(python_source) {
+            Ok(_) => panic!("expected errors"),
+            Err(errors) => errors,
+        };
+
+        // for error in &errors {
+        //     println!("{}", error);
+        // }
+        assert_eq!(errors.len(), 5);
+        assert_eq!(errors[0].span.line, 2);
+        assert_eq!(errors[1].kind, CompileErrorKind::UnsupportedConstant);
+        assert_eq!(errors[1].span.line, 3);
+        assert_eq!(errors[2].kind, CompileErrorKind::UnsupportedOperator);
+        assert_eq!(errors[3].kind, CompileErrorKind::InvalidTarget);
+        assert_eq!(errors[3].span.column, 5);
+    }
+
+    #[test]
+    fn errors_in_rejected_loops() {
+        // Each loop is rejected, yet what is in and after it is still checked
+        let python_source = r#"
+def func(n):
+    for i in n:
+        x = 1.5
+    for i in range(1, n, 0):
+        y = n ** 2
+    for a[0] in range(n):
+        z = 2.5
+    return n ** 3
+"#;
+        let errors = AstVisitor::from_text(python_source).err().unwrap();
+        let kinds = errors.iter().map(|error| error.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                CompileErrorKind::UnsupportedSyntax,
+                CompileErrorKind::UnsupportedConstant,
+                CompileErrorKind::UnsupportedSyntax,
+                CompileErrorKind::UnsupportedOperator,
+                CompileErrorKind::InvalidTarget,
+                CompileErrorKind::UnsupportedConstant,
+                CompileErrorKind::UnsupportedOperator,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_error() {
+        let errors = AstVisitor::from_text("def func(n):\n    return (n\n")
+            .err()
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, CompileErrorKind::Parse);
+    }
+
+    #[test]
+    fn for_range() {
+        let python_source = r#"
+def func(n, step):
+    total = 0
+    for i in range(n):
+        total += i
+    for i in range(1, n, step):
+        if i > 10:
+            yield i
+    return total
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        // println!("graph {}", graph.to_dot());
+        let branches = graph
+            .nodes()
+            .filter(|idx| tohdl_ir::graph::BranchNode::downcastable(graph.get_node(*idx)))
+            .count();
+        assert_eq!(branches, 3);
+    }
+
+    #[test]
+    fn for_generator() {
+        let python_source = r#"
+def func(n):
+    for i in hrange(n):
+        yield i
+    yield from hrange(n)
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        // println!("graph {}", graph.to_dot());
+        let kinds = graph
+            .nodes()
+            .filter_map(|idx| tohdl_ir::graph::ExternalNode::concrete(graph.get_node(idx)))
+            .map(|node| node.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![ExternalKind::Iterate, ExternalKind::Call]);
+    }
+
+    #[test]
+    fn break_continue() {
+        let python_source = r#"
+def func(n):
+    i = 0
+    while i < n:
+        i += 1
+        if i == 3:
+            continue
+        if i == 5:
+            break
+            i = 10
+        yield i
+    return i
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        // println!("graph {}", graph.to_dot());
+        // Statement after break is dropped, loop header has the entry and two latches
+        assert_eq!(graph.nodes().count(), 8);
+        let header = graph
+            .nodes()
+            .find(|idx| tohdl_ir::graph::BranchNode::downcastable(graph.get_node(*idx)))
+            .unwrap();
+        assert_eq!(graph.preds(header).count(), 3);
+
+        let errors = AstVisitor::from_text("def func(n):\n    break\n")
+            .err()
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn tuples() {
+        let python_source = r#"
+def func(n):
+    a, b = 0, 1
+    while a < n:
+        yield a, b
+        a, b = b, a + b
+    c, d = split(a)
+    return c, d
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        // println!("graph {}", graph.to_dot());
+        let temps = graph
+            .nodes()
+            .filter_map(|idx| tohdl_ir::graph::AssignNode::concrete(graph.get_node(idx)))
+            .filter(|node| node.lvalue.name.starts_with("__tuple"))
+            .count();
+        assert_eq!(temps, 1);
+        let yields = graph
+            .nodes()
+            .filter_map(|idx| tohdl_ir::graph::YieldNode::concrete(graph.get_node(idx)))
+            .map(|node| node.values.len())
+            .collect::<Vec<_>>();
+        assert_eq!(yields, vec![2]);
+
+        let errors = AstVisitor::from_text("def func(n):\n    a, b = n, n, n\n")
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].kind, CompileErrorKind::InvalidTarget);
+    }
+
+    #[test]
+    fn bool_unary_ops() {
+        let python_source = r#"
+def func(a, b, c):
+    x = 0 < a <= b != c
+    y = not (a or b) and -c
+    z = ~+a
+    w = a if b > c else c - 1
+    return x, y, z, w
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        let rvalues = graph
+            .nodes()
+            .filter_map(|idx| tohdl_ir::graph::AssignNode::concrete(graph.get_node(idx)))
+            .map(|node| node.rvalue.to_string())
+            .collect::<Vec<_>>();
+        // println!("{:?}", rvalues);
+        assert_eq!(
+            rvalues,
+            vec![
+                "(((0 < a) && (a <= b)) && (b != c))",
+                "((!(a || b)) && (-c))",
+                "(~a)",
+                "((b > c) ? a : (c - 1))",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let python_source = r#"
+def func(a: u8, b: i12, c) -> Generator[tuple[u16, bool], None, None]:
+    d: u4 = a
+    e: i12
+    e = b + c
+    yield d, e
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        let types = graph
+            .get_inputs()
+            .map(|var| var.int_type().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec!["u8", "i12", "i32"]);
+        assert_eq!(
+            graph.outputs,
+            vec![IntType::new(16, false), IntType::new(1, false)]
+        );
+        let yields = graph
+            .nodes()
+            .filter_map(|idx| tohdl_ir::graph::YieldNode::concrete(graph.get_node(idx)))
+            .flat_map(|node| node.values.iter())
+            .flat_map(|value| value.get_vars_iter())
+            .map(|var| var.int_type().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(yields, vec!["u4", "i12"]);
+
+        let errors = AstVisitor::from_text("def func(a):\n    b = a\n    b: u8 = a\n")
+            .err()
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn big_constants() {
+        let python_source = r#"
+def func(n):
+    yield n & 0xFFFFFFFF, n ^ 0x1234567890ABCDEF, -5
+"#;
+        let visitor = AstVisitor::from_text(python_source).unwrap();
+        let graph = visitor.get_graph();
+
+        let yields = graph
+            .nodes()
+            .filter_map(|idx| tohdl_ir::graph::YieldNode::concrete(graph.get_node(idx)))
+            .flat_map(|node| node.values.iter())
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            yields,
+            vec!["(n & 4294967295)", "(n ^ 1311768467294899695)", "(-5)"]
+        );
+    }
 }
@@ -1,8 +1,11 @@
 use std::collections::BTreeMap;
 
+pub use num_bigint::BigInt;
+
+use serde::{Deserialize, Serialize};
 use typed_builder::TypedBuilder;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Operator {
     Add,
     Sub,
@@ -14,11 +17,16 @@ pub enum Operator {
     GtE,
     LtE,
     Eq,
+    NotEq,
     LShift,
     RShift,
     BitAnd,
     BitOr,
     BitXor,
+    /// Logical and, evaluates both sides and produces 0 or 1
+    And,
+    /// Logical or, evaluates both sides and produces 0 or 1
+    Or,
 }
 
 impl std::fmt::Display for Operator {
@@ -34,22 +42,43 @@ impl std::fmt::Display for Operator {
             Operator::LtE => write!(f, "<="),
             Operator::GtE => write!(f, ">="),
             Operator::Eq => write!(f, "=="),
+            Operator::NotEq => write!(f, "!="),
             Operator::LShift => write!(f, "<<"),
             Operator::RShift => write!(f, ">>"),
             Operator::BitAnd => write!(f, "&"),
             Operator::BitOr => write!(f, "|"),
             Operator::BitXor => write!(f, "^"),
+            Operator::And => write!(f, "&&"),
+            Operator::Or => write!(f, "||"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum UnaryOperator {
+    /// Logical not, produces 0 or 1
+    Not,
+    Neg,
+    Invert,
+}
+
+impl std::fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UnaryOperator::Not => write!(f, "!"),
+            UnaryOperator::Neg => write!(f, "-"),
+            UnaryOperator::Invert => write!(f, "~"),
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum VarType {
     Int,
     Pointer(Box<VarType>),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, TypedBuilder)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, TypedBuilder, Serialize, Deserialize)]
 pub struct VarExpr {
     #[builder(setter(into))]
     pub name: String,
@@ -59,6 +88,8 @@ pub struct VarExpr {
     pub size: usize,
     #[builder(default=VarType::Int)]
     pub type_: VarType,
+    #[builder(default = true)]
+    pub signed: bool,
 }
 
 impl VarExpr {
@@ -67,6 +98,53 @@ impl VarExpr {
             name: name.to_string(),
             size: 32,
             type_: VarType::Int,
+            signed: true,
+        }
+    }
+
+    /// Same variable type under a different name, used when renaming
+    pub fn with_name(&self, name: &str) -> Self {
+        VarExpr {
+            name: name.to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn int_type(&self) -> IntType {
+        IntType::new(self.size, self.signed)
+    }
+
+    pub fn set_int_type(&mut self, int_type: IntType) {
+        self.size = int_type.size;
+        self.signed = int_type.signed;
+    }
+}
+
+/// Width and signedness of an integer value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct IntType {
+    pub size: usize,
+    pub signed: bool,
+}
+
+impl IntType {
+    pub fn new(size: usize, signed: bool) -> Self {
+        IntType { size, signed }
+    }
+}
+
+impl Default for IntType {
+    fn default() -> Self {
+        IntType::new(32, true)
+    }
+}
+
+impl std::fmt::Display for IntType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.signed {
+            write!(f, "i{}", self.size)
+        } else {
+            write!(f, "u{}", self.size)
         }
     }
 }
@@ -77,14 +155,81 @@ impl std::fmt::Display for VarExpr {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Integer constant of an explicit width,
+/// `value` always fits in `size` bits of the given signedness
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct IntExpr {
-    pub value: i32,
+    #[serde(with = "decimal")]
+    pub value: BigInt,
+    pub size: usize,
+    pub signed: bool,
 }
 
 impl IntExpr {
-    pub fn new(value: i32) -> Self {
-        IntExpr { value }
+    /// Constant as written in Python, 32-bit signed if it fits,
+    /// otherwise as wide as needed and unsigned unless negative
+    pub fn new(value: impl Into<BigInt>) -> Self {
+        let value = value.into();
+        if i32::try_from(&value).is_ok() {
+            return IntExpr {
+                value,
+                size: 32,
+                signed: true,
+            };
+        }
+        let signed = value < BigInt::default();
+        let size = if signed {
+            // Magnitude of `-value - 1` plus a sign bit
+            (-&value - BigInt::from(1)).bits() as usize + 1
+        } else {
+            value.bits() as usize
+        };
+        IntExpr {
+            value,
+            size,
+            signed,
+        }
+    }
+
+    /// Constant of the given type, `value` wraps around like an assignment in hardware
+    pub fn sized(value: impl Into<BigInt>, size: usize, signed: bool) -> Self {
+        let modulus = BigInt::from(1) << size;
+        let mut value = value.into() % &modulus;
+        if value < BigInt::default() {
+            value += &modulus;
+        }
+        if signed && value.bits() as usize == size {
+            value -= &modulus;
+        }
+        IntExpr {
+            value,
+            size,
+            signed,
+        }
+    }
+
+    pub fn int_type(&self) -> IntType {
+        IntType::new(self.size, self.signed)
+    }
+
+    /// Value as a machine integer, `None` if it does not fit
+    pub fn to_i128(&self) -> Option<i128> {
+        i128::try_from(&self.value).ok()
+    }
+}
+
+/// Serializes constants as decimal strings, JSON numbers lose precision past 53 bits
+mod decimal {
+    use super::BigInt;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
     }
 }
 
@@ -94,11 +239,14 @@ impl std::fmt::Display for IntExpr {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Expr {
     Var(VarExpr),
     Int(IntExpr),
     BinOp(Box<Expr>, Operator, Box<Expr>),
+    UnaryOp(UnaryOperator, Box<Expr>),
+    /// `cond ? then : else`, both arms are evaluated
+    Mux(Box<Expr>, Box<Expr>, Box<Expr>),
 }
 
 impl Expr {
@@ -110,6 +258,12 @@ impl Expr {
             Expr::BinOp(left, _, right) => {
                 Box::new(left.get_vars_iter_mut().chain(right.get_vars_iter_mut()))
             }
+            Expr::UnaryOp(_, operand) => Box::new(operand.get_vars_iter_mut()),
+            Expr::Mux(cond, then, else_) => Box::new(
+                cond.get_vars_iter_mut()
+                    .chain(then.get_vars_iter_mut())
+                    .chain(else_.get_vars_iter_mut()),
+            ),
         };
         result
     }
@@ -122,6 +276,12 @@ impl Expr {
             Expr::BinOp(left, _, right) => {
                 Box::new(left.get_vars_iter().chain(right.get_vars_iter()))
             }
+            Expr::UnaryOp(_, operand) => Box::new(operand.get_vars_iter()),
+            Expr::Mux(cond, then, else_) => Box::new(
+                cond.get_vars_iter()
+                    .chain(then.get_vars_iter())
+                    .chain(else_.get_vars_iter()),
+            ),
         };
         result
     }
@@ -134,6 +294,12 @@ impl Expr {
             Expr::BinOp(left, _, right) => {
                 Box::new(left.get_exprs_iter().chain(right.get_exprs_iter()))
             }
+            Expr::UnaryOp(_, operand) => Box::new(operand.get_exprs_iter()),
+            Expr::Mux(cond, then, else_) => Box::new(
+                cond.get_exprs_iter()
+                    .chain(then.get_exprs_iter())
+                    .chain(else_.get_exprs_iter()),
+            ),
         };
         result
     }
@@ -161,6 +327,8 @@ impl std::fmt::Display for Expr {
             Expr::Var(e) => write!(f, "{}", e),
             Expr::Int(e) => write!(f, "{}", e),
             Expr::BinOp(left, op, right) => write!(f, "({} {} {})", left, op, right),
+            Expr::UnaryOp(op, operand) => write!(f, "({}{})", op, operand),
+            Expr::Mux(cond, then, else_) => write!(f, "({} ? {} : {})", cond, then, else_),
         }
     }
 }
@@ -231,4 +399,68 @@ mod tests {
 
         assert_eq!(expr.to_string(), "(10 + ((b + 10) + c))");
     }
+
+    #[test]
+    fn test_unary_op() {
+        // !(a != -b)
+        let mut expr = Expr::UnaryOp(
+            UnaryOperator::Not,
+            Box::new(Expr::BinOp(
+                Box::new(Expr::Var(VarExpr::new("a"))),
+                Operator::NotEq,
+                Box::new(Expr::UnaryOp(
+                    UnaryOperator::Neg,
+                    Box::new(Expr::Var(VarExpr::new("b"))),
+                )),
+            )),
+        );
+
+        assert_eq!(expr.to_string(), "(!(a != (-b)))");
+
+        let mapping: BTreeMap<VarExpr, Expr> =
+            vec![(VarExpr::new("b"), Expr::Int(IntExpr::new(3)))]
+                .into_iter()
+                .collect();
+        expr.backwards_replace(&mapping);
+
+        assert_eq!(expr.to_string(), "(!(a != (-3)))");
+        assert_eq!(expr.get_vars_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_mux() {
+        let mut expr = Expr::Mux(
+            Box::new(Expr::BinOp(
+                Box::new(Expr::Var(VarExpr::new("a"))),
+                Operator::Lt,
+                Box::new(Expr::Var(VarExpr::new("b"))),
+            )),
+            Box::new(Expr::Var(VarExpr::new("a"))),
+            Box::new(Expr::Var(VarExpr::new("c"))),
+        );
+
+        assert_eq!(expr.to_string(), "((a < b) ? a : c)");
+        assert_eq!(expr.get_vars_iter().count(), 4);
+
+        let mapping: BTreeMap<VarExpr, Expr> =
+            vec![(VarExpr::new("a"), Expr::Int(IntExpr::new(1)))]
+                .into_iter()
+                .collect();
+        expr.backwards_replace(&mapping);
+
+        assert_eq!(expr.to_string(), "((1 < b) ? 1 : c)");
+    }
+
+    #[test]
+    fn test_int_expr() {
+        assert_eq!(IntExpr::new(-5).int_type(), IntType::new(32, true));
+        assert_eq!(IntExpr::new(0xFFFF_FFFFu32).int_type(), IntType::new(32, false));
+        assert_eq!(IntExpr::new(1u64 << 40).int_type(), IntType::new(41, false));
+        assert_eq!(IntExpr::new(-(1i64 << 40)).int_type(), IntType::new(41, true));
+
+        assert_eq!(IntExpr::sized(-1, 8, false).value, BigInt::from(255));
+        assert_eq!(IntExpr::sized(200, 8, true).value, BigInt::from(-56));
+        assert_eq!(IntExpr::sized(-128, 8, true).value, BigInt::from(-128));
+        assert_eq!(IntExpr::sized(300, 8, false).to_string(), "44");
+    }
 }
@@ -1,7 +1,9 @@
 mod edge;
 mod cfg;
+mod eq;
 mod node;
 
 pub use edge::{BranchEdge, NoneEdge, Edge};
 pub use cfg::{CFG, NodeIndex};
+pub use eq::GraphDiff;
 pub use node::*;
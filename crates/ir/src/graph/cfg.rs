@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use petgraph::visit::{EdgeRef, IntoEdgeReferences};
 
-use crate::expr::VarExpr;
+use crate::expr::{IntType, VarExpr};
 
 use super::edge::{BranchEdge, Edge};
 use super::{FuncNode, Node};
@@ -45,6 +45,9 @@ pub struct CFG {
     pub name: String,
     pub graph: petgraph::stable_graph::StableDiGraph<Box<dyn Node>, Box<dyn Edge>>,
     pub entry: NodeIndex,
+    /// Types of yielded and returned values by position,
+    /// positions past the end are the default `IntType`
+    pub outputs: Vec<IntType>,
 }
 
 impl Default for CFG {
@@ -53,6 +56,7 @@ impl Default for CFG {
             name: Default::default(),
             graph: petgraph::stable_graph::StableDiGraph::default(),
             entry: 0.into(),
+            outputs: vec![],
         }
     }
 }
@@ -63,6 +67,7 @@ impl CFG {
             name: name.into(),
             graph: petgraph::stable_graph::StableDiGraph::default(),
             entry: 0.into(),
+            outputs: vec![],
         }
     }
     pub fn set_entry(&mut self, entry: NodeIndex) {
@@ -82,13 +87,8 @@ impl CFG {
         }
     }
 
-    /// False positives and negatives are certainly possible
-    pub fn graph_eq(a: &CFG, b: &CFG) -> bool {
-        let a_root: NodeIndex = 0.into();
-        let b_root: NodeIndex = 0.into();
-        let a_nodes = a.dfs(a_root);
-        let b_nodes = b.dfs(b_root);
-        a_nodes.eq(&b_nodes)
+    pub fn get_output_type(&self, position: usize) -> IntType {
+        self.outputs.get(position).copied().unwrap_or_default()
     }
 
     pub fn to_dot(&self) -> String {
@@ -315,6 +315,22 @@ impl CFG {
         *self.graph.node_weight_mut(idx.into()).unwrap() = Box::new(node);
     }
 
+    /// Adds nodes so each keeps its given index, gaps are filled with placeholder nodes
+    /// that are returned and should be removed once edges are added
+    pub(crate) fn add_nodes_at(&mut self, mut nodes: BTreeMap<usize, Box<dyn Node>>) -> Vec<NodeIndex> {
+        let count = nodes.keys().last().map_or(0, |last| last + 1);
+        let mut placeholders = vec![];
+        for idx in 0..count {
+            match nodes.remove(&idx) {
+                Some(node) => {
+                    self.add_node_boxed(node);
+                }
+                None => placeholders.push(self.add_node(FuncNode { params: vec![] })),
+            }
+        }
+        placeholders
+    }
+
     pub fn add_node_boxed(&mut self, node: Box<dyn Node>) -> NodeIndex {
         self.graph.add_node(node).index().into()
     }
@@ -1,6 +1,7 @@
 use super::Edge;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct BranchEdge {
     pub condition: bool,
 }
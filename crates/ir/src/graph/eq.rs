@@ -0,0 +1,440 @@
+//! Structural equivalence of graphs, see [CFG::graph_diff]
+
+use std::collections::{BTreeMap, VecDeque};
+
+use petgraph::visit::EdgeRef;
+
+use crate::expr::VarExpr;
+use crate::text::try_node_text;
+
+use super::{BranchEdge, Node, NodeIndex, NoneEdge, CFG};
+
+/// First mismatch found between two graphs, with the nodes being compared
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphDiff {
+    pub a: Option<NodeIndex>,
+    pub b: Option<NodeIndex>,
+    pub reason: String,
+}
+
+impl std::fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) => write!(f, "%{} and %{}: {}", a.0, b.0, self.reason),
+            _ => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl CFG {
+    /// Whether graphs have the same nodes and edges, up to node indices
+    pub fn graph_eq(a: &CFG, b: &CFG) -> bool {
+        CFG::graph_diff(a, b, false).is_none()
+    }
+
+    /// Compares graphs node by node starting from their entries,
+    /// node indices may differ but node payloads, edge labels and output types must match.
+    /// With `alpha`, variables may also be consistently renamed, keeping their types.
+    /// Successors under the same edge label are paired by their payloads,
+    /// trying every pairing of identical successors until one matches.
+    /// Nodes unreachable from the entries are only compared by payloads and edge labels
+    pub fn graph_diff(a: &CFG, b: &CFG, alpha: bool) -> Option<GraphDiff> {
+        Matcher {
+            a,
+            b,
+            alpha,
+            nodes: BTreeMap::new(),
+            nodes_rev: BTreeMap::new(),
+            vars: BTreeMap::new(),
+            vars_rev: BTreeMap::new(),
+        }
+        .run()
+        .err()
+    }
+}
+
+#[derive(Clone)]
+struct Matcher<'a> {
+    a: &'a CFG,
+    b: &'a CFG,
+    alpha: bool,
+    nodes: BTreeMap<NodeIndex, NodeIndex>,
+    nodes_rev: BTreeMap<NodeIndex, NodeIndex>,
+    vars: BTreeMap<VarExpr, VarExpr>,
+    vars_rev: BTreeMap<VarExpr, VarExpr>,
+}
+
+fn diff<T>(a: Option<NodeIndex>, b: Option<NodeIndex>, reason: String) -> Result<T, GraphDiff> {
+    Err(GraphDiff { a, b, reason })
+}
+
+/// Node text with every variable renamed to `_`, variables are compared separately
+fn shape(node: &dyn Node) -> String {
+    let mut node = dyn_clone::clone_box(node);
+    for var in node.declared_vars_mut() {
+        *var = var.with_name("_");
+    }
+    for var in node.referenced_vars_mut() {
+        *var = var.with_name("_");
+    }
+    try_node_text(node.as_ref()).unwrap_or_else(|| node.to_string())
+}
+
+/// Node text, or its shape when variables may be renamed
+fn text(node: &dyn Node, alpha: bool) -> String {
+    if alpha {
+        shape(node)
+    } else {
+        try_node_text(node).unwrap_or_else(|| node.to_string())
+    }
+}
+
+fn vars(node: &dyn Node) -> Vec<&VarExpr> {
+    let mut ret = node.declared_vars();
+    ret.extend(node.referenced_vars());
+    ret
+}
+
+fn succs(graph: &CFG, idx: NodeIndex) -> Vec<(String, String, NodeIndex)> {
+    let mut ret = graph
+        .graph
+        .edges_directed(idx.into(), petgraph::Direction::Outgoing)
+        .map(|edge| {
+            let weight = edge.weight();
+            let label = if let Some(edge) = weight.downcast_ref::<BranchEdge>() {
+                edge.condition.to_string()
+            } else if weight.downcast_ref::<NoneEdge>().is_some() {
+                String::new()
+            } else {
+                format!("{:?}", weight)
+            };
+            let succ: NodeIndex = edge.target().into();
+            (label, shape(graph.get_node(succ).as_ref()), succ)
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|x, y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+    ret
+}
+
+/// Ways to pair successors of matching nodes, sorted as by [succs].
+/// Successors with the same label and payload may be paired in any order, the others in sorted order
+fn pairings(
+    a_succs: &[(String, String, NodeIndex)],
+    b_succs: &[(String, String, NodeIndex)],
+) -> Vec<Vec<(NodeIndex, NodeIndex)>> {
+    let mut ret = vec![vec![]];
+    let mut start = 0;
+    while start < a_succs.len() {
+        let key = (&a_succs[start].0, &a_succs[start].1);
+        let end = start
+            + a_succs[start..]
+                .iter()
+                .take_while(|s| (&s.0, &s.1) == key)
+                .count();
+        let tied = b_succs[start..end].iter().all(|t| (&t.0, &t.1) == key);
+        let a_group = a_succs[start..end].iter().map(|s| s.2).collect::<Vec<_>>();
+        let b_group = b_succs[start..end].iter().map(|t| t.2).collect::<Vec<_>>();
+        let orders = if tied {
+            permutations(&b_group)
+        } else {
+            vec![b_group]
+        };
+        let a_group = &a_group;
+        ret = ret
+            .into_iter()
+            .flat_map(|prefix| {
+                orders.iter().map(move |order| {
+                    let mut pairing = prefix.clone();
+                    pairing.extend(a_group.iter().copied().zip(order.iter().copied()));
+                    pairing
+                })
+            })
+            .collect();
+        start = end;
+    }
+    ret
+}
+
+/// Every order of `items`, starting with the given one
+fn permutations(items: &[NodeIndex]) -> Vec<Vec<NodeIndex>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+    let mut ret = vec![];
+    for (i, first) in items.iter().enumerate() {
+        let mut rest = items.to_vec();
+        rest.remove(i);
+        for mut order in permutations(&rest) {
+            order.insert(0, *first);
+            ret.push(order);
+        }
+    }
+    ret
+}
+
+impl Matcher<'_> {
+    fn run(&mut self) -> Result<(), GraphDiff> {
+        if self.a.outputs != self.b.outputs {
+            return diff(
+                None,
+                None,
+                format!("outputs {:?} and {:?}", self.a.outputs, self.b.outputs),
+            );
+        }
+        let mut queue = VecDeque::new();
+        self.pair(self.a.entry, self.b.entry, &mut queue)?;
+        *self = self.clone().search(queue)?;
+        self.compare_unreachable()
+    }
+
+    /// Pairs the successors of queued pairs, backtracking over pairings of identical successors
+    fn search(mut self, mut queue: VecDeque<(NodeIndex, NodeIndex)>) -> Result<Self, GraphDiff> {
+        while let Some((x, y)) = queue.pop_front() {
+            self.compare(x, y)?;
+            let a_succs = succs(self.a, x);
+            let b_succs = succs(self.b, y);
+            let a_labels = a_succs.iter().map(|s| &s.0).collect::<Vec<_>>();
+            let b_labels = b_succs.iter().map(|s| &s.0).collect::<Vec<_>>();
+            if a_labels != b_labels {
+                return diff(
+                    Some(x),
+                    Some(y),
+                    format!("successor edges {:?} and {:?}", a_labels, b_labels),
+                );
+            }
+            let mut pairings = pairings(&a_succs, &b_succs);
+            if pairings.len() == 1 {
+                for (s, t) in pairings.pop().unwrap() {
+                    self.pair(s, t, &mut queue)?;
+                }
+                continue;
+            }
+            let mut first_diff = None;
+            for pairing in pairings {
+                let mut matcher = self.clone();
+                let mut queue = queue.clone();
+                let attempt = pairing
+                    .into_iter()
+                    .try_for_each(|(s, t)| matcher.pair(s, t, &mut queue))
+                    .and_then(|_| matcher.search(queue));
+                match attempt {
+                    Ok(matcher) => return Ok(matcher),
+                    Err(err) => {
+                        first_diff.get_or_insert(err);
+                    }
+                }
+            }
+            return Err(first_diff.unwrap());
+        }
+        Ok(self)
+    }
+
+    /// Compares the nodes that were not paired from the entries
+    fn compare_unreachable(&self) -> Result<(), GraphDiff> {
+        let unreachable = |graph: &CFG, paired: &BTreeMap<NodeIndex, NodeIndex>| {
+            let mut ret = graph
+                .nodes()
+                .filter(|idx| !paired.contains_key(idx))
+                .map(|idx| {
+                    let labels = succs(graph, idx)
+                        .into_iter()
+                        .map(|s| s.0)
+                        .collect::<Vec<_>>();
+                    (text(graph.get_node(idx).as_ref(), self.alpha), labels)
+                })
+                .collect::<Vec<_>>();
+            ret.sort();
+            ret
+        };
+        let (a_rest, b_rest) = (
+            unreachable(self.a, &self.nodes),
+            unreachable(self.b, &self.nodes_rev),
+        );
+        if a_rest != b_rest {
+            return diff(
+                None,
+                None,
+                format!(
+                    "unreachable nodes {:?} and {:?}",
+                    a_rest.iter().map(|s| &s.0).collect::<Vec<_>>(),
+                    b_rest.iter().map(|s| &s.0).collect::<Vec<_>>()
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    /// Records that `x` in a corresponds to `y` in b, queueing them if newly paired
+    fn pair(
+        &mut self,
+        x: NodeIndex,
+        y: NodeIndex,
+        queue: &mut VecDeque<(NodeIndex, NodeIndex)>,
+    ) -> Result<(), GraphDiff> {
+        match (self.nodes.get(&x), self.nodes_rev.get(&y)) {
+            (None, None) => {
+                self.nodes.insert(x, y);
+                self.nodes_rev.insert(y, x);
+                queue.push_back((x, y));
+                Ok(())
+            }
+            (Some(&other), _) if other != y => diff(
+                Some(x),
+                Some(y),
+                format!("%{} already matches %{}", x.0, other.0),
+            ),
+            (_, Some(&other)) if other != x => diff(
+                Some(x),
+                Some(y),
+                format!("%{} already matches %{}", other.0, y.0),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn compare(&mut self, x: NodeIndex, y: NodeIndex) -> Result<(), GraphDiff> {
+        let (a_node, b_node) = (self.a.get_node(x).as_ref(), self.b.get_node(y).as_ref());
+        if shape(a_node) != shape(b_node) {
+            return diff(Some(x), Some(y), format!("`{}` and `{}`", a_node, b_node));
+        }
+        for (u, v) in vars(a_node).into_iter().zip(vars(b_node)) {
+            if !self.alpha {
+                if u != v {
+                    return diff(Some(x), Some(y), format!("`{}` and `{}`", u, v));
+                }
+                continue;
+            }
+            match (self.vars.get(u), self.vars_rev.get(v)) {
+                (None, None) => {
+                    self.vars.insert(u.clone(), v.clone());
+                    self.vars_rev.insert(v.clone(), u.clone());
+                }
+                (Some(other), _) if other != v => {
+                    return diff(
+                        Some(x),
+                        Some(y),
+                        format!("`{}` is renamed to both `{}` and `{}`", u, other, v),
+                    )
+                }
+                (_, Some(other)) if other != u => {
+                    return diff(
+                        Some(x),
+                        Some(y),
+                        format!("`{}` and `{}` are both renamed to `{}`", other, u, v),
+                    )
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "cfg a
+entry %0
+
+%0: func(n)
+%1: i.0 = 0
+    -> %2
+
+%2: if (i.0 < n)
+    true -> %3
+    false -> %4
+
+%3: yield (i.0)
+    -> %2
+
+%4: return ()
+";
+
+    #[test]
+    fn renumbered() {
+        let b = "cfg b
+entry %4
+
+%4: func(n)
+%0: i.0 = 0
+    -> %3
+
+%3: if (i.0 < n)
+    false -> %1
+    true -> %2
+
+%2: yield (i.0)
+    -> %3
+
+%1: return ()
+";
+        let a = CFG::from_text(A).unwrap();
+        let b = CFG::from_text(b).unwrap();
+        assert!(CFG::graph_eq(&a, &b));
+        assert!(CFG::graph_eq(&b, &a));
+    }
+
+    #[test]
+    fn alpha() {
+        let a = CFG::from_text(A).unwrap();
+        let b = CFG::from_text(&A.replace("i.0", "x.7")).unwrap();
+        assert!(!CFG::graph_eq(&a, &b));
+        assert_eq!(CFG::graph_diff(&a, &b, true), None);
+
+        // Two variables cannot be renamed to one
+        let b = CFG::from_text(&A.replace("i.0 < n", "i.0 < i.0")).unwrap();
+        let diff = CFG::graph_diff(&a, &b, true).unwrap();
+        assert_eq!(diff.a, Some(NodeIndex(2)));
+        // println!("{}", diff);
+
+        // Types are kept when renaming
+        let b = CFG::from_text(&A.replace("i.0", "i.0:u8")).unwrap();
+        assert!(CFG::graph_diff(&a, &b, true).is_some());
+    }
+
+    #[test]
+    fn mismatch() {
+        let a = CFG::from_text(A).unwrap();
+        let b = CFG::from_text(&A.replace("true -> %3", "true -> %4")).unwrap();
+        let diff = CFG::graph_diff(&a, &b, false).unwrap();
+        assert_eq!((diff.a, diff.b), (Some(NodeIndex(3)), Some(NodeIndex(4))));
+
+        let b = CFG::from_text(&A.replace("yield (i.0)", "yield (n)")).unwrap();
+        let diff = CFG::graph_diff(&a, &b, false).unwrap();
+        assert_eq!(diff.a, Some(NodeIndex(3)));
+        assert_eq!(diff.reason, "`i.0` and `n`");
+    }
+
+    #[test]
+    fn identical_successors() {
+        let a = "cfg a
+entry %0
+
+%0: func(n)
+    -> %1
+    -> %2
+
+%1: yield (n)
+%3: return (n)
+
+%2: yield (n)
+%4: return (0)
+";
+        // Same payloads under the same label, paired the other way round
+        let b = a
+            .replace("%3: return (n)", "%3: return (0)")
+            .replace("%4: return (0)", "%4: return (n)");
+        let a = CFG::from_text(a).unwrap();
+        let b = CFG::from_text(&b).unwrap();
+        assert!(CFG::graph_eq(&a, &b));
+        assert!(CFG::graph_eq(&b, &a));
+
+        // Unreachable nodes with the same count but different payloads
+        let c = A.replace("%4: return ()", "%4: return ()\n\n%5: yield (n)");
+        let d = A.replace("%4: return ()", "%4: return ()\n\n%5: yield (i.0)");
+        let (c, d) = (CFG::from_text(&c).unwrap(), CFG::from_text(&d).unwrap());
+        assert!(!CFG::graph_eq(&c, &d));
+        assert!(CFG::graph_eq(&c, &c));
+    }
+}
@@ -1,8 +1,9 @@
 use crate::expr::*;
+use serde::{Deserialize, Serialize};
 
 use super::DataFlow;
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct AssignNode {
     pub lvalue: VarExpr,
     pub rvalue: Expr,
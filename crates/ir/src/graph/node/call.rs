@@ -1,8 +1,9 @@
 use crate::expr::VarExpr;
+use serde::{Deserialize, Serialize};
 
 use super::DataFlow;
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct CallNode {
     pub args: Vec<VarExpr>,
 }
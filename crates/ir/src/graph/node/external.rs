@@ -1,13 +1,31 @@
 use super::DataFlow;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq)]
+/// How the caller uses an external function
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExternalKind {
+    /// `x = f(...)`, a single successor receives the return value
+    #[default]
+    Call,
+    /// `for x in f(...)`, the true successor receives each yielded value,
+    /// the false successor is taken once the generator returns.
+    /// The only `CallNode` predecessor starts the generator,
+    /// every other predecessor resumes it
+    Iterate,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct ExternalNode {
     pub name: String,
+    pub kind: ExternalKind,
 }
 
 impl std::fmt::Display for ExternalNode {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "external({})", self.name)
+        match self.kind {
+            ExternalKind::Call => write!(f, "external({})", self.name),
+            ExternalKind::Iterate => write!(f, "iterate({})", self.name),
+        }
     }
 }
 
@@ -1,5 +1,6 @@
 use super::{DataFlow, Node};
 use crate::expr::*;
+use serde::{Deserialize, Serialize};
 
 pub trait MultiExpr {
     fn values(&self) -> &Vec<Expr>;
@@ -38,7 +39,7 @@ impl<T: MultiExpr + Clone> DataFlow for T {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReturnNode {
     pub values: Vec<Expr>,
 }
@@ -63,7 +64,7 @@ impl std::fmt::Display for ReturnNode {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct YieldNode {
     pub values: Vec<Expr>,
 }
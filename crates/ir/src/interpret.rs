@@ -0,0 +1,420 @@
+//! Reference interpreter for a [`CFG`], usable at any stage of the pass pipeline.
+//!
+//! Expressions are evaluated on unbounded integers with the hardware meaning of each operator,
+//! i.e. division truncates and the remainder takes the sign of the dividend.
+//! Values wrap to the variable's type when assigned, and to the output type when yielded or returned.
+
+use crate::expr::*;
+use crate::graph::*;
+
+/// Request to an external function, see [`Interpreter::with_external`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalEvent<'a> {
+    /// `x = name(args)`, answered with the returned values
+    Call {
+        node: NodeIndex,
+        name: &'a str,
+        args: &'a [BigInt],
+    },
+    /// `for x in name(args)` starts a new iteration, the answer is ignored
+    Start {
+        node: NodeIndex,
+        name: &'a str,
+        args: &'a [BigInt],
+    },
+    /// Next values of the iteration started at `node`, answered with `None` once it is done
+    Next { node: NodeIndex, name: &'a str },
+}
+
+/// Values produced by running a graph
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Execution {
+    pub yields: Vec<Vec<BigInt>>,
+    pub returned: Vec<BigInt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterpretError {
+    pub node: NodeIndex,
+    pub message: String,
+}
+
+impl std::fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}: {}", self.node.0, self.message)
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+type External<'a> = Box<dyn FnMut(ExternalEvent) -> Result<Option<Vec<BigInt>>, String> + 'a>;
+
+pub struct Interpreter<'a> {
+    graph: &'a CFG,
+    /// Steps before giving up, guards against graphs that never return
+    pub max_steps: usize,
+    external: External<'a>,
+}
+
+/// Runs graph without external functions
+pub fn interpret(graph: &CFG, inputs: &[BigInt]) -> Result<Execution, InterpretError> {
+    Interpreter::new(graph).run(inputs)
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(graph: &'a CFG) -> Self {
+        Self {
+            graph,
+            max_steps: 1_000_000,
+            external: Box::new(|event| match event {
+                ExternalEvent::Call { name, .. }
+                | ExternalEvent::Start { name, .. }
+                | ExternalEvent::Next { name, .. } => {
+                    Err(format!("no external function `{}`", name))
+                }
+            }),
+        }
+    }
+
+    /// Answers external function calls and iterations
+    pub fn with_external(
+        mut self,
+        external: impl FnMut(ExternalEvent) -> Result<Option<Vec<BigInt>>, String> + 'a,
+    ) -> Self {
+        self.external = Box::new(external);
+        self
+    }
+
+    pub fn run(&mut self, inputs: &[BigInt]) -> Result<Execution, InterpretError> {
+        let graph = self.graph;
+        let mut vars: std::collections::BTreeMap<&str, BigInt> = Default::default();
+        let mut execution = Execution::default();
+        // Values passed to the next func node, by a call node, an external function or the caller
+        let mut passed = Some(inputs.to_vec());
+        let mut current = graph.entry;
+        for _ in 0..self.max_steps {
+            let error = |message: String| InterpretError {
+                node: current,
+                message,
+            };
+            let node = graph.get_node(current);
+            let next = if let Some(FuncNode { params }) = FuncNode::concrete(node) {
+                if let Some(values) = passed.take() {
+                    if values.len() != params.len() {
+                        return Err(error(format!(
+                            "{} values passed to {} params",
+                            values.len(),
+                            params.len()
+                        )));
+                    }
+                    for (param, value) in params.iter().zip(values) {
+                        vars.insert(&param.name, wrap(value, param.int_type()));
+                    }
+                }
+                self.succ(current)?
+            } else if let Some(AssignNode { lvalue, rvalue }) = AssignNode::concrete(node) {
+                let value = eval(rvalue, &vars).map_err(error)?;
+                vars.insert(&lvalue.name, wrap(value, lvalue.int_type()));
+                self.succ(current)?
+            } else if let Some(BranchNode { cond }) = BranchNode::concrete(node) {
+                let value = eval(cond, &vars).map_err(error)?;
+                Some(self.branch_succ(current, value != BigInt::default())?)
+            } else if let Some(CallNode { args }) = CallNode::concrete(node) {
+                let values = args
+                    .iter()
+                    .map(|arg| vars.get(arg.name.as_str()).cloned())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| error(format!("undefined variable in {}", node)))?;
+                passed = Some(values);
+                self.succ(current)?
+            } else if let Some(YieldNode { values }) = YieldNode::concrete(node) {
+                execution
+                    .yields
+                    .push(self.outputs(values, &vars).map_err(error)?);
+                match self.succ(current)? {
+                    Some(next) => Some(next),
+                    None => return Ok(execution),
+                }
+            } else if let Some(ReturnNode { values }) = ReturnNode::concrete(node) {
+                execution.returned = self.outputs(values, &vars).map_err(error)?;
+                return Ok(execution);
+            } else if let Some(ExternalNode { name, kind }) = ExternalNode::concrete(node) {
+                let (values, next) = match kind {
+                    ExternalKind::Call => {
+                        let args = passed.take().unwrap_or_default();
+                        let values = (self.external)(ExternalEvent::Call {
+                            node: current,
+                            name,
+                            args: &args,
+                        })
+                        .map_err(error)?
+                        .unwrap_or_default();
+                        (values, self.succ(current)?)
+                    }
+                    ExternalKind::Iterate => {
+                        if let Some(args) = passed.take() {
+                            (self.external)(ExternalEvent::Start {
+                                node: current,
+                                name,
+                                args: &args,
+                            })
+                            .map_err(error)?;
+                        }
+                        let values = (self.external)(ExternalEvent::Next {
+                            node: current,
+                            name,
+                        })
+                        .map_err(error)?;
+                        let next = self.branch_succ(current, values.is_some())?;
+                        (values.unwrap_or_default(), Some(next))
+                    }
+                };
+                // A bare `return` returns None, which is zero here
+                passed = match next.map(|next| FuncNode::concrete(graph.get_node(next))) {
+                    Some(Some(FuncNode { params })) => {
+                        let mut values = values;
+                        values.resize(params.len(), BigInt::default());
+                        Some(values)
+                    }
+                    _ => None,
+                };
+                next
+            } else {
+                return Err(error(format!("cannot interpret {}", node)));
+            };
+            match next {
+                Some(next) => current = next,
+                // Falling off the end returns nothing
+                None => return Ok(execution),
+            }
+        }
+        Err(InterpretError {
+            node: current,
+            message: format!("did not finish within {} steps", self.max_steps),
+        })
+    }
+
+    fn succ(&self, idx: NodeIndex) -> Result<Option<NodeIndex>, InterpretError> {
+        let succs = self.graph.succs(idx).collect::<Vec<_>>();
+        match succs[..] {
+            [] => Ok(None),
+            [succ] => Ok(Some(succ)),
+            _ => Err(InterpretError {
+                node: idx,
+                message: format!("expected one successor, found {}", succs.len()),
+            }),
+        }
+    }
+
+    fn branch_succ(&self, idx: NodeIndex, condition: bool) -> Result<NodeIndex, InterpretError> {
+        self.graph
+            .succs(idx)
+            .find(|succ| {
+                self.graph
+                    .get_edge(idx, *succ)
+                    .and_then(|edge| edge.downcast_ref::<BranchEdge>())
+                    .is_some_and(|edge| edge.condition == condition)
+            })
+            .ok_or_else(|| InterpretError {
+                node: idx,
+                message: format!("no {} edge", condition),
+            })
+    }
+
+    fn outputs(
+        &self,
+        values: &[Expr],
+        vars: &std::collections::BTreeMap<&str, BigInt>,
+    ) -> Result<Vec<BigInt>, String> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Ok(wrap(eval(value, vars)?, self.graph.get_output_type(i))))
+            .collect()
+    }
+}
+
+fn wrap(value: BigInt, int_type: IntType) -> BigInt {
+    IntExpr::sized(value, int_type.size, int_type.signed).value
+}
+
+fn bool_value(value: bool) -> BigInt {
+    BigInt::from(value as i32)
+}
+
+fn shift_amount(value: BigInt) -> Result<usize, String> {
+    usize::try_from(&value)
+        .ok()
+        .filter(|amount| *amount <= 1 << 16)
+        .ok_or_else(|| format!("shift by {}", value))
+}
+
+/// Evaluates `expr` with variables looked up by name, with the operator semantics above
+pub fn eval(
+    expr: &Expr,
+    vars: &std::collections::BTreeMap<&str, BigInt>,
+) -> Result<BigInt, String> {
+    let zero = BigInt::default();
+    match expr {
+        Expr::Var(var) => vars
+            .get(var.name.as_str())
+            .cloned()
+            .ok_or_else(|| format!("undefined variable `{}`", var.name)),
+        Expr::Int(int) => Ok(int.value.clone()),
+        Expr::BinOp(left, op, right) => {
+            let (left, right) = (eval(left, vars)?, eval(right, vars)?);
+            Ok(match op {
+                Operator::Add => left + right,
+                Operator::Sub => left - right,
+                Operator::Mul => left * right,
+                Operator::Div | Operator::Mod if right == zero => {
+                    return Err("division by zero".to_string())
+                }
+                Operator::Div => left / right,
+                Operator::Mod => left % right,
+                Operator::Lt => bool_value(left < right),
+                Operator::Gt => bool_value(left > right),
+                Operator::LtE => bool_value(left <= right),
+                Operator::GtE => bool_value(left >= right),
+                Operator::Eq => bool_value(left == right),
+                Operator::NotEq => bool_value(left != right),
+                Operator::LShift => left << shift_amount(right)?,
+                Operator::RShift => left >> shift_amount(right)?,
+                Operator::BitAnd => left & right,
+                Operator::BitOr => left | right,
+                Operator::BitXor => left ^ right,
+                Operator::And => bool_value(left != zero && right != zero),
+                Operator::Or => bool_value(left != zero || right != zero),
+            })
+        }
+        Expr::UnaryOp(op, operand) => {
+            let value = eval(operand, vars)?;
+            Ok(match op {
+                UnaryOperator::Not => bool_value(value == zero),
+                UnaryOperator::Neg => -value,
+                UnaryOperator::Invert => !value,
+            })
+        }
+        Expr::Mux(cond, then, else_) => {
+            if eval(cond, vars)? != zero {
+                eval(then, vars)
+            } else {
+                eval(else_, vars)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(values: &[i64]) -> Vec<BigInt> {
+        values.iter().map(|v| BigInt::from(*v)).collect()
+    }
+
+    const RANGE: &str = "cfg range
+entry %0
+
+%0: func(n)
+%1: i = 0
+    -> %2
+
+%2: if (i < n)
+    true -> %3
+    false -> %4
+
+%3: yield (i, (-7 / 2), (-7 % 2))
+%5: i = (i + 1)
+    -> %2
+
+%4: return (i)
+";
+
+    #[test]
+    fn range() {
+        let graph = CFG::from_text(RANGE).unwrap();
+        let execution = interpret(&graph, &ints(&[3])).unwrap();
+        assert_eq!(
+            execution.yields,
+            vec![ints(&[0, -3, -1]), ints(&[1, -3, -1]), ints(&[2, -3, -1])]
+        );
+        assert_eq!(execution.returned, ints(&[3]));
+
+        let error = interpret(&graph, &ints(&[])).err().unwrap();
+        assert_eq!(error.node, NodeIndex(0));
+
+        let mut interpreter = Interpreter::new(&graph);
+        interpreter.max_steps = 10;
+        assert!(interpreter.run(&ints(&[100])).is_err());
+    }
+
+    #[test]
+    fn phi() {
+        // Call node arguments are all read before the func node's params are written
+        let text = "cfg swap
+entry %0
+outputs u4, i32
+
+%0: func(a, b)
+%1: call(b, a)
+%2: func(a, b)
+%3: return ((a + 20), b)
+";
+        let graph = CFG::from_text(text).unwrap();
+        let execution = interpret(&graph, &ints(&[1, 2])).unwrap();
+        assert_eq!(execution.returned, ints(&[6, 1]));
+    }
+
+    #[test]
+    fn external() {
+        let text = "cfg outer
+entry %0
+
+%0: func(n)
+%1: call(n)
+%2: external(double)
+%3: func(m)
+%4: call(m)
+    -> %5
+
+%5: iterate(count)
+    true -> %6
+    false -> %7
+
+%6: func(x:u2)
+%8: yield (x:u2)
+    -> %5
+
+%7: return (m)
+";
+        let graph = CFG::from_text(text).unwrap();
+        let mut remaining = 0;
+        let mut events = vec![];
+        let execution = Interpreter::new(&graph)
+            .with_external(|event| {
+                events.push(format!("{:?}", event));
+                Ok(match event {
+                    ExternalEvent::Call { args, .. } => Some(vec![&args[0] * 2]),
+                    ExternalEvent::Start { args, .. } => {
+                        remaining = i64::try_from(&args[0]).unwrap();
+                        None
+                    }
+                    ExternalEvent::Next { .. } if remaining > 0 => {
+                        remaining -= 1;
+                        Some(ints(&[remaining]))
+                    }
+                    ExternalEvent::Next { .. } => None,
+                })
+            })
+            .run(&ints(&[2]))
+            .unwrap();
+        // Yielded values wrap to the two bits of `x`
+        assert_eq!(
+            execution.yields,
+            vec![ints(&[3]), ints(&[2]), ints(&[1]), ints(&[0])]
+        );
+        assert_eq!(execution.returned, ints(&[4]));
+        // println!("{:?}", events);
+    }
+}
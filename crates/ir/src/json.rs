@@ -0,0 +1,312 @@
+//! JSON form of a [`CFG`], for tools that inspect the IR between passes.
+//!
+//! Nodes and edges are tagged with a `kind` looked up in a [`Registry`],
+//! crates that define their own nodes register them before dumping or loading.
+//! Node indices are kept, and each node's outgoing edges are listed in successor order.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+
+use petgraph::visit::EdgeRef;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::expr::IntType;
+use crate::graph::*;
+
+/// Bumped whenever the document layout or a registered payload changes incompatibly
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum JsonError {
+    Json(serde_json::Error),
+    /// Document was written with a different [`SCHEMA_VERSION`]
+    Version(u32),
+    /// Node or edge type that is not in the registry, by its display text when dumping
+    /// and by its kind when loading
+    Unregistered(String),
+    DuplicateNode(usize),
+    MissingNode(usize),
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Json(e) => write!(f, "{}", e),
+            JsonError::Version(version) => write!(
+                f,
+                "schema version {} is not supported, expected {}",
+                version, SCHEMA_VERSION
+            ),
+            JsonError::Unregistered(kind) => write!(f, "unregistered node or edge `{}`", kind),
+            JsonError::DuplicateNode(idx) => write!(f, "duplicate node {}", idx),
+            JsonError::MissingNode(idx) => write!(f, "node {} does not exist", idx),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> Self {
+        JsonError::Json(e)
+    }
+}
+
+type Encode = fn(&dyn Any) -> Option<serde_json::Result<Value>>;
+
+struct Entry<T: ?Sized> {
+    kind: &'static str,
+    encode: Encode,
+    decode: fn(Value) -> serde_json::Result<Box<T>>,
+}
+
+/// Maps node and edge types to the `kind` tags they are stored under.
+/// The default registry knows every node and edge in this crate
+pub struct Registry {
+    nodes: Vec<Entry<dyn Node>>,
+    edges: Vec<Entry<dyn Edge>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register_node::<AssignNode>("assign");
+        registry.register_node::<BranchNode>("branch");
+        registry.register_node::<CallNode>("call");
+        registry.register_node::<FuncNode>("func");
+        registry.register_node::<ReturnNode>("return");
+        registry.register_node::<YieldNode>("yield");
+        registry.register_node::<ExternalNode>("external");
+        registry.register_edge::<NoneEdge>("none");
+        registry.register_edge::<BranchEdge>("branch");
+        registry
+    }
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Self {
+            nodes: vec![],
+            edges: vec![],
+        }
+    }
+
+    pub fn register_node<T: Node + Serialize + DeserializeOwned>(&mut self, kind: &'static str) {
+        assert!(
+            self.nodes.iter().all(|entry| entry.kind != kind),
+            "node kind `{}` is already registered",
+            kind
+        );
+        self.nodes.push(Entry {
+            kind,
+            encode: |node| node.downcast_ref::<T>().map(serde_json::to_value),
+            decode: |value| Ok(Box::new(serde_json::from_value::<T>(value)?)),
+        });
+    }
+
+    pub fn register_edge<T: Edge + Serialize + DeserializeOwned>(&mut self, kind: &'static str) {
+        assert!(
+            self.edges.iter().all(|entry| entry.kind != kind),
+            "edge kind `{}` is already registered",
+            kind
+        );
+        self.edges.push(Entry {
+            kind,
+            encode: |edge| edge.downcast_ref::<T>().map(serde_json::to_value),
+            decode: |value| Ok(Box::new(serde_json::from_value::<T>(value)?)),
+        });
+    }
+
+    fn encode<T: ?Sized>(
+        entries: &[Entry<T>],
+        value: &dyn Any,
+    ) -> Option<Result<(&'static str, Value), JsonError>> {
+        entries
+            .iter()
+            .find_map(|entry| (entry.encode)(value).map(|data| Ok((entry.kind, data?))))
+    }
+
+    fn decode<T: ?Sized>(
+        entries: &[Entry<T>],
+        kind: &str,
+        data: Value,
+    ) -> Result<Box<T>, JsonError> {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.kind == kind)
+            .ok_or_else(|| JsonError::Unregistered(kind.to_string()))?;
+        Ok((entry.decode)(data)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    name: String,
+    entry: usize,
+    outputs: Vec<IntType>,
+    nodes: Vec<NodeEntry>,
+    edges: Vec<EdgeEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeEntry {
+    index: usize,
+    kind: String,
+    data: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EdgeEntry {
+    from: usize,
+    to: usize,
+    kind: String,
+    data: Value,
+}
+
+/// Only the version, so documents from other versions are reported as such
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl CFG {
+    pub fn to_json(&self, registry: &Registry) -> Result<String, JsonError> {
+        to_json(self, registry)
+    }
+
+    pub fn from_json(text: &str, registry: &Registry) -> Result<CFG, JsonError> {
+        from_json(text, registry)
+    }
+}
+
+pub fn to_json(graph: &CFG, registry: &Registry) -> Result<String, JsonError> {
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    for idx in graph.nodes() {
+        let node = graph.get_node(idx);
+        let (kind, data) = Registry::encode(&registry.nodes, node.as_any())
+            .ok_or_else(|| JsonError::Unregistered(node.to_string()))??;
+        nodes.push(NodeEntry {
+            index: idx.0,
+            kind: kind.to_string(),
+            data,
+        });
+        for edge in graph
+            .graph
+            .edges_directed(idx.into(), petgraph::Direction::Outgoing)
+        {
+            let (kind, data) = Registry::encode(&registry.edges, edge.weight().as_ref().as_any())
+                .ok_or_else(|| JsonError::Unregistered(format!("{:?}", edge.weight())))??;
+            edges.push(EdgeEntry {
+                from: idx.0,
+                to: edge.target().index(),
+                kind: kind.to_string(),
+                data,
+            });
+        }
+    }
+    let document = Document {
+        version: SCHEMA_VERSION,
+        name: graph.name.clone(),
+        entry: graph.entry.0,
+        outputs: graph.outputs.clone(),
+        nodes,
+        edges,
+    };
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+pub fn from_json(text: &str, registry: &Registry) -> Result<CFG, JsonError> {
+    let header: Header = serde_json::from_str(text)?;
+    if header.version != SCHEMA_VERSION {
+        return Err(JsonError::Version(header.version));
+    }
+    let document: Document = serde_json::from_str(text)?;
+
+    let mut nodes = BTreeMap::new();
+    for entry in document.nodes {
+        let node = Registry::decode(&registry.nodes, &entry.kind, entry.data)?;
+        if nodes.insert(entry.index, node).is_some() {
+            return Err(JsonError::DuplicateNode(entry.index));
+        }
+    }
+    if !nodes.contains_key(&document.entry) {
+        return Err(JsonError::MissingNode(document.entry));
+    }
+
+    let mut graph = CFG::new(document.name);
+    graph.outputs = document.outputs;
+    graph.set_entry(document.entry.into());
+    let placeholders = graph.add_nodes_at(nodes);
+    let exists = |graph: &CFG, idx: usize| {
+        graph.graph.node_weight(NodeIndex(idx).into()).is_some()
+            && !placeholders.contains(&idx.into())
+    };
+    // Edges added later come first among a node's successors
+    for entry in document.edges.into_iter().rev() {
+        for idx in [entry.from, entry.to] {
+            if !exists(&graph, idx) {
+                return Err(JsonError::MissingNode(idx));
+            }
+        }
+        let edge = Registry::decode(&registry.edges, &entry.kind, entry.data)?;
+        graph.add_edge(entry.from.into(), entry.to.into(), edge);
+    }
+    for idx in placeholders {
+        graph.rmv_node(idx);
+    }
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "cfg even
+entry %0
+outputs u8
+
+%0: func(n, mem_0:*u4)
+%1: i:u8 = 18446744073709551616:u65
+    -> %2
+
+%2: if ((i:u8 < n) ? (!n) : (-1))
+    true -> %3
+    false -> %4
+
+%3: yield (i:u8)
+%5: external(f)
+%6: iterate(g)
+    true -> %2
+    false -> %2
+
+%4: return ()
+";
+        let graph = CFG::from_text(text).unwrap();
+        let registry = Registry::default();
+        let json = graph.to_json(&registry).unwrap();
+        // println!("{}", json);
+        let graph = CFG::from_json(&json, &registry).unwrap();
+        assert_eq!(graph.to_text(), text);
+        assert_eq!(graph.to_json(&registry).unwrap(), json);
+    }
+
+    #[test]
+    fn errors() {
+        let graph = CFG::from_text("cfg a\nentry %0\n\n%0: func()\n").unwrap();
+        let json = graph.to_json(&Registry::default()).unwrap();
+        assert!(matches!(
+            CFG::from_json(&json, &Registry::empty()),
+            Err(JsonError::Unregistered(kind)) if kind == "func"
+        ));
+        let json = json.replace("\"version\": 1", "\"version\": 0");
+        assert!(matches!(
+            CFG::from_json(&json, &Registry::default()),
+            Err(JsonError::Version(0))
+        ));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod expr;
 pub mod graph;
+pub mod interpret;
+pub mod json;
+pub mod text;
@@ -0,0 +1,819 @@
+//! Textual form of a [`CFG`], meant for tests and debugging.
+//!
+//! ```text
+//! cfg even
+//! entry %0
+//! outputs u8
+//!
+//! %0: func(n)
+//! %1: i:u8 = 0
+//!     -> %2
+//!
+//! %2: if (i:u8 < n)
+//!     true -> %3
+//!     false -> %4
+//!
+//! %3: yield (i:u8)
+//! %5: i:u8 = (i:u8 + 2:u8)
+//!     -> %2
+//!
+//! %4: return ()
+//! ```
+//!
+//! Nodes are written as `%index: node` and keep their index when parsed.
+//! Consecutive nodes of a block are joined by a [`NoneEdge`],
+//! the last node of a block lists its outgoing edges in successor order.
+//! Variables and constants are annotated with their type unless it is `i32`,
+//! pointers are written as `name:*` (or `name:*u8`).
+//! Lines starting with `#` are ignored.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use petgraph::visit::EdgeRef;
+
+use crate::expr::*;
+use crate::graph::*;
+
+/// Error from [`parse`], `line` is 1-indexed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl CFG {
+    pub fn to_text(&self) -> String {
+        print(self)
+    }
+
+    pub fn from_text(text: &str) -> Result<CFG, ParseError> {
+        parse(text)
+    }
+}
+
+/// Prints graph in the textual form, panics on nodes or edges defined outside of this crate
+pub fn print(graph: &CFG) -> String {
+    let mut out = match graph.name.as_str() {
+        "" => "cfg\n".to_string(),
+        name => format!("cfg {}\n", name),
+    };
+    out.push_str(&format!("entry %{}\n", graph.entry.0));
+    if !graph.outputs.is_empty() {
+        let outputs = graph
+            .outputs
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!("outputs {}\n", outputs));
+    }
+
+    let continues = continuations(graph);
+    let mut printed = BTreeSet::new();
+    let heads = graph
+        .nodes()
+        .filter(|idx| !continues.values().any(|succ| succ == idx))
+        .collect::<Vec<_>>();
+    // Nodes on a cycle of continuations have no head, they start a block wherever it is broken
+    for idx in heads.into_iter().chain(graph.nodes()) {
+        if printed.contains(&idx) {
+            continue;
+        }
+        out.push('\n');
+        let mut current = idx;
+        loop {
+            printed.insert(current);
+            out.push_str(&format!(
+                "%{}: {}\n",
+                current.0,
+                node_text(graph.get_node(current).as_ref())
+            ));
+            match continues.get(&current) {
+                Some(next) if !printed.contains(next) => current = *next,
+                _ => break,
+            }
+        }
+        if continues.contains_key(&current) {
+            // Block was cut short by a cycle, the fall through edge is written out
+            out.push_str(&format!("    -> %{}\n", continues[&current].0));
+            continue;
+        }
+        for edge in graph
+            .graph
+            .edges_directed(current.into(), petgraph::Direction::Outgoing)
+        {
+            out.push_str(&format!(
+                "    {}-> %{}\n",
+                edge_label(edge.weight().as_ref()),
+                edge.target().index()
+            ));
+        }
+    }
+    out
+}
+
+/// Maps each node to the next node in its block,
+/// i.e. its only successor through a `NoneEdge` when that successor has no other predecessor
+fn continuations(graph: &CFG) -> BTreeMap<NodeIndex, NodeIndex> {
+    let mut ret = BTreeMap::new();
+    for idx in graph.nodes() {
+        let mut edges = graph
+            .graph
+            .edges_directed(idx.into(), petgraph::Direction::Outgoing);
+        let (Some(edge), None) = (edges.next(), edges.next()) else {
+            continue;
+        };
+        let succ: NodeIndex = edge.target().into();
+        if succ == idx
+            || succ == graph.entry
+            || edge.weight().downcast_ref::<NoneEdge>().is_none()
+            || graph.preds(succ).count() != 1
+        {
+            continue;
+        }
+        ret.insert(idx, succ);
+    }
+    ret
+}
+
+fn edge_label(edge: &dyn Edge) -> String {
+    if let Some(edge) = edge.downcast_ref::<BranchEdge>() {
+        format!("{} ", edge.condition)
+    } else if edge.downcast_ref::<NoneEdge>().is_some() {
+        String::new()
+    } else {
+        panic!("Unexpected edge {:?}", edge)
+    }
+}
+
+fn node_text(node: &dyn Node) -> String {
+    try_node_text(node).unwrap_or_else(|| panic!("Unexpected node {}", node))
+}
+
+/// Text of a node defined in this crate
+pub(crate) fn try_node_text(node: &dyn Node) -> Option<String> {
+    let node = node.as_any();
+    let text = if let Some(node) = node.downcast_ref::<AssignNode>() {
+        format!("{} = {}", var_text(&node.lvalue), expr_text(&node.rvalue))
+    } else if let Some(node) = node.downcast_ref::<BranchNode>() {
+        format!("if {}", expr_text(&node.cond))
+    } else if let Some(node) = node.downcast_ref::<FuncNode>() {
+        format!("func({})", vars_text(&node.params))
+    } else if let Some(node) = node.downcast_ref::<CallNode>() {
+        format!("call({})", vars_text(&node.args))
+    } else if let Some(node) = node.downcast_ref::<ReturnNode>() {
+        format!("return ({})", exprs_text(&node.values))
+    } else if let Some(node) = node.downcast_ref::<YieldNode>() {
+        format!("yield ({})", exprs_text(&node.values))
+    } else if let Some(node) = node.downcast_ref::<ExternalNode>() {
+        node.to_string()
+    } else {
+        return None;
+    };
+    Some(text)
+}
+
+fn vars_text(vars: &[VarExpr]) -> String {
+    vars.iter().map(var_text).collect::<Vec<_>>().join(", ")
+}
+
+fn exprs_text(exprs: &[Expr]) -> String {
+    exprs.iter().map(expr_text).collect::<Vec<_>>().join(", ")
+}
+
+fn var_text(var: &VarExpr) -> String {
+    let mut depth = 0;
+    let mut type_ = &var.type_;
+    while let VarType::Pointer(inner) = type_ {
+        depth += 1;
+        type_ = inner;
+    }
+    let int_type = var.int_type();
+    if depth == 0 && int_type == IntType::default() {
+        return var.name.clone();
+    }
+    let mut ret = format!("{}:{}", var.name, "*".repeat(depth));
+    if int_type != IntType::default() {
+        ret.push_str(&int_type.to_string());
+    }
+    ret
+}
+
+fn int_text(int: &IntExpr) -> String {
+    if int.int_type() == IntType::default() {
+        int.value.to_string()
+    } else {
+        format!("{}:{}", int.value, int.int_type())
+    }
+}
+
+fn expr_text(expr: &Expr) -> String {
+    match expr {
+        Expr::Var(var) => var_text(var),
+        Expr::Int(int) => int_text(int),
+        Expr::BinOp(left, op, right) => {
+            format!("({} {} {})", expr_text(left), op, expr_text(right))
+        }
+        Expr::UnaryOp(op, operand) => format!("({}{})", op, expr_text(operand)),
+        Expr::Mux(cond, then, else_) => format!(
+            "({} ? {} : {})",
+            expr_text(cond),
+            expr_text(then),
+            expr_text(else_)
+        ),
+    }
+}
+
+/// Parses the textual form, `print(&parse(text)?)` reproduces text written by [`print`]
+pub fn parse(text: &str) -> Result<CFG, ParseError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.starts_with('#'))
+        .peekable();
+    while let Some((_, "")) = lines.peek() {
+        lines.next();
+    }
+
+    let (line_no, line) = lines.next().unwrap_or((1, ""));
+    let name = match line.strip_prefix("cfg") {
+        Some(name) if name.is_empty() || name.starts_with(' ') => name.trim().to_string(),
+        _ => return Err(ParseError::new(line_no, "expected `cfg <name>`")),
+    };
+    let (line_no, line) = lines.next().unwrap_or((line_no + 1, ""));
+    let entry = line
+        .strip_prefix("entry ")
+        .and_then(|rest| Cursor::new(rest).node_index().ok())
+        .ok_or_else(|| ParseError::new(line_no, "expected `entry %<index>`"))?;
+    let mut outputs = vec![];
+    if let Some((line_no, line)) = lines.peek().copied() {
+        if let Some(rest) = line.strip_prefix("outputs ") {
+            lines.next();
+            for output in rest.split(',') {
+                outputs.push(
+                    int_type(output.trim())
+                        .ok_or_else(|| ParseError::new(line_no, "expected integer type"))?,
+                );
+            }
+        }
+    }
+
+    let mut nodes: BTreeMap<usize, Box<dyn Node>> = BTreeMap::new();
+    let mut edges: Vec<(usize, usize, usize, Box<dyn Edge>)> = vec![];
+    // Last node of the current block and the explicit edges listed after it
+    let mut last: Option<usize> = None;
+    let mut block_edges: Vec<(usize, usize, usize, Box<dyn Edge>)> = vec![];
+    for (line_no, line) in lines {
+        if line.is_empty() {
+            edges.extend(block_edges.drain(..).rev());
+            last = None;
+        } else if line.starts_with('%') {
+            if !block_edges.is_empty() {
+                return Err(ParseError::new(
+                    line_no,
+                    "node after edges, blocks are separated by an empty line",
+                ));
+            }
+            let mut cursor = Cursor::new(line);
+            let idx = cursor.node_index().map_err(|e| e.at(line_no))?;
+            cursor.expect(":").map_err(|e| e.at(line_no))?;
+            let node = cursor.node().map_err(|e| e.at(line_no))?;
+            if nodes.insert(idx, node).is_some() {
+                return Err(ParseError::new(line_no, format!("duplicate node %{}", idx)));
+            }
+            if let Some(prev) = last {
+                edges.push((line_no, prev, idx, NoneEdge.into()));
+            }
+            last = Some(idx);
+        } else {
+            let Some(from) = last else {
+                return Err(ParseError::new(line_no, "edge outside of a block"));
+            };
+            let (edge, rest): (Box<dyn Edge>, _) = if let Some(rest) = line.strip_prefix("true ") {
+                (BranchEdge::new(true).into(), rest)
+            } else if let Some(rest) = line.strip_prefix("false ") {
+                (BranchEdge::new(false).into(), rest)
+            } else {
+                (NoneEdge.into(), line)
+            };
+            let mut cursor = Cursor::new(rest);
+            cursor.expect("->").map_err(|e| e.at(line_no))?;
+            let to = cursor.node_index().map_err(|e| e.at(line_no))?;
+            cursor.end().map_err(|e| e.at(line_no))?;
+            block_edges.push((line_no, from, to, edge));
+        }
+    }
+    edges.extend(block_edges.drain(..).rev());
+
+    let mut graph = CFG::new(name);
+    graph.outputs = outputs;
+    if !nodes.contains_key(&entry) {
+        return Err(ParseError::new(
+            line_no,
+            format!("entry %{} is not a node", entry),
+        ));
+    }
+    graph.set_entry(entry.into());
+
+    let count = nodes.keys().last().map_or(0, |last| last + 1);
+    let placeholders = graph.add_nodes_at(nodes);
+    for (line_no, from, to, edge) in edges {
+        if to >= count || placeholders.contains(&to.into()) {
+            return Err(ParseError::new(
+                line_no,
+                format!("edge to unknown node %{}", to),
+            ));
+        }
+        graph.add_edge(from.into(), to.into(), edge);
+    }
+    for idx in placeholders {
+        graph.rmv_node(idx);
+    }
+    Ok(graph)
+}
+
+fn int_type(text: &str) -> Option<IntType> {
+    let signed = match text.chars().next()? {
+        'i' => true,
+        'u' => false,
+        _ => return None,
+    };
+    let size = text[1..].parse::<usize>().ok()?;
+    if size == 0 || text[1..].starts_with('0') {
+        return None;
+    }
+    Some(IntType::new(size, signed))
+}
+
+/// Error without a line, filled in by [`parse`]
+struct CursorError(String);
+
+impl CursorError {
+    fn at(self, line: usize) -> ParseError {
+        ParseError::new(line, self.0)
+    }
+}
+
+type CursorResult<T> = Result<T, CursorError>;
+
+/// Reads a single line
+struct Cursor<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error<T>(&self, expected: &str) -> CursorResult<T> {
+        let found = match self.rest() {
+            "" => "end of line".to_string(),
+            rest => format!("`{}`", rest),
+        };
+        Err(CursorError(format!(
+            "expected {}, found {}",
+            expected, found
+        )))
+    }
+
+    /// Consumes `token` after optional whitespace
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> CursorResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(&format!("`{}`", token))
+        }
+    }
+
+    fn end(&mut self) -> CursorResult<()> {
+        self.skip_ws();
+        if self.rest().is_empty() {
+            Ok(())
+        } else {
+            self.error("end of line")
+        }
+    }
+
+    /// Consumes the longest prefix whose chars match `pred`
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn node_index(&mut self) -> CursorResult<usize> {
+        self.expect("%")?;
+        match self.take_while(|c| c.is_ascii_digit()).parse() {
+            Ok(idx) => Ok(idx),
+            Err(_) => self.error("node index"),
+        }
+    }
+
+    fn node(&mut self) -> CursorResult<Box<dyn Node>> {
+        self.skip_ws();
+        let node: Box<dyn Node> = if self.eat("func(") {
+            Box::new(FuncNode {
+                params: self.list(Self::var)?,
+            })
+        } else if self.eat("call(") {
+            Box::new(CallNode {
+                args: self.list(Self::var)?,
+            })
+        } else if self.eat("return (") {
+            Box::new(ReturnNode {
+                values: self.list(Self::expr)?,
+            })
+        } else if self.eat("yield (") {
+            Box::new(YieldNode {
+                values: self.list(Self::expr)?,
+            })
+        } else if self.rest().starts_with("external(") || self.rest().starts_with("iterate(") {
+            let kind = if self.eat("external(") {
+                ExternalKind::Call
+            } else {
+                self.expect("iterate(")?;
+                ExternalKind::Iterate
+            };
+            let Some(name) = self.rest().strip_suffix(')') else {
+                return self.error("`)`");
+            };
+            self.pos = self.text.len();
+            Box::new(ExternalNode {
+                name: name.to_string(),
+                kind,
+            })
+        } else if self.eat("if ") {
+            Box::new(BranchNode { cond: self.expr()? })
+        } else {
+            let lvalue = self.var()?;
+            self.expect("=")?;
+            Box::new(AssignNode {
+                lvalue,
+                rvalue: self.expr()?,
+            })
+        };
+        self.end()?;
+        Ok(node)
+    }
+
+    /// Comma separated items up to and including the closing parenthesis
+    fn list<T>(&mut self, item: fn(&mut Self) -> CursorResult<T>) -> CursorResult<Vec<T>> {
+        let mut ret = vec![];
+        if self.eat(")") {
+            return Ok(ret);
+        }
+        loop {
+            ret.push(item(self)?);
+            if self.eat(")") {
+                return Ok(ret);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn var(&mut self) -> CursorResult<VarExpr> {
+        self.skip_ws();
+        if !self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        {
+            return self.error("variable");
+        }
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        let mut var = VarExpr::new(name);
+        // Types are attached without whitespace, which tells them apart from `c ? a : b`
+        if self.rest().starts_with(':') {
+            self.pos += 1;
+            let mut depth = 0;
+            while self.rest().starts_with('*') {
+                self.pos += 1;
+                depth += 1;
+            }
+            let text = self.take_while(|c| c.is_ascii_alphanumeric());
+            if !text.is_empty() {
+                match int_type(text) {
+                    Some(int_type) => var.set_int_type(int_type),
+                    None => return Err(CursorError(format!("invalid type `{}`", text))),
+                }
+            } else if depth == 0 {
+                return self.error("type");
+            }
+            for _ in 0..depth {
+                var.type_ = VarType::Pointer(Box::new(var.type_));
+            }
+        }
+        Ok(var)
+    }
+
+    fn int(&mut self) -> CursorResult<IntExpr> {
+        self.skip_ws();
+        let start = self.pos;
+        if self.rest().starts_with('-') {
+            self.pos += 1;
+        }
+        if self.take_while(|c| c.is_ascii_digit()).is_empty() {
+            return self.error("integer");
+        }
+        let value: BigInt = self.text[start..self.pos].parse().unwrap();
+        let mut int_type = IntType::default();
+        if self.rest().starts_with(':') {
+            self.pos += 1;
+            let text = self.take_while(|c| c.is_ascii_alphanumeric());
+            int_type = match self::int_type(text) {
+                Some(int_type) => int_type,
+                None => return Err(CursorError(format!("invalid type `{}`", text))),
+            };
+        }
+        let int = IntExpr::sized(value.clone(), int_type.size, int_type.signed);
+        if int.value != value {
+            return Err(CursorError(format!(
+                "{} does not fit in {}",
+                value, int_type
+            )));
+        }
+        Ok(int)
+    }
+
+    fn expr(&mut self) -> CursorResult<Expr> {
+        self.skip_ws();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                self.paren()
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => Ok(Expr::Int(self.int()?)),
+            _ => Ok(Expr::Var(self.var()?)),
+        }
+    }
+
+    /// Rest of a parenthesized expression, parentheses never wrap a lone operand
+    fn paren(&mut self) -> CursorResult<Expr> {
+        self.skip_ws();
+        let left = match self.peek() {
+            Some('!') | Some('~') => {
+                let op = if self.eat("!") {
+                    UnaryOperator::Not
+                } else {
+                    self.expect("~")?;
+                    UnaryOperator::Invert
+                };
+                let operand = self.expr()?;
+                self.expect(")")?;
+                return Ok(Expr::UnaryOp(op, Box::new(operand)));
+            }
+            Some('-') if !self.rest()[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                self.pos += 1;
+                let operand = self.expr()?;
+                self.expect(")")?;
+                return Ok(Expr::UnaryOp(UnaryOperator::Neg, Box::new(operand)));
+            }
+            Some('-') => {
+                // `(-5:u8)` negates 5:u8, `(-5 + a)` adds the constant -5
+                let start = self.pos;
+                self.pos += 1;
+                if let Ok(magnitude) = self.int() {
+                    if self.eat(")") {
+                        return Ok(Expr::UnaryOp(
+                            UnaryOperator::Neg,
+                            Box::new(Expr::Int(magnitude)),
+                        ));
+                    }
+                }
+                self.pos = start;
+                Expr::Int(self.int()?)
+            }
+            _ => self.expr()?,
+        };
+        if self.eat("?") {
+            let then = self.expr()?;
+            self.expect(":")?;
+            let else_ = self.expr()?;
+            self.expect(")")?;
+            return Ok(Expr::Mux(Box::new(left), Box::new(then), Box::new(else_)));
+        }
+        let op = self.operator()?;
+        let right = self.expr()?;
+        self.expect(")")?;
+        Ok(Expr::BinOp(Box::new(left), op, Box::new(right)))
+    }
+
+    fn operator(&mut self) -> CursorResult<Operator> {
+        // Longer operators first so `<<` is not read as `<`
+        let operators = [
+            ("<<", Operator::LShift),
+            (">>", Operator::RShift),
+            ("<=", Operator::LtE),
+            (">=", Operator::GtE),
+            ("==", Operator::Eq),
+            ("!=", Operator::NotEq),
+            ("&&", Operator::And),
+            ("||", Operator::Or),
+            ("+", Operator::Add),
+            ("-", Operator::Sub),
+            ("*", Operator::Mul),
+            ("/", Operator::Div),
+            ("%", Operator::Mod),
+            ("<", Operator::Lt),
+            (">", Operator::Gt),
+            ("&", Operator::BitAnd),
+            ("|", Operator::BitOr),
+            ("^", Operator::BitXor),
+        ];
+        for (token, op) in operators {
+            if self.eat(token) {
+                return Ok(op);
+            }
+        }
+        self.error("operator")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVEN: &str = "cfg even
+entry %0
+outputs u8
+
+%0: func(n)
+%1: i:u8 = 0
+    -> %2
+
+%2: if (i:u8 < n)
+    true -> %3
+    false -> %4
+
+%3: yield (i:u8, (-1), (-1 * n))
+%5: i:u8 = (i:u8 + 2:u8)
+    -> %2
+
+%4: return ()
+";
+
+    #[test]
+    fn round_trip() {
+        let graph = parse(EVEN).unwrap();
+        assert_eq!(graph.name, "even");
+        assert_eq!(graph.outputs, vec![IntType::new(8, false)]);
+        assert_eq!(graph.nodes().count(), 6);
+        assert_eq!(print(&graph), EVEN);
+        // println!("{}", graph.to_dot());
+    }
+
+    #[test]
+    fn exprs() {
+        let text = "cfg
+entry %0
+
+%0: func(a:u4, mem_0:*, ptr:**i8)
+%1: x:i12 = (((!a:u4) ? (~-3:i8) : (-a:u4)) >> (b && (c != 4294967295:u32)))
+%2: external(range)
+    -> %3
+
+%3: iterate(gen)
+    true -> %4
+    false -> %4
+    -> %3
+
+%4: call()
+";
+        let graph = parse(text).unwrap();
+        assert_eq!(print(&graph), text);
+
+        let node = AssignNode::concrete(graph.get_node(1.into())).unwrap();
+        assert_eq!(node.lvalue.int_type(), IntType::new(12, true));
+        assert!(matches!(node.rvalue, Expr::BinOp(_, Operator::RShift, _)));
+        let func = FuncNode::concrete(graph.get_node(0.into())).unwrap();
+        assert_eq!(
+            func.params[2].type_,
+            VarType::Pointer(Box::new(VarType::Pointer(Box::new(VarType::Int))))
+        );
+    }
+
+    #[test]
+    fn negated_constants() {
+        let neg = |int: IntExpr| Expr::UnaryOp(UnaryOperator::Neg, Box::new(Expr::Int(int)));
+        let sum = |int: IntExpr| {
+            Expr::BinOp(
+                Box::new(Expr::Int(int)),
+                Operator::Add,
+                Box::new(Expr::Var(VarExpr::new("a"))),
+            )
+        };
+        for expr in [
+            neg(IntExpr::sized(5, 8, false)),
+            neg(IntExpr::new(0xFFFF_FFFFu32)),
+            neg(IntExpr::new(BigInt::from(1) << 100u32)),
+            neg(IntExpr::sized(-128, 8, true)),
+            sum(IntExpr::sized(-128, 8, true)),
+            sum(IntExpr::new(-(BigInt::from(1) << 100u32))),
+        ] {
+            let mut graph = CFG::new("negated");
+            graph.add_node(AssignNode {
+                lvalue: VarExpr::new("x"),
+                rvalue: expr.clone(),
+            });
+            let text = print(&graph);
+            // println!("{}", text);
+            let parsed = parse(&text).unwrap();
+            let node = AssignNode::concrete(parsed.get_node(0.into())).unwrap();
+            assert_eq!(node.rvalue, expr);
+        }
+        assert!(print(&parse("cfg\nentry %0\n\n%0: x = (-5:u8)\n").unwrap()).contains("(-5:u8)"));
+    }
+
+    #[test]
+    fn gaps() {
+        let text = "cfg gaps
+entry %3
+
+%3: func()
+%7: return (1)
+";
+        let graph = parse(text).unwrap();
+        assert_eq!(
+            graph.nodes().collect::<Vec<_>>(),
+            vec![NodeIndex(3), NodeIndex(7)]
+        );
+        assert_eq!(print(&graph), text);
+    }
+
+    #[test]
+    fn built_graph() {
+        let mut graph = CFG::new("built");
+        let func = graph.add_node(FuncNode {
+            params: vec![VarExpr::new("n")],
+        });
+        let ret = graph.add_node(ReturnNode {
+            values: vec![Expr::Int(IntExpr::sized(300, 9, false))],
+        });
+        graph.add_edge(func, ret, NoneEdge.into());
+        graph.add_edge(ret, ret, NoneEdge.into());
+
+        let text = graph.to_text();
+        assert_eq!(
+            text,
+            "cfg built\nentry %0\n\n%0: func(n)\n    -> %1\n\n%1: return (300:u9)\n    -> %1\n"
+        );
+        assert_eq!(CFG::from_text(&text).unwrap().to_text(), text);
+    }
+
+    #[test]
+    fn errors() {
+        let err = parse("cfg a\nentry %0\n\n%0: x = (a +)\n").err().unwrap();
+        assert_eq!(err.line, 4);
+        let err = parse("cfg a\nentry %0\n\n%0: x = 256:u8\n").err().unwrap();
+        assert_eq!(err.message, "256 does not fit in u8");
+        let err = parse("cfg a\nentry %0\n\n%0: func()\n    -> %1\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 5);
+        assert!(parse("cfg a\nentry %1\n\n%0: func()\n").is_err());
+        // println!("{}", err);
+    }
+}
@@ -1,14 +1,69 @@
+use std::collections::BTreeSet;
+
 use tohdl_ir::expr::Expr;
+use tohdl_ir::expr::IntExpr;
+use tohdl_ir::expr::Operator;
+use tohdl_ir::expr::VarExpr;
+use tohdl_ir::graph::AssignNode;
+use tohdl_ir::graph::BranchNode;
 use tohdl_ir::graph::CallNode;
 use tohdl_ir::graph::BranchEdge;
+use tohdl_ir::graph::ExternalKind;
+use tohdl_ir::graph::ExternalNode;
+use tohdl_ir::graph::FuncNode;
 use tohdl_ir::graph::Node;
 use tohdl_ir::graph::NodeIndex;
 use tohdl_ir::graph::NoneEdge;
 use tohdl_ir::graph::ReturnNode;
+use tohdl_ir::graph::YieldNode;
 use tohdl_ir::graph::CFG;
 
+use crate::transform::ExplicitReturn;
+use crate::BasicTransform;
+
 /// Inlines an external function
 pub fn inline_extern_func<'a>(extern_node: NodeIndex, caller: &mut CFG, callee: &CFG) {
+    let kind = ExternalNode::concrete(caller.get_node(extern_node))
+        .unwrap_or_else(|| panic!("Expected external node {}", caller.get_node(extern_node)))
+        .kind;
+
+    // Generators may fall off the end instead of returning
+    let mut callee = callee.clone();
+    ExplicitReturn::default().apply(&mut callee);
+
+    match kind {
+        ExternalKind::Call => {
+            // A bare `return` returns None, which is zero here
+            let params = caller
+                .succs(extern_node)
+                .filter_map(|succ| FuncNode::concrete(caller.get_node(succ)))
+                .map(|node| node.params.len())
+                .max()
+                .unwrap_or_default();
+            let name = callee.name.clone();
+            for idx in callee.nodes().collect::<Vec<_>>() {
+                if let Some(node) = ReturnNode::concrete_mut(callee.get_node_mut(idx)) {
+                    while node.values.len() < params {
+                        node.values.push(Expr::Int(IntExpr::new(0)));
+                    }
+                    assert_eq!(
+                        node.values.len(),
+                        params,
+                        "{} returns {} values but the caller expects {}",
+                        name,
+                        node.values.len(),
+                        params
+                    );
+                }
+            }
+            inline_call(extern_node, caller, &callee)
+        }
+        ExternalKind::Iterate => inline_iterate(extern_node, caller, &callee),
+    }
+}
+
+/// Inlines `x = f(...)`, callee returns pass their values to the caller's func node
+fn inline_call(extern_node: NodeIndex, caller: &mut CFG, callee: &CFG) {
     let old_to_new_idx = CFG::merge_graph(caller, callee);
 
     let callee_exits = CFG::find_exits(callee).collect::<Vec<_>>();
@@ -91,6 +146,185 @@ pub fn inline_extern_func<'a>(extern_node: NodeIndex, caller: &mut CFG, callee:
     }
 }
 
+/// Inlines `for x in f(...)`.
+///
+/// Each callee yield passes its values to the loop body's func node.
+/// Once the body finishes, execution resumes after the yield that started it,
+/// using a resume variable when the callee has more than one yield.
+/// Callee returns exit the loop.
+fn inline_iterate(extern_node: NodeIndex, caller: &mut CFG, callee: &CFG) {
+    let old_to_new_idx = CFG::merge_graph(caller, callee);
+    let callee_entry = *old_to_new_idx.get(&callee.entry).unwrap();
+    let callee_nodes = old_to_new_idx.values().cloned().collect::<Vec<_>>();
+
+    // The call node starts the generator, every other predecessor resumes it
+    let preds = caller
+        .preds(extern_node)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let (starts, resumes): (Vec<_>, Vec<_>) = preds
+        .into_iter()
+        .partition(|pred| CallNode::downcastable(caller.get_node(*pred)));
+    assert_eq!(
+        starts.len(),
+        1,
+        "Iterating external node should have exactly one call node predecessor"
+    );
+    let start_edge = caller.rmv_edge(starts[0], extern_node);
+    caller.add_edge(starts[0], callee_entry, start_edge);
+    let mut resume_edges = vec![];
+    for pred in resumes {
+        while caller.get_edge(pred, extern_node).is_some() {
+            resume_edges.push((pred, caller.rmv_edge(pred, extern_node)));
+        }
+    }
+
+    // True edge goes to the loop body, false edge leaves the loop
+    let mut body = None;
+    let mut exit = None;
+    for succ in caller.succs(extern_node).collect::<Vec<_>>() {
+        match caller
+            .get_edge(extern_node, succ)
+            .unwrap()
+            .downcast_ref::<BranchEdge>()
+        {
+            Some(BranchEdge { condition: true }) => body = Some(succ),
+            Some(BranchEdge { condition: false }) => exit = Some(succ),
+            None => panic!("Iterating external node should only have branch edges"),
+        }
+    }
+    let body = body.expect("Iterating external node should have a loop body");
+    let exit = exit.unwrap_or_else(|| caller.add_node(ReturnNode { values: vec![] }));
+    caller.rmv_node(extern_node);
+
+    let params = FuncNode::concrete(caller.get_node(body))
+        .expect("Loop body should start with a func node")
+        .params
+        .len();
+
+    // Replace yields with calls into the loop body
+    let yields = callee_nodes
+        .iter()
+        .cloned()
+        .filter(|idx| YieldNode::downcastable(caller.get_node(*idx)))
+        .collect::<Vec<_>>();
+    let resume = fresh_var(caller, "__resume");
+    for (i, yield_idx) in yields.iter().enumerate() {
+        let values = YieldNode::concrete(caller.get_node(*yield_idx))
+            .unwrap()
+            .values
+            .clone();
+        assert_eq!(
+            values.len(),
+            params,
+            "Yield {} does not match loop target",
+            caller.get_node(*yield_idx)
+        );
+        let mut args = vec![];
+        for expr in values {
+            match expr {
+                Expr::Var(var) => args.push(var),
+                expr => {
+                    let temp_var = fresh_var(caller, "__yield");
+                    let assign_node = AssignNode {
+                        lvalue: temp_var.clone(),
+                        rvalue: expr,
+                    };
+                    caller.insert_node_before(assign_node, *yield_idx, NoneEdge.into());
+                    args.push(temp_var);
+                }
+            }
+        }
+        if yields.len() > 1 {
+            let assign_node = AssignNode {
+                lvalue: resume.clone(),
+                rvalue: Expr::Int(IntExpr::new(i as i32)),
+            };
+            caller.insert_node_before(assign_node, *yield_idx, NoneEdge.into());
+        }
+        caller.replace_node(*yield_idx, CallNode { args });
+    }
+
+    // Cut after inserting before every yield,
+    // so resuming before a yield runs the nodes inserted for it
+    let mut continuations = vec![];
+    for yield_idx in &yields {
+        let succs = caller.succs(*yield_idx).collect::<Vec<_>>();
+        assert_eq!(succs.len(), 1, "Yield node should have one successor");
+        caller.rmv_edge(*yield_idx, succs[0]);
+        continuations.push(succs[0]);
+        caller.add_edge(*yield_idx, body, NoneEdge.into());
+    }
+
+    // Resuming continues after the yield that ran the body
+    let resume_target = match continuations.len() {
+        0 => None,
+        _ => {
+            let mut target = *continuations.last().unwrap();
+            for (i, continuation) in continuations.iter().enumerate().rev().skip(1) {
+                let cond = Expr::BinOp(
+                    Box::new(Expr::Var(resume.clone())),
+                    Operator::Eq,
+                    Box::new(Expr::Int(IntExpr::new(i as i32))),
+                );
+                let branch = caller.add_node(BranchNode { cond });
+                caller.add_edge(branch, *continuation, BranchEdge::new(true).into());
+                caller.add_edge(branch, target, BranchEdge::new(false).into());
+                target = branch;
+            }
+            Some(target)
+        }
+    };
+    if let Some(target) = resume_target {
+        for (pred, edge) in resume_edges {
+            caller.add_edge(pred, target, edge);
+        }
+    }
+
+    // Callee returns leave the loop
+    for idx in callee_nodes {
+        if ReturnNode::downcastable(caller.get_node(idx)) {
+            let preds = caller.preds(idx).collect::<BTreeSet<_>>();
+            for pred in preds {
+                while caller.get_edge(pred, idx).is_some() {
+                    let edge = caller.rmv_edge(pred, idx);
+                    caller.add_edge(pred, exit, edge);
+                }
+            }
+            caller.rmv_node(idx);
+        }
+    }
+
+    // Loop body is unreachable if the callee never yields
+    let reachable = caller.dfs(caller.entry).into_iter().collect::<BTreeSet<_>>();
+    for idx in caller.nodes().collect::<Vec<_>>() {
+        if !reachable.contains(&idx) {
+            caller.rmv_node(idx);
+        }
+    }
+}
+
+/// Variable named `{prefix}_{n}` that is not used anywhere in the graph
+fn fresh_var(graph: &CFG, prefix: &str) -> VarExpr {
+    let used = graph
+        .nodes()
+        .flat_map(|idx| {
+            let node = graph.get_node(idx);
+            node.referenced_vars()
+                .into_iter()
+                .chain(node.declared_vars())
+                .map(|var| var.name.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect::<BTreeSet<_>>();
+    (0..)
+        .map(|i| format!("{}_{}", prefix, i))
+        .find(|name| !used.contains(name))
+        .map(|name| VarExpr::new(&name))
+        .unwrap()
+}
+
 /// Converts return node to call node
 fn convert_return_to_call_node(node: &ReturnNode) -> CallNode {
     let args = node
@@ -15,7 +15,7 @@ pub struct Loop {
 }
 
 /// Detects nested loops
-fn detect_nested_loops(graph: &CFG) -> Vec<Loop> {
+pub(crate) fn detect_nested_loops(graph: &CFG) -> Vec<Loop> {
     let mut loops = detect_loops(graph);
 
     let mut prev_length = std::usize::MAX;
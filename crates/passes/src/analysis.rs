@@ -0,0 +1,6 @@
+//! Analyses on the IR.
+//! These do not modify the graph.
+
+mod verify;
+
+pub use verify::{Verify, Violation};
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use tohdl_ir::{expr::*, graph::*};
+
+/// Invariant that a graph breaks, with the nodes involved
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// Branch without exactly a true and a false successor
+    BranchSuccs {
+        node: NodeIndex,
+        trues: usize,
+        falses: usize,
+        count: usize,
+    },
+    /// External call with other than one successor, or external iterate with other than two
+    ExternalSuccs { node: NodeIndex, count: usize },
+    /// Call node passing a different number of args than its func node takes
+    Arity {
+        call: NodeIndex,
+        func: NodeIndex,
+        args: usize,
+        params: usize,
+    },
+    /// Variable declared by more than one node
+    MultipleDefinitions { var: VarExpr, nodes: Vec<NodeIndex> },
+    /// Lowest node of a cycle
+    Cycle { node: NodeIndex },
+}
+
+impl Violation {
+    pub fn nodes(&self) -> Vec<NodeIndex> {
+        match self {
+            Violation::BranchSuccs { node, .. } => vec![*node],
+            Violation::ExternalSuccs { node, .. } => vec![*node],
+            Violation::Arity { call, func, .. } => vec![*call, *func],
+            Violation::MultipleDefinitions { nodes, .. } => nodes.clone(),
+            Violation::Cycle { node } => vec![*node],
+        }
+    }
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::BranchSuccs {
+                node,
+                trues,
+                falses,
+                count,
+            } => write!(
+                f,
+                "branch {} has {} successors, {} true and {} false, expected one of each",
+                node, count, trues, falses
+            ),
+            Violation::ExternalSuccs { node, count } => {
+                write!(f, "external {} has {} successors", node, count)
+            }
+            Violation::Arity {
+                call,
+                func,
+                args,
+                params,
+            } => write!(
+                f,
+                "call {} passes {} args to func {} with {} params",
+                call, args, func, params
+            ),
+            Violation::MultipleDefinitions { var, nodes } => {
+                write!(f, "{} is defined by {:?}", var, nodes)
+            }
+            Violation::Cycle { node } => write!(f, "{} is on a cycle", node),
+        }
+    }
+}
+
+/// Checks invariants that passes rely on.
+/// Structural checks always run, `ssa` and `acyclic` only hold at some stages of the pipeline
+#[derive(Clone, Debug, Default)]
+pub struct Verify {
+    /// Every variable is declared once, holds after [crate::transform::BraunEtAl]
+    pub ssa: bool,
+    /// No cycles, holds for the subgraphs made by [crate::transform::LowerToFsm]
+    pub acyclic: bool,
+}
+
+impl Verify {
+    /// Verifies with every check enabled
+    pub fn all() -> Self {
+        Self {
+            ssa: true,
+            acyclic: true,
+        }
+    }
+
+    pub fn run(&self, graph: &CFG) -> Vec<Violation> {
+        let mut ret = vec![];
+        for idx in graph.nodes() {
+            let node = graph.get_node(idx);
+            let count = graph.succs(idx).count();
+            if BranchNode::downcastable(node) {
+                let conditions = graph
+                    .graph
+                    .edges_directed(idx.into(), petgraph::Direction::Outgoing)
+                    .filter_map(|edge| Some(edge.weight().downcast_ref::<BranchEdge>()?.condition))
+                    .collect::<Vec<_>>();
+                let trues = conditions.iter().filter(|condition| **condition).count();
+                let falses = conditions.len() - trues;
+                if (trues, falses, count) != (1, 1, 2) {
+                    ret.push(Violation::BranchSuccs {
+                        node: idx,
+                        trues,
+                        falses,
+                        count,
+                    });
+                }
+            }
+            if let Some(external) = ExternalNode::concrete(node) {
+                let expected = match external.kind {
+                    ExternalKind::Call => 1,
+                    ExternalKind::Iterate => 2,
+                };
+                if count != expected {
+                    ret.push(Violation::ExternalSuccs { node: idx, count });
+                }
+            }
+            if let Some(CallNode { args }) = CallNode::concrete(node) {
+                for succ in graph.succs(idx) {
+                    if let Some(FuncNode { params }) = FuncNode::concrete(graph.get_node(succ)) {
+                        if args.len() != params.len() {
+                            ret.push(Violation::Arity {
+                                call: idx,
+                                func: succ,
+                                args: args.len(),
+                                params: params.len(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if self.ssa {
+            let mut definitions: BTreeMap<&VarExpr, Vec<NodeIndex>> = BTreeMap::new();
+            for idx in graph.nodes() {
+                for var in graph.get_node(idx).declared_vars() {
+                    definitions.entry(var).or_default().push(idx);
+                }
+            }
+            for (var, nodes) in definitions {
+                if nodes.len() > 1 {
+                    ret.push(Violation::MultipleDefinitions {
+                        var: var.clone(),
+                        nodes,
+                    });
+                }
+            }
+        }
+        if self.acyclic {
+            // One violation per strongly connected component that loops,
+            // named by its lowest node so it stays the same across passes that keep it
+            for component in petgraph::algo::tarjan_scc(&graph.graph) {
+                let node = *component.iter().min().unwrap();
+                if component.len() > 1 || graph.graph.contains_edge(node, node) {
+                    ret.push(Violation::Cycle { node: node.into() });
+                }
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::*, transform::*, BasicTransform};
+
+    #[test]
+    fn range() {
+        let mut graph = make_range();
+        assert_eq!(Verify::default().run(&graph), vec![]);
+        assert!(!Verify::all().run(&graph).is_empty());
+
+        InsertFuncNodes::transform(&mut graph);
+        InsertCallNodes::transform(&mut graph);
+        BraunEtAl::transform(&mut graph);
+        let violations = Verify::all().run(&graph);
+        // println!("{:?}", violations);
+        assert!(violations
+            .iter()
+            .all(|v| matches!(v, Violation::Cycle { .. })));
+    }
+
+    #[test]
+    fn arity() {
+        let mut graph = make_range();
+        InsertFuncNodes::transform(&mut graph);
+        InsertCallNodes::transform(&mut graph);
+        BraunEtAl::transform(&mut graph);
+        let call = graph
+            .nodes()
+            .find(|idx| CallNode::downcastable(graph.get_node(*idx)))
+            .unwrap();
+        CallNode::concrete_mut(graph.get_node_mut(call))
+            .unwrap()
+            .args
+            .push(VarExpr::new("extra"));
+        let violations = Verify::default().run(&graph);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].nodes().contains(&call));
+    }
+
+    #[test]
+    fn branch_conditions() {
+        let mut graph = make_range();
+        assert_eq!(Verify::default().run(&graph), vec![]);
+        let branch = graph
+            .nodes()
+            .find(|idx| BranchNode::downcastable(graph.get_node(*idx)))
+            .unwrap();
+        for succ in graph.succs(branch).collect::<Vec<_>>() {
+            graph.rmv_edge(branch, succ);
+            graph.add_edge(branch, succ, BranchEdge::new(true).into());
+        }
+        assert_eq!(
+            Verify::default().run(&graph),
+            vec![Violation::BranchSuccs {
+                node: branch,
+                trues: 2,
+                falses: 0,
+                count: 2,
+            }]
+        );
+    }
+}
@@ -1,20 +1,30 @@
 pub mod algorithms;
+pub mod analysis;
 pub mod manager;
 pub mod optimize;
 pub mod transform;
 
+use std::collections::BTreeMap;
+
 use tohdl_ir::graph::CFG;
 
 #[derive(Debug, Clone)]
 pub struct TransformResultType {
     pub elapsed_time: std::time::Duration,
     pub name: String,
+    /// Named tallies of what the transform changed, such as removed nodes
+    pub counts: BTreeMap<String, usize>,
     did_work: bool,
 }
 
 impl Default for TransformResultType {
     fn default() -> Self {
-        Self { elapsed_time: Default::default(), name: Default::default(), did_work: true }
+        Self {
+            elapsed_time: Default::default(),
+            name: Default::default(),
+            counts: Default::default(),
+            did_work: true,
+        }
     }
 }
 
@@ -29,12 +39,21 @@ impl TransformResultType {
     pub fn did_work(&mut self) {
         self.did_work = true;
     }
+
+    /// Adds `amount` to the tally called `name`
+    pub fn count(&mut self, name: &str, amount: usize) {
+        *self.counts.entry(name.into()).or_default() += amount;
+    }
 }
 
 
 impl std::fmt::Display for TransformResultType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Transform {:>20} elapsed time: {:>12?}", self.name, self.elapsed_time)
+        write!(f, "Transform {:>20} elapsed time: {:>12?}", self.name, self.elapsed_time)?;
+        for (name, amount) in &self.counts {
+            write!(f, " {name}: {amount}")?;
+        }
+        Ok(())
     }
 }
 
@@ -107,6 +126,47 @@ where
 pub(crate) mod tests {
     use tohdl_ir::expr::*;
     use tohdl_ir::graph::*;
+    use tohdl_ir::interpret::interpret;
+
+    use crate::transform::{BraunEtAl, InsertCallNodes, InsertFuncNodes};
+    use crate::BasicTransform;
+
+    /// Inputs of the single parameter functions that passes are checked on
+    pub const INPUTS: &[&[i64]] = &[&[-3], &[0], &[1], &[7], &[20]];
+
+    /// Parses `text` and puts it in SSA form, as passes after [BraunEtAl] expect
+    pub fn ssa(text: &str) -> CFG {
+        let mut graph = CFG::from_text(text).unwrap();
+        InsertFuncNodes::transform(&mut graph);
+        InsertCallNodes::transform(&mut graph);
+        BraunEtAl::transform(&mut graph);
+        graph
+    }
+
+    /// Runs `pass` on `graph`, checking that it yields and returns the same on every inputs
+    pub fn preserving<T>(graph: &mut CFG, inputs: &[&[i64]], pass: impl FnOnce(&mut CFG) -> T) -> T {
+        let before = graph.clone();
+        let ret = pass(graph);
+        // println!("{}", graph.to_text());
+        for inputs in inputs {
+            let inputs = inputs.iter().map(|i| BigInt::from(*i)).collect::<Vec<_>>();
+            assert_eq!(
+                interpret(graph, &inputs).unwrap(),
+                interpret(&before, &inputs).unwrap(),
+                "inputs {:?}",
+                inputs
+            );
+        }
+        ret
+    }
+
+    /// Number of nodes of type `T`
+    pub fn count<T: Node>(graph: &CFG) -> usize {
+        graph
+            .nodes()
+            .filter(|idx| T::downcastable(graph.get_node(*idx)))
+            .count()
+    }
 
     /// Make range function
     pub fn make_range() -> CFG {
@@ -325,7 +385,7 @@ def even_fib(n):
         i = i + 1
     return 0
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
@@ -344,7 +404,7 @@ def double_while(n):
         x = x + 1
     return 0
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
@@ -361,7 +421,7 @@ def linear():
     c = a + d
     return 0
         "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
@@ -375,7 +435,7 @@ def func(a):
         b = 1
     else:
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
@@ -388,7 +448,7 @@ def aug_assign(a):
     a += 5
     return a
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
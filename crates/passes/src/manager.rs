@@ -1,3 +1,7 @@
+use tohdl_ir::expr::BigInt;
+use tohdl_ir::interpret::{interpret, Execution};
+
+use crate::analysis::Verify;
 use crate::*;
 
 #[derive(Default)]
@@ -7,6 +11,8 @@ pub struct PassManager {
     log: bool,
     prefix: String,
     write: bool,
+    verify: Option<Verify>,
+    inputs: Vec<Vec<BigInt>>,
 }
 
 impl PassManager {
@@ -23,6 +29,8 @@ impl PassManager {
             log: true,
             prefix: "".into(),
             write: false,
+            verify: None,
+            inputs: vec![],
         }
     }
 
@@ -34,10 +42,27 @@ impl PassManager {
             log: true,
             prefix,
             write: false,
+            verify: None,
+            inputs: vec![],
         }
     }
 }
 
+impl PassManager {
+    /// Debug mode, runs `verify` after every pass and panics naming the first pass
+    /// after which the graph has a violation it did not have before it, listing every such one
+    pub fn verify(&mut self, verify: Verify) {
+        self.verify = Some(verify);
+    }
+
+    /// Debug mode, interprets the graph on each set of inputs before the first pass
+    /// and panics naming the first pass after which a result differs.
+    /// Inputs the original graph cannot run on are skipped
+    pub fn interpret(&mut self, inputs: Vec<Vec<BigInt>>) {
+        self.inputs = inputs;
+    }
+}
+
 impl PassManager {
     fn log_pass(&self, result: &TransformResultType) {
         println!("{}", result);
@@ -53,6 +78,16 @@ impl BasicTransform for PassManager {
         if self.log {
             println!("Pass Manager at {}", std::panic::Location::caller());
         }
+        let mut violations = self
+            .verify
+            .as_ref()
+            .map(|verify| verify.run(graph))
+            .unwrap_or_default();
+        let expected = self
+            .inputs
+            .iter()
+            .filter_map(|inputs| Some((inputs, interpret(graph, inputs).ok()?)))
+            .collect::<Vec<(&Vec<BigInt>, Execution)>>();
         for (i, pass) in self.passes.iter().enumerate() {
             let result = pass(graph);
             self.result.elapsed_time += result.elapsed_time;
@@ -64,6 +99,30 @@ impl BasicTransform for PassManager {
             if self.log {
                 self.log_pass(&result);
             }
+            if let Some(verify) = &self.verify {
+                let after = verify.run(graph);
+                let broken = after
+                    .iter()
+                    .filter(|v| !violations.contains(v))
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>();
+                if !broken.is_empty() {
+                    panic!(
+                        "Pass {} ({}) broke the IR:\n{}",
+                        i, result.name, broken.join("\n")
+                    );
+                }
+                violations = after;
+            }
+            for (inputs, expected) in &expected {
+                let actual = interpret(graph, inputs);
+                if actual.as_ref() != Ok(expected) {
+                    panic!(
+                        "Pass {} ({}) changed the result for inputs {:?}:\n{:?}\nbecame\n{:?}",
+                        i, result.name, inputs, expected, actual
+                    );
+                }
+            }
         }
         &self.result
     }
@@ -73,6 +132,7 @@ impl BasicTransform for PassManager {
 mod tests {
     use super::*;
     use crate::{tests::*, transform::*};
+    use tohdl_ir::{expr::*, graph::*};
 
     #[test]
     fn main() {
@@ -105,4 +165,108 @@ mod tests {
 
         // graph.write_dot("manager.dot")
     }
+
+    #[test]
+    fn verify() {
+        let mut manager = PassManager::default();
+        manager.verify(Verify::default());
+        manager.add_pass(InsertFuncNodes::transform);
+        manager.add_pass(InsertCallNodes::transform);
+        manager.add_pass(BraunEtAl::transform);
+        manager.apply(&mut make_range());
+
+        fn drop_branch_edge(graph: &mut CFG) -> TransformResultType {
+            let idx = graph
+                .nodes()
+                .find(|idx| BranchNode::downcastable(graph.get_node(*idx)))
+                .unwrap();
+            let succ = graph.succs(idx).next().unwrap();
+            graph.rmv_edge(idx, succ);
+            TransformResultType {
+                name: "DropBranchEdge".into(),
+                ..Default::default()
+            }
+        }
+        let mut manager = PassManager::default();
+        manager.verify(Verify::default());
+        manager.add_pass(InsertFuncNodes::transform);
+        manager.add_pass(drop_branch_edge);
+        let result = std::panic::catch_unwind(move || {
+            manager.apply(&mut make_range());
+        });
+        let message = *result.err().unwrap().downcast::<String>().unwrap();
+        assert!(message.contains("DropBranchEdge"));
+    }
+
+    #[test]
+    fn verify_same_kind() {
+        // `i` is already defined twice, a second variable defined twice is still caught
+        fn redefine_param(graph: &mut CFG) -> TransformResultType {
+            let entry = graph.get_entry();
+            graph.insert_node_after(
+                AssignNode {
+                    lvalue: VarExpr::new("n"),
+                    rvalue: Expr::Int(IntExpr::new(0)),
+                },
+                entry,
+                NoneEdge.into(),
+            );
+            TransformResultType {
+                name: "RedefineParam".into(),
+                ..Default::default()
+            }
+        }
+        let mut manager = PassManager::default();
+        manager.verify(Verify {
+            ssa: true,
+            ..Default::default()
+        });
+        manager.add_pass(redefine_param);
+        let result = std::panic::catch_unwind(move || {
+            manager.apply(&mut make_range());
+        });
+        let message = *result.err().unwrap().downcast::<String>().unwrap();
+        // println!("{}", message);
+        assert!(message.contains("RedefineParam"));
+        assert!(!message.contains("i is defined"));
+    }
+
+    #[test]
+    fn interpret() {
+        let inputs = vec![vec![BigInt::from(0)], vec![BigInt::from(5)]];
+        let mut manager = PassManager::default();
+        manager.interpret(inputs.clone());
+        manager.add_pass(InsertFuncNodes::transform);
+        manager.add_pass(InsertCallNodes::transform);
+        manager.add_pass(BraunEtAl::transform);
+        manager.apply(&mut make_range());
+
+        fn off_by_one(graph: &mut CFG) -> TransformResultType {
+            for idx in graph.nodes().collect::<Vec<_>>() {
+                let Some(node) = ReturnNode::concrete_mut(graph.get_node_mut(idx)) else {
+                    continue;
+                };
+                if let Some(value) = node.values.first_mut() {
+                    *value = Expr::BinOp(
+                        Box::new(value.clone()),
+                        Operator::Add,
+                        Box::new(Expr::Int(IntExpr::new(1))),
+                    );
+                }
+            }
+            TransformResultType {
+                name: "OffByOne".into(),
+                ..Default::default()
+            }
+        }
+        let mut manager = PassManager::default();
+        manager.interpret(inputs);
+        manager.add_pass(InsertFuncNodes::transform);
+        manager.add_pass(off_by_one);
+        let result = std::panic::catch_unwind(move || {
+            manager.apply(&mut make_range());
+        });
+        let message = *result.err().unwrap().downcast::<String>().unwrap();
+        assert!(message.contains("OffByOne"));
+    }
 }
@@ -1,4 +1,12 @@
+mod eliminate_dead_code;
+mod infer_widths;
+mod propagate_constants;
+mod reduce_strength;
 mod remove_redundant_calls;
 mod remove_unread_vars;
+pub use eliminate_dead_code::EliminateDeadCode;
+pub use infer_widths::{leaf_width, InferWidths};
+pub use propagate_constants::PropagateConstants;
+pub use reduce_strength::ReduceStrength;
 pub use remove_redundant_calls::RemoveRedundantCalls;
 pub use remove_unread_vars::RemoveUnreadVars;
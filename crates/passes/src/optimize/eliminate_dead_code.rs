@@ -0,0 +1,509 @@
+//! Removes code that cannot change what a graph yields or returns.
+//! Unlike [super::RemoveUnreadVars], liveness starts from the observable values
+//! rather than from reference counts, so vars only read by their own dead chain go too.
+//! Also removes nodes unreachable from the entry, branches whose sides lead to the same place,
+//! and loops without effects that provably terminate
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use tohdl_ir::{expr::*, graph::*};
+
+use crate::algorithms::loop_detector::detect_nested_loops;
+use crate::*;
+
+#[derive(Default)]
+pub struct EliminateDeadCode {
+    result: TransformResultType,
+    removed_nodes: usize,
+    removed_vars: BTreeSet<VarExpr>,
+}
+
+impl BasicTransform for EliminateDeadCode {
+    fn apply(&mut self, graph: &mut CFG) -> &TransformResultType {
+        loop {
+            let before = (self.removed_nodes, self.removed_vars.len());
+            self.remove_unreachable(graph);
+            self.remove_dead_vars(graph);
+            self.remove_empty_branches(graph);
+            self.remove_effectless_loop(graph);
+            if before == (self.removed_nodes, self.removed_vars.len()) {
+                break;
+            }
+        }
+        if self.removed_nodes == 0 && self.removed_vars.is_empty() {
+            self.result = TransformResultType::no_work();
+        }
+        self.result.count("removed nodes", self.removed_nodes);
+        self.result.count("removed vars", self.removed_vars.len());
+        &self.result
+    }
+}
+
+impl EliminateDeadCode {
+    fn remove_unreachable(&mut self, graph: &mut CFG) {
+        let reachable = graph.dfs(graph.entry);
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            if !reachable.contains(&idx) {
+                graph.rmv_node(idx);
+                self.removed_nodes += 1;
+            }
+        }
+    }
+
+    /// Call node whose args are params of the func nodes it calls
+    fn is_call(graph: &CFG, idx: NodeIndex) -> bool {
+        CallNode::downcastable(graph.get_node(idx))
+            && graph
+                .succs(idx)
+                .all(|succ| FuncNode::downcastable(graph.get_node(succ)))
+    }
+
+    /// Vars that yielded, returned or branched on values depend on
+    fn live_vars(graph: &CFG) -> BTreeSet<VarExpr> {
+        let mut definitions: BTreeMap<&VarExpr, Vec<NodeIndex>> = BTreeMap::new();
+        let mut worklist = vec![];
+        for idx in graph.nodes() {
+            let node = graph.get_node(idx);
+            for var in node.declared_vars() {
+                definitions.entry(var).or_default().push(idx);
+            }
+            if !AssignNode::downcastable(node) && !Self::is_call(graph, idx) {
+                worklist.extend(node.referenced_vars());
+            }
+        }
+
+        let mut live = BTreeSet::new();
+        while let Some(var) = worklist.pop() {
+            if !live.insert(var.clone()) {
+                continue;
+            }
+            for &idx in definitions.get(var).into_iter().flatten() {
+                let node = graph.get_node(idx);
+                let Some(FuncNode { params }) = FuncNode::concrete(node) else {
+                    worklist.extend(node.referenced_vars());
+                    continue;
+                };
+                // A live param keeps the matching arg of every call live
+                let position = params.iter().position(|param| param == var).unwrap();
+                for pred in graph.preds(idx) {
+                    if let Some(CallNode { args }) = CallNode::concrete(graph.get_node(pred)) {
+                        worklist.extend(args.get(position));
+                    }
+                }
+            }
+        }
+        live
+    }
+
+    fn remove_dead_vars(&mut self, graph: &mut CFG) {
+        let live = Self::live_vars(graph);
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            if let Some(AssignNode { lvalue, .. }) = AssignNode::concrete(graph.get_node(idx)) {
+                if !live.contains(lvalue) {
+                    self.removed_vars.insert(lvalue.clone());
+                    graph.rmv_node_and_reattach(idx);
+                    self.removed_nodes += 1;
+                }
+                continue;
+            }
+
+            // Params of the entry are the inputs, and those of other funcs need every pred
+            // to be a call to stay paired with args
+            let Some(FuncNode { params }) = FuncNode::concrete(graph.get_node(idx)) else {
+                continue;
+            };
+            let preds = graph.preds(idx).collect::<Vec<_>>();
+            let paired = preds.iter().all(|pred| {
+                CallNode::concrete(graph.get_node(*pred))
+                    .is_some_and(|call| call.args.len() == params.len())
+            });
+            if idx == graph.entry || !paired {
+                continue;
+            }
+            let dead = params
+                .iter()
+                .enumerate()
+                .filter(|(_, param)| !live.contains(*param))
+                .map(|(position, _)| position)
+                .collect::<Vec<_>>();
+            for &position in dead.iter().rev() {
+                let params = &mut FuncNode::concrete_mut(graph.get_node_mut(idx))
+                    .unwrap()
+                    .params;
+                self.removed_vars.insert(params.remove(position));
+                for pred in &preds {
+                    let call = CallNode::concrete_mut(graph.get_node_mut(*pred)).unwrap();
+                    call.args.remove(position);
+                }
+            }
+        }
+    }
+
+    /// Removes branches whose sides meet again without doing anything
+    fn remove_empty_branches(&mut self, graph: &mut CFG) {
+        let branches = graph
+            .nodes()
+            .filter(|idx| BranchNode::downcastable(graph.get_node(*idx)))
+            .collect::<Vec<_>>();
+        for idx in branches {
+            let succs = graph.succs(idx).collect::<Vec<_>>();
+            let [first, second] = succs[..] else {
+                continue;
+            };
+            if first == second {
+                graph.rmv_edge(idx, second);
+            } else if Self::same_call(graph, idx, first, second) {
+                // Both sides only call the same func with the same args
+                graph.rmv_node(second);
+                self.removed_nodes += 1;
+            } else {
+                continue;
+            }
+            graph.rmv_node_and_reattach(idx);
+            self.removed_nodes += 1;
+        }
+    }
+
+    fn same_call(graph: &CFG, branch: NodeIndex, first: NodeIndex, second: NodeIndex) -> bool {
+        let (Some(a), Some(b)) = (
+            CallNode::concrete(graph.get_node(first)),
+            CallNode::concrete(graph.get_node(second)),
+        ) else {
+            return false;
+        };
+        let only_from_branch =
+            |idx: NodeIndex| graph.preds(idx).collect::<Vec<_>>() == vec![branch];
+        a.args == b.args
+            && only_from_branch(first)
+            && only_from_branch(second)
+            && graph.succs(first).collect::<Vec<_>>() == graph.succs(second).collect::<Vec<_>>()
+    }
+
+    /// Whether a loop in SSA form counts up or down by one to a bound it never changes,
+    /// checking the bound on every iteration. The bound must fit the type of the counter,
+    /// which then never wraps around. Inner loops must have been removed first
+    fn terminates(graph: &CFG, members: &BTreeSet<NodeIndex>) -> bool {
+        let headers = members
+            .iter()
+            .filter(|idx| graph.preds(**idx).any(|pred| !members.contains(&pred)))
+            .collect::<Vec<_>>();
+        let [&header] = headers[..] else {
+            return false;
+        };
+        let Some(FuncNode { params }) = FuncNode::concrete(graph.get_node(header)) else {
+            return false;
+        };
+
+        // Every iteration goes through the header and then the exiting branch
+        let mut idx = header;
+        let cond = loop {
+            if let Some(BranchNode { cond }) = BranchNode::concrete(graph.get_node(idx)) {
+                break cond;
+            }
+            let succs = graph.succs(idx).collect::<Vec<_>>();
+            match succs[..] {
+                [succ] if succ != header && members.contains(&succ) => idx = succ,
+                _ => return false,
+            }
+        };
+        let branch = idx;
+        let stays = |condition: bool| {
+            graph.succs(branch).all(|succ| {
+                let edge = graph.get_edge(branch, succ).unwrap();
+                let taken = edge.downcast_ref::<BranchEdge>().map(|edge| edge.condition);
+                members.contains(&succ) == (taken == Some(condition))
+            })
+        };
+        if !stays(true) {
+            return false;
+        }
+        let (counter, bound, step) = match cond {
+            Expr::BinOp(left, Operator::Lt, right) => (left, right, 1),
+            Expr::BinOp(left, Operator::Gt, right) => (left, right, -1),
+            _ => return false,
+        };
+        let (counter, bound, step) = match (&**counter, &**bound) {
+            (Expr::Var(counter), bound) if params.contains(counter) => (counter, bound, step),
+            (bound, Expr::Var(counter)) if params.contains(counter) => (counter, bound, -step),
+            _ => return false,
+        };
+        let fits = match bound {
+            Expr::Int(int) => {
+                IntExpr::sized(int.value.clone(), counter.size, counter.signed).value == int.value
+            }
+            Expr::Var(var) => {
+                let declared_in_loop = members
+                    .iter()
+                    .any(|idx| graph.get_node(*idx).declared_vars().contains(&var));
+                !declared_in_loop
+                    && (var.signed == counter.signed && var.size <= counter.size
+                        || !var.signed && counter.signed && var.size < counter.size)
+            }
+            _ => false,
+        };
+        if !fits {
+            return false;
+        }
+
+        // Each way back to the header passes the counter stepped once
+        let position = params.iter().position(|param| param == counter).unwrap();
+        let steps = |arg: &VarExpr| {
+            members.iter().any(|idx| {
+                let Some(AssignNode { lvalue, rvalue }) =
+                    AssignNode::concrete(graph.get_node(*idx))
+                else {
+                    return false;
+                };
+                let var = Box::new(Expr::Var(counter.clone()));
+                let one = Box::new(Expr::Int(IntExpr::new(1)));
+                let stepped = if step > 0 {
+                    [
+                        Expr::BinOp(var.clone(), Operator::Add, one.clone()),
+                        Expr::BinOp(one, Operator::Add, var),
+                    ]
+                    .contains(rvalue)
+                } else {
+                    *rvalue == Expr::BinOp(var, Operator::Sub, one)
+                };
+                lvalue == arg && stepped
+            })
+        };
+        let latches_step = graph
+            .preds(header)
+            .filter(|pred| members.contains(pred))
+            .all(|pred| {
+                CallNode::concrete(graph.get_node(pred))
+                    .and_then(|call| call.args.get(position))
+                    .is_some_and(steps)
+            });
+
+        // No inner loops, every cycle goes through the header
+        let inner = members
+            .iter()
+            .flat_map(|idx| graph.succs(*idx).map(move |succ| (*idx, succ)))
+            .filter(|(_, succ)| *succ != header && members.contains(succ))
+            .collect::<Vec<_>>();
+        let inner = petgraph::graphmap::DiGraphMap::<NodeIndex, ()>::from_edges(inner);
+        latches_step && !petgraph::algo::is_cyclic_directed(&inner)
+    }
+
+    /// Removes the first loop found that terminates, has a single exit, no yields or returns,
+    /// and defines no var read after it. Its entering nodes jump to the exit instead
+    fn remove_effectless_loop(&mut self, graph: &mut CFG) {
+        for members in detect_nested_loops(graph).into_iter().map(|l| l.members) {
+            let members = members.into_iter().collect::<BTreeSet<_>>();
+            if members.contains(&graph.entry)
+                || members.iter().any(|idx| {
+                    let node = graph.get_node(*idx);
+                    !(AssignNode::downcastable(node)
+                        || BranchNode::downcastable(node)
+                        || CallNode::downcastable(node)
+                        || FuncNode::downcastable(node))
+                })
+            {
+                continue;
+            }
+
+            let exits = members
+                .iter()
+                .flat_map(|idx| graph.succs(*idx))
+                .filter(|succ| !members.contains(succ))
+                .collect::<BTreeSet<_>>();
+            let [exit] = exits.into_iter().collect::<Vec<_>>()[..] else {
+                continue;
+            };
+            // The nodes jumping to the exit would not be calls passing its args
+            if FuncNode::concrete(graph.get_node(exit)).is_some_and(|func| !func.params.is_empty())
+            {
+                continue;
+            }
+
+            let defined = members
+                .iter()
+                .flat_map(|idx| graph.get_node(*idx).declared_vars())
+                .collect::<BTreeSet<_>>();
+            let read_after = graph.nodes().any(|idx| {
+                !members.contains(&idx)
+                    && graph
+                        .get_node(idx)
+                        .referenced_vars()
+                        .iter()
+                        .any(|var| defined.contains(var))
+            });
+            if read_after || !Self::terminates(graph, &members) {
+                continue;
+            }
+
+            // Calls into the loop go with it
+            let mut removed = members;
+            for idx in removed.clone() {
+                for pred in graph.preds(idx).collect::<Vec<_>>() {
+                    if !removed.contains(&pred)
+                        && pred != graph.entry
+                        && CallNode::downcastable(graph.get_node(pred))
+                        && graph.succs(pred).all(|succ| succ == idx)
+                    {
+                        removed.insert(pred);
+                    }
+                }
+            }
+            for &idx in &removed {
+                for pred in graph.preds(idx).collect::<Vec<_>>() {
+                    if !removed.contains(&pred) {
+                        let edge = graph.rmv_edge(pred, idx);
+                        graph.add_edge(pred, exit, edge);
+                    }
+                }
+            }
+            for &idx in &removed {
+                for var in graph.get_node(idx).declared_vars() {
+                    self.removed_vars.insert(var.clone());
+                }
+                graph.rmv_node(idx);
+                self.removed_nodes += 1;
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn eliminate(text: &str) -> (CFG, TransformResultType) {
+        let mut graph = ssa(text);
+        let result = preserving(&mut graph, INPUTS, EliminateDeadCode::transform);
+        (graph, result)
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut graph = CFG::from_text(
+            "cfg unreachable
+entry %0
+
+%0: func(n)
+%1: return (n)
+
+%2: yield (n)
+%3: return ()
+",
+        )
+        .unwrap();
+        let result = EliminateDeadCode::transform(&mut graph);
+        assert_eq!(count::<YieldNode>(&graph), 0);
+        assert_eq!(result.counts["removed nodes"], 2);
+    }
+
+    #[test]
+    fn empty_diamond() {
+        let (graph, result) = eliminate(
+            "cfg empty_diamond
+entry %0
+
+%0: func(n)
+%1: if (n < 2)
+    true -> %2
+    false -> %3
+
+%2: x = 1
+    -> %4
+
+%3: x = (n * 2)
+    -> %4
+
+%4: return (n)
+",
+        );
+        assert_eq!(count::<BranchNode>(&graph), 0);
+        assert_eq!(count::<AssignNode>(&graph), 0);
+        assert_eq!(result.counts["removed vars"], 2);
+    }
+
+    #[test]
+    fn effectless_loop() {
+        // Only `n` is observable, `i` and `j` are not
+        let (graph, result) = eliminate(
+            "cfg effectless_loop
+entry %0
+
+%0: func(n)
+%1: i = 0
+%2: j = 0
+    -> %3
+
+%3: if (i < n)
+    true -> %4
+    false -> %6
+
+%4: j = (j + i)
+%5: i = (i + 1)
+    -> %3
+
+%6: yield (n)
+%7: return ()
+",
+        );
+        assert_eq!(count::<BranchNode>(&graph), 0);
+        assert_eq!(count::<AssignNode>(&graph), 0);
+        assert!(result.counts["removed nodes"] > 0);
+    }
+
+    #[test]
+    fn unbounded_loops() {
+        // Stepping by two could wrap around past `n`, and `k` is not known to be one
+        for step in ["(i + 2)", "(i + k)"] {
+            let (graph, _) = eliminate(
+                &"cfg unbounded_loops
+entry %0
+
+%0: func(n)
+%1: i = 0
+%2: k = 1
+    -> %3
+
+%3: if (i < n)
+    true -> %4
+    false -> %6
+
+%4: i = STEP
+    -> %3
+
+%6: yield (n)
+%7: return ()
+"
+                .replace("STEP", step),
+            );
+            // println!("{}", graph.to_text());
+            assert_eq!(count::<BranchNode>(&graph), 1, "{step}");
+        }
+    }
+
+    #[test]
+    fn observable_loop() {
+        let (graph, result) = eliminate(
+            "cfg observable_loop
+entry %0
+
+%0: func(n)
+%1: i = 0
+%2: j = 0
+    -> %3
+
+%3: if (i < n)
+    true -> %4
+    false -> %6
+
+%4: j = (j + i)
+%5: i = (i + 1)
+    -> %3
+
+%6: return (j)
+",
+        );
+        assert_eq!(count::<BranchNode>(&graph), 1);
+        assert_eq!(result.counts["removed nodes"], 0);
+    }
+}
@@ -0,0 +1,712 @@
+//! Infers the narrowest width and signedness of every SSA variable
+//! by propagating value ranges through the graph,
+//! refining them with branch conditions along the way.
+//! Should be ran after [crate::transform::BraunEtAl]
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use tohdl_ir::{expr::*, graph::*};
+
+use crate::*;
+
+/// Inclusive range of values
+type Range = (i128, i128);
+
+/// Possible values of each variable at a point in the graph,
+/// variables not defined along any path reaching that point are absent
+type Env = BTreeMap<VarExpr, Range>;
+
+/// Number of times a node's environment may grow before it is widened
+const WIDEN_AFTER: usize = 3;
+
+/// Widest type whose values fit in a [Range], wider variables are never narrowed
+const MAX_SIZE: usize = 126;
+
+#[derive(Default)]
+pub struct InferWidths {
+    result: TransformResultType,
+    /// Values each variable is assigned over the whole graph
+    pub(crate) ranges: BTreeMap<VarExpr, Range>,
+    /// Constants of the graph, used as widening thresholds
+    thresholds: BTreeSet<i128>,
+}
+
+impl BasicTransform for InferWidths {
+    fn apply(&mut self, graph: &mut CFG) -> &TransformResultType {
+        self.collect_thresholds(graph);
+        self.propagate(graph);
+        self.write_back(graph);
+        &self.result
+    }
+}
+
+/// All values of an integer type
+fn type_range(int_type: IntType) -> Range {
+    // Keep the bounds representable
+    let size = std::cmp::min(int_type.size, MAX_SIZE) as u32;
+    if int_type.signed {
+        (-(1 << (size - 1)), (1 << (size - 1)) - 1)
+    } else {
+        (0, (1 << size) - 1)
+    }
+}
+
+/// Narrowest integer type holding every value in `range`
+fn fit(range: Range) -> IntType {
+    let (lo, hi) = range;
+    if lo >= 0 {
+        let size = 128 - hi.leading_zeros() as usize;
+        IntType::new(std::cmp::max(size, 1), false)
+    } else {
+        // Bits for the magnitude plus a sign bit
+        let magnitude = std::cmp::max(hi, -(lo + 1));
+        IntType::new(129 - magnitude.leading_zeros() as usize, true)
+    }
+}
+
+fn contains(outer: Range, inner: Range) -> bool {
+    outer.0 <= inner.0 && inner.1 <= outer.1
+}
+
+fn hull(a: Range, b: Range) -> Range {
+    (std::cmp::min(a.0, b.0), std::cmp::max(a.1, b.1))
+}
+
+/// Range of `lo..=hi` from unordered corner values, `None` on overflow
+fn corners(values: [Option<i128>; 4]) -> Option<Range> {
+    let values = values.into_iter().collect::<Option<Vec<_>>>()?;
+    Some((*values.iter().min()?, *values.iter().max()?))
+}
+
+/// Values `expr` may evaluate to given `env`, `None` if any value of its width is possible.
+/// Expressions are evaluated at least 32 bits wide, as in the Verilog backend
+fn eval(expr: &Expr, env: &Env) -> Option<Range> {
+    let width = std::cmp::max(leaf_width(expr), 32);
+    if width > MAX_SIZE {
+        return None;
+    }
+    let result = eval_unbounded(expr, env)?;
+    if contains(type_range(IntType::new(width, true)), result) {
+        Some(result)
+    } else {
+        // Wraps around in hardware
+        None
+    }
+}
+
+/// Widest signed leaf of `expr`, unsigned leaves need an extra bit
+pub fn leaf_width(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var(var) => var.size + !var.signed as usize,
+        Expr::Int(int) => int.size + !int.signed as usize,
+        Expr::BinOp(left, _, right) => std::cmp::max(leaf_width(left), leaf_width(right)),
+        Expr::UnaryOp(_, operand) => leaf_width(operand),
+        Expr::Mux(cond, then, else_) => [cond, then, else_]
+            .into_iter()
+            .map(|expr| leaf_width(expr))
+            .max()
+            .unwrap(),
+    }
+}
+
+/// Width of the narrowest signed type holding every value of `expr`
+/// given the types of its leaves, `None` if unbounded
+pub(crate) fn value_width(expr: &Expr) -> Option<usize> {
+    let int_type = fit(eval_unbounded(expr, &Env::new())?);
+    Some(int_type.size + !int_type.signed as usize)
+}
+
+fn eval_unbounded(expr: &Expr, env: &Env) -> Option<Range> {
+    match expr {
+        Expr::Int(int) => int.to_i128().map(|value| (value, value)),
+        // Ranges of wider variables are truncated, so their values are unknown
+        Expr::Var(var) if var.size > MAX_SIZE => None,
+        Expr::Var(var) => Some(
+            env.get(var)
+                .copied()
+                .unwrap_or_else(|| type_range(var.int_type())),
+        ),
+        Expr::UnaryOp(UnaryOperator::Not, _) => Some((0, 1)),
+        Expr::UnaryOp(UnaryOperator::Neg, operand) => {
+            let (lo, hi) = eval_unbounded(operand, env)?;
+            Some((-hi, -lo))
+        }
+        Expr::UnaryOp(UnaryOperator::Invert, operand) => {
+            let (lo, hi) = eval_unbounded(operand, env)?;
+            Some((-hi - 1, -lo - 1))
+        }
+        Expr::Mux(_, then, else_) => Some(hull(
+            eval_unbounded(then, env)?,
+            eval_unbounded(else_, env)?,
+        )),
+        Expr::BinOp(left, op, right) => {
+            if matches!(
+                op,
+                Operator::Lt
+                    | Operator::Gt
+                    | Operator::LtE
+                    | Operator::GtE
+                    | Operator::Eq
+                    | Operator::NotEq
+                    | Operator::And
+                    | Operator::Or
+            ) {
+                return Some((0, 1));
+            }
+            let a = eval_unbounded(left, env);
+            let b = eval_unbounded(right, env);
+            eval_binop(a, op, b)
+        }
+    }
+}
+
+fn eval_binop(a: Option<Range>, op: &Operator, b: Option<Range>) -> Option<Range> {
+    match (a, op, b) {
+        (Some(a), Operator::Add, Some(b)) => Some((a.0.checked_add(b.0)?, a.1.checked_add(b.1)?)),
+        (Some(a), Operator::Sub, Some(b)) => Some((a.0.checked_sub(b.1)?, a.1.checked_sub(b.0)?)),
+        (Some(a), Operator::Mul, Some(b)) => corners([
+            a.0.checked_mul(b.0),
+            a.0.checked_mul(b.1),
+            a.1.checked_mul(b.0),
+            a.1.checked_mul(b.1),
+        ]),
+        // Truncating division never grows the magnitude of the dividend
+        (Some(a), Operator::Div, Some(b)) if a.0 >= 0 && b.0 >= 0 => Some((0, a.1)),
+        (Some(a), Operator::Div, _) => {
+            let magnitude = std::cmp::max(a.0.abs(), a.1.abs());
+            Some((-magnitude, magnitude))
+        }
+        // Result takes the sign of the dividend and is smaller than the divisor
+        (a, Operator::Mod, Some(b)) if b.0 > 0 || b.1 < 0 => {
+            let limit = std::cmp::max(b.0.abs(), b.1.abs()) - 1;
+            match a {
+                Some(a) if a.0 >= 0 => Some((0, std::cmp::min(a.1, limit))),
+                _ => Some((-limit, limit)),
+            }
+        }
+        (Some(a), Operator::LShift, Some(b)) if b.0 >= 0 && b.1 < 64 => corners([
+            a.0.checked_mul(1 << b.0),
+            a.0.checked_mul(1 << b.1),
+            a.1.checked_mul(1 << b.0),
+            a.1.checked_mul(1 << b.1),
+        ]),
+        (Some(a), Operator::RShift, Some(b)) if a.0 >= 0 && b.0 >= 0 => Some((
+            a.0 >> std::cmp::min(b.1, 127),
+            a.1 >> std::cmp::min(b.0, 127),
+        )),
+        // A non-negative operand clears every higher bit
+        (Some(a), Operator::BitAnd, Some(b)) if a.0 >= 0 && b.0 >= 0 => {
+            Some((0, std::cmp::min(a.1, b.1)))
+        }
+        (Some(a), Operator::BitAnd, _) if a.0 >= 0 => Some((0, a.1)),
+        (_, Operator::BitAnd, Some(b)) if b.0 >= 0 => Some((0, b.1)),
+        (Some(a), Operator::BitOr | Operator::BitXor, Some(b)) if a.0 >= 0 && b.0 >= 0 => {
+            let size = fit((0, std::cmp::max(a.1, b.1))).size;
+            Some(type_range(IntType::new(size, false)))
+        }
+        _ => None,
+    }
+}
+
+/// Narrows the range of a variable compared against a range of values
+fn refine_compare(range: Range, op: &Operator, other: Range, taken: bool) -> Range {
+    let (lo, hi) = range;
+    // Comparison that holds on this edge
+    let op = match (op, taken) {
+        (op, true) => op.clone(),
+        (Operator::Lt, false) => Operator::GtE,
+        (Operator::LtE, false) => Operator::Gt,
+        (Operator::Gt, false) => Operator::LtE,
+        (Operator::GtE, false) => Operator::Lt,
+        (Operator::Eq, false) => Operator::NotEq,
+        (Operator::NotEq, false) => Operator::Eq,
+        _ => return range,
+    };
+    match op {
+        Operator::Lt => (lo, std::cmp::min(hi, other.1 - 1)),
+        Operator::LtE => (lo, std::cmp::min(hi, other.1)),
+        Operator::Gt => (std::cmp::max(lo, other.0 + 1), hi),
+        Operator::GtE => (std::cmp::max(lo, other.0), hi),
+        Operator::Eq => (std::cmp::max(lo, other.0), std::cmp::min(hi, other.1)),
+        _ => range,
+    }
+}
+
+/// Swaps the sides of a comparison, `a < b` is `b > a`
+fn mirror(op: &Operator) -> Operator {
+    match op {
+        Operator::Lt => Operator::Gt,
+        Operator::Gt => Operator::Lt,
+        Operator::LtE => Operator::GtE,
+        Operator::GtE => Operator::LtE,
+        other => other.clone(),
+    }
+}
+
+/// Narrows `env` assuming `cond` is `taken`, `None` if that is impossible
+fn refine(cond: &Expr, taken: bool, env: &mut Env) -> Option<()> {
+    match cond {
+        // Both sides hold when the conjunction does
+        Expr::BinOp(left, Operator::And, right) if taken => {
+            refine(left, taken, env)?;
+            refine(right, taken, env)
+        }
+        Expr::BinOp(left, Operator::Or, right) if !taken => {
+            refine(left, taken, env)?;
+            refine(right, taken, env)
+        }
+        Expr::UnaryOp(UnaryOperator::Not, operand) => refine(operand, !taken, env),
+        Expr::BinOp(left, op, right) => {
+            let sides = [(left, op.clone(), right), (right, mirror(op), left)];
+            for (var, op, other) in sides {
+                let Expr::Var(var) = var.as_ref() else {
+                    continue;
+                };
+                let Some(other) = eval(other, env) else {
+                    continue;
+                };
+                let Some(range) = eval(&Expr::Var(var.clone()), env) else {
+                    continue;
+                };
+                let refined = refine_compare(range, &op, other, taken);
+                if refined.0 > refined.1 {
+                    return None;
+                }
+                env.insert(var.clone(), refined);
+            }
+            Some(())
+        }
+        _ => Some(()),
+    }
+}
+
+impl InferWidths {
+    fn collect_thresholds(&mut self, graph: &mut CFG) {
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            for expr in graph.get_node_mut(idx).referenced_exprs_mut() {
+                self.collect_expr_thresholds(expr);
+            }
+        }
+    }
+
+    fn collect_expr_thresholds(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Int(int) => {
+                if let Some(value) = int.to_i128() {
+                    self.thresholds.extend([
+                        value.saturating_sub(1),
+                        value,
+                        value.saturating_add(1),
+                    ]);
+                }
+            }
+            Expr::Var(_) => {}
+            Expr::BinOp(left, _, right) => {
+                self.collect_expr_thresholds(left);
+                self.collect_expr_thresholds(right);
+            }
+            Expr::UnaryOp(_, operand) => self.collect_expr_thresholds(operand),
+            Expr::Mux(cond, then, else_) => {
+                self.collect_expr_thresholds(cond);
+                self.collect_expr_thresholds(then);
+                self.collect_expr_thresholds(else_);
+            }
+        }
+    }
+
+    /// Widens a growing range to the nearest thresholds, or the whole type
+    fn widen(&self, var: &VarExpr, old: Range, new: Range) -> Range {
+        let (type_lo, type_hi) = type_range(var.int_type());
+        let lo = if new.0 < old.0 {
+            self.thresholds
+                .range(type_lo..=std::cmp::max(new.0, type_lo))
+                .next_back()
+                .copied()
+                .unwrap_or(type_lo)
+        } else {
+            old.0
+        };
+        let hi = if new.1 > old.1 {
+            self.thresholds
+                .range(std::cmp::min(new.1, type_hi)..=type_hi)
+                .next()
+                .copied()
+                .unwrap_or(type_hi)
+        } else {
+            old.1
+        };
+        (lo, hi)
+    }
+
+    /// Records that `var` may hold `values`, wrapping around its type
+    fn define(&mut self, env: &mut Env, var: &VarExpr, values: Option<Range>) {
+        let all = type_range(var.int_type());
+        let range = match values {
+            Some(range) if contains(all, range) => range,
+            _ => all,
+        };
+        env.insert(var.clone(), range);
+        let entry = self.ranges.entry(var.clone()).or_insert(range);
+        *entry = hull(*entry, range);
+    }
+
+    /// Applies the definitions of a node to `env`
+    fn transfer(&mut self, graph: &CFG, idx: NodeIndex, env: &mut Env) {
+        let node = graph.get_node(idx);
+        if let Some(AssignNode { lvalue, rvalue }) = AssignNode::concrete(node) {
+            let values = eval(rvalue, env);
+            self.define(env, lvalue, values);
+        } else if let Some(FuncNode { params }) = FuncNode::concrete(node) {
+            // Params bound by a call are defined on the edge
+            let called = graph
+                .preds(idx)
+                .any(|pred| CallNode::downcastable(graph.get_node(pred)));
+            if !called {
+                for param in params {
+                    self.define(env, param, None);
+                }
+            }
+        } else {
+            for var in node.declared_vars() {
+                self.define(env, var, None);
+            }
+        }
+    }
+
+    /// Environment at the start of `succ` when coming from `idx`, `None` if the edge is never taken
+    fn transfer_edge(
+        &mut self,
+        graph: &CFG,
+        idx: NodeIndex,
+        succ: NodeIndex,
+        env: &Env,
+    ) -> Option<Env> {
+        let mut env = env.clone();
+        let node = graph.get_node(idx);
+        if let Some(BranchNode { cond }) = BranchNode::concrete(node) {
+            if let Some(BranchEdge { condition }) = graph.get_edge(idx, succ)?.downcast_ref() {
+                refine(cond, *condition, &mut env)?;
+            }
+        }
+        if let (Some(CallNode { args }), Some(FuncNode { params })) = (
+            CallNode::concrete(node),
+            FuncNode::concrete(graph.get_node(succ)),
+        ) {
+            let values = args
+                .iter()
+                .map(|arg| eval(&Expr::Var(arg.clone()), &env))
+                .collect::<Vec<_>>();
+            for (param, values) in params.iter().zip(values) {
+                self.define(&mut env, param, values);
+            }
+        }
+        Some(env)
+    }
+
+    /// Joins `new` into `old`, returns whether `old` changed
+    fn join(&self, old: &mut Env, new: &Env, widen: bool) -> bool {
+        let mut changed = false;
+        for (var, &range) in new {
+            match old.get_mut(var) {
+                Some(existing) if contains(*existing, range) => {}
+                Some(existing) => {
+                    let joined = hull(*existing, range);
+                    *existing = if widen {
+                        self.widen(var, *existing, joined)
+                    } else {
+                        joined
+                    };
+                    changed = true;
+                }
+                None => {
+                    old.insert(var.clone(), range);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    pub(crate) fn propagate(&mut self, graph: &CFG) {
+        let entry = graph.get_entry();
+        let mut envs: BTreeMap<NodeIndex, Env> = BTreeMap::new();
+        let mut visits: BTreeMap<NodeIndex, usize> = BTreeMap::new();
+        envs.insert(entry, Env::new());
+        let mut worklist = VecDeque::from([entry]);
+        while let Some(idx) = worklist.pop_front() {
+            let mut env = envs[&idx].clone();
+            self.transfer(graph, idx, &mut env);
+            for succ in graph.succs(idx).collect::<BTreeSet<_>>() {
+                let Some(out) = self.transfer_edge(graph, idx, succ, &env) else {
+                    continue;
+                };
+                let changed = match envs.get_mut(&succ) {
+                    Some(existing) => {
+                        let count = visits.entry(succ).or_default();
+                        *count += 1;
+                        let widen = *count > WIDEN_AFTER;
+                        let mut joined = existing.clone();
+                        let changed = self.join(&mut joined, &out, widen);
+                        envs.insert(succ, joined);
+                        changed
+                    }
+                    None => {
+                        envs.insert(succ, out);
+                        true
+                    }
+                };
+                if changed && !worklist.contains(&succ) {
+                    worklist.push_back(succ);
+                }
+            }
+        }
+    }
+
+    /// Narrowest type of every variable that can be narrowed, inputs keep their type
+    pub(crate) fn narrowed(&self, graph: &CFG) -> BTreeMap<VarExpr, IntType> {
+        let inputs = graph.get_inputs().collect::<BTreeSet<_>>();
+        self.ranges
+            .iter()
+            .filter(|(var, _)| !inputs.contains(var) && var.size <= MAX_SIZE)
+            .filter_map(|(var, &range)| {
+                let int_type = fit(range);
+                if int_type.size < var.size {
+                    Some((var.clone(), int_type))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn write_back(&mut self, graph: &mut CFG) {
+        let narrowed = self.narrowed(graph);
+        let outputs = self.narrow_outputs(graph);
+        if narrowed.is_empty() && outputs == graph.outputs {
+            self.result = TransformResultType::no_work();
+            return;
+        }
+        graph.outputs = outputs;
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            let node = graph.get_node_mut(idx);
+            for var in node.referenced_vars_mut() {
+                if let Some(int_type) = narrowed.get(var) {
+                    var.set_int_type(*int_type);
+                }
+            }
+            for var in node.declared_vars_mut() {
+                if let Some(int_type) = narrowed.get(var) {
+                    var.set_int_type(*int_type);
+                }
+            }
+        }
+        self.result.did_work();
+    }
+
+    /// Unannotated outputs that are never negative get narrower ports,
+    /// since zero extension keeps their value for any consumer
+    fn narrow_outputs(&self, graph: &CFG) -> Vec<IntType> {
+        if !graph.outputs.is_empty() {
+            return graph.outputs.clone();
+        }
+        let mut outputs: Vec<Option<Range>> = vec![];
+        for idx in graph.nodes() {
+            let node = graph.get_node(idx);
+            let values = if let Some(YieldNode { values }) = YieldNode::concrete(node) {
+                values
+            } else if let Some(ReturnNode { values }) = ReturnNode::concrete(node) {
+                values
+            } else {
+                continue;
+            };
+            if outputs.len() < values.len() {
+                outputs.resize(values.len(), Some((0, 0)));
+            }
+            let ranges = values
+                .iter()
+                .map(|value| {
+                    let env = value
+                        .get_vars_iter()
+                        .filter_map(|var| Some((var.clone(), *self.ranges.get(var)?)))
+                        .collect::<Env>();
+                    eval(value, &env)
+                })
+                .collect::<Vec<_>>();
+            for (output, range) in outputs.iter_mut().zip(ranges) {
+                *output = match (*output, range) {
+                    (Some(a), Some(b)) => Some(hull(a, b)),
+                    _ => None,
+                };
+            }
+        }
+        let outputs = outputs
+            .into_iter()
+            .map(|range| match range {
+                Some(range) if range.0 >= 0 && fit(range).size < 32 => fit(range),
+                _ => IntType::default(),
+            })
+            .collect::<Vec<_>>();
+        if outputs
+            .iter()
+            .any(|int_type| *int_type != IntType::default())
+        {
+            outputs
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manager::PassManager, transform::*};
+
+    /// ```python
+    /// def func(n):
+    ///     i = 0
+    ///     while i < 10:
+    ///         yield i % 4, n
+    ///         i = i + 1
+    /// ```
+    fn make_counter() -> CFG {
+        let mut graph = CFG::default();
+        let i = VarExpr::new("i");
+        let n = VarExpr::new("n");
+
+        let entry = graph.add_node(FuncNode {
+            params: vec![n.clone()],
+        });
+        let init = graph.add_node(AssignNode {
+            lvalue: i.clone(),
+            rvalue: Expr::Int(IntExpr::new(0)),
+        });
+        graph.add_edge(entry, init, NoneEdge.into());
+        let cond = graph.add_node(BranchNode {
+            cond: Expr::BinOp(
+                Box::new(Expr::Var(i.clone())),
+                Operator::Lt,
+                Box::new(Expr::Int(IntExpr::new(10))),
+            ),
+        });
+        graph.add_edge(init, cond, NoneEdge.into());
+        let body = graph.add_node(YieldNode {
+            values: vec![
+                Expr::BinOp(
+                    Box::new(Expr::Var(i.clone())),
+                    Operator::Mod,
+                    Box::new(Expr::Int(IntExpr::new(4))),
+                ),
+                Expr::Var(n.clone()),
+            ],
+        });
+        graph.add_edge(cond, body, BranchEdge::new(true).into());
+        let incr = graph.add_node(AssignNode {
+            lvalue: i.clone(),
+            rvalue: Expr::BinOp(
+                Box::new(Expr::Var(i.clone())),
+                Operator::Add,
+                Box::new(Expr::Int(IntExpr::new(1))),
+            ),
+        });
+        graph.add_edge(body, incr, NoneEdge.into());
+        graph.add_edge(incr, cond, NoneEdge.into());
+        let exit = graph.add_node(ReturnNode { values: vec![] });
+        graph.add_edge(cond, exit, BranchEdge::new(false).into());
+        graph
+    }
+
+    #[test]
+    fn counter() {
+        let mut graph = make_counter();
+        let mut manager = PassManager::default();
+        manager.add_pass(InsertFuncNodes::transform);
+        manager.add_pass(InsertCallNodes::transform);
+        manager.add_pass(BraunEtAl::transform);
+        manager.apply(&mut graph);
+
+        InferWidths::transform(&mut graph);
+        // graph.write_dot("infer_widths.dot");
+
+        let mut types = BTreeMap::new();
+        for idx in graph.nodes() {
+            for var in graph.get_node(idx).declared_vars() {
+                types.insert(var.name.clone(), var.int_type());
+            }
+        }
+        // println!("{:?}", types);
+        let counters = types
+            .iter()
+            .filter(|(name, _)| name.starts_with("i."))
+            .map(|(_, int_type)| *int_type)
+            .collect::<Vec<_>>();
+        assert!(counters.iter().all(|int_type| !int_type.signed));
+        assert_eq!(counters.iter().map(|int_type| int_type.size).max(), Some(4));
+        assert_eq!(
+            graph.get_inputs().next().unwrap().int_type(),
+            IntType::default()
+        );
+        assert_eq!(
+            graph.outputs,
+            vec![IntType::new(2, false), IntType::default()]
+        );
+    }
+
+    /// ```python
+    /// def func(a: u200):
+    ///     b: u200 = a
+    ///     c = b >> 190
+    ///     yield b, c
+    /// ```
+    #[test]
+    fn wide_types() {
+        let mut graph = CFG::default();
+        let mut a = VarExpr::new("a");
+        a.set_int_type(IntType::new(200, false));
+        let b = a.with_name("b");
+        let c = VarExpr::new("c");
+
+        let entry = graph.add_node(FuncNode {
+            params: vec![a.clone()],
+        });
+        let copy = graph.add_node(AssignNode {
+            lvalue: b.clone(),
+            rvalue: Expr::Var(a.clone()),
+        });
+        graph.add_edge(entry, copy, NoneEdge.into());
+        let shift = graph.add_node(AssignNode {
+            lvalue: c.clone(),
+            rvalue: Expr::BinOp(
+                Box::new(Expr::Var(b.clone())),
+                Operator::RShift,
+                Box::new(Expr::Int(IntExpr::new(190))),
+            ),
+        });
+        graph.add_edge(copy, shift, NoneEdge.into());
+        let exit = graph.add_node(ReturnNode {
+            values: vec![Expr::Var(b.clone()), Expr::Var(c.clone())],
+        });
+        graph.add_edge(shift, exit, NoneEdge.into());
+
+        InferWidths::transform(&mut graph);
+
+        let mut types = BTreeMap::new();
+        for idx in graph.nodes() {
+            for var in graph.get_node(idx).declared_vars() {
+                types.insert(var.name.clone(), var.int_type());
+            }
+        }
+        // println!("{:?}", types);
+        assert_eq!(types["b"], IntType::new(200, false));
+        assert_eq!(types["c"], IntType::default());
+    }
+
+    #[test]
+    fn fit_ranges() {
+        assert_eq!(fit((0, 0)), IntType::new(1, false));
+        assert_eq!(fit((0, 255)), IntType::new(8, false));
+        assert_eq!(fit((-128, 127)), IntType::new(8, true));
+        assert_eq!(fit((-129, 0)), IntType::new(9, true));
+        assert_eq!(fit((-1, 1)), IntType::new(2, true));
+    }
+}
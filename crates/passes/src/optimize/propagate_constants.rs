@@ -0,0 +1,435 @@
+//! Sparse conditional constant propagation, after Wegman and Zadeck.
+//! Variables start out unknown and only stop being constant once a reachable definition
+//! disagrees, so constants carried around loops are found,
+//! and a branch on a constant only reaches the side it takes.
+//! Should be run after [crate::transform::BraunEtAl], where func node params act as phis
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use tohdl_ir::{expr::*, graph::*};
+
+use super::infer_widths::leaf_width;
+use crate::*;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    /// No reachable definition seen yet
+    Unknown,
+    Const(BigInt),
+    Varying,
+}
+
+fn meet(a: &Value, b: &Value) -> Value {
+    match (a, b) {
+        (Value::Unknown, other) | (other, Value::Unknown) => other.clone(),
+        (Value::Const(a), Value::Const(b)) if a == b => Value::Const(a.clone()),
+        _ => Value::Varying,
+    }
+}
+
+/// Value of an operator applied to constants, as the Verilog backend computes it.
+/// `None` if the result does not fit the width `expr` is evaluated at,
+/// or if the backend differs from unbounded arithmetic
+fn fold(expr: &Expr, operands: Vec<BigInt>) -> Option<BigInt> {
+    let int = |value: &BigInt| Box::new(Expr::Int(IntExpr::new(value.clone())));
+    let folded = match (expr, &operands[..]) {
+        (Expr::BinOp(_, op, _), [left, right]) => Expr::BinOp(int(left), op.clone(), int(right)),
+        (Expr::UnaryOp(op, _), [operand]) => Expr::UnaryOp(op.clone(), int(operand)),
+        _ => return None,
+    };
+    let value = tohdl_ir::interpret::eval(&folded, &BTreeMap::new()).ok()?;
+    let width = std::cmp::max(leaf_width(expr), 32);
+    if IntExpr::sized(value.clone(), width, true).value == value {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct PropagateConstants {
+    result: TransformResultType,
+    values: BTreeMap<VarExpr, Value>,
+    /// Nodes referencing each variable, revisited when its value drops
+    uses: BTreeMap<VarExpr, BTreeSet<NodeIndex>>,
+    /// Nodes reached through executable edges
+    reached: BTreeSet<NodeIndex>,
+    /// Edges found to be executable
+    edges: BTreeSet<(NodeIndex, NodeIndex)>,
+    worklist: VecDeque<NodeIndex>,
+}
+
+impl BasicTransform for PropagateConstants {
+    fn apply(&mut self, graph: &mut CFG) -> &TransformResultType {
+        self.propagate(graph);
+        if !self.rewrite(graph) {
+            self.result = TransformResultType::no_work();
+        }
+        &self.result
+    }
+}
+
+impl PropagateConstants {
+    fn value(&self, var: &VarExpr) -> Value {
+        // Variables without a definition may hold anything
+        self.values.get(var).cloned().unwrap_or(Value::Varying)
+    }
+
+    fn eval(&self, expr: &Expr) -> Value {
+        let operands = match expr {
+            Expr::Var(var) => return self.value(var),
+            Expr::Int(int) => return Value::Const(int.value.clone()),
+            Expr::Mux(cond, then, else_) => {
+                return match self.eval(cond) {
+                    Value::Unknown => Value::Unknown,
+                    Value::Const(cond) if cond != BigInt::default() => self.eval(then),
+                    Value::Const(_) => self.eval(else_),
+                    Value::Varying => meet(&self.eval(then), &self.eval(else_)),
+                }
+            }
+            Expr::BinOp(left, _, right) => vec![self.eval(left), self.eval(right)],
+            Expr::UnaryOp(_, operand) => vec![self.eval(operand)],
+        };
+        if operands.contains(&Value::Varying) {
+            return Value::Varying;
+        }
+        let operands = operands
+            .into_iter()
+            .map(|value| match value {
+                Value::Const(value) => Some(value),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        match operands {
+            Some(operands) => fold(expr, operands).map_or(Value::Varying, Value::Const),
+            None => Value::Unknown,
+        }
+    }
+
+    fn enqueue(&mut self, idx: NodeIndex) {
+        if !self.worklist.contains(&idx) {
+            self.worklist.push_back(idx);
+        }
+    }
+
+    /// Lowers the value of `var`, revisiting its reached uses if it changed
+    fn define(&mut self, var: &VarExpr, value: Value) {
+        let value = match value {
+            // Wraps on assignment
+            Value::Const(value) => Value::Const(IntExpr::sized(value, var.size, var.signed).value),
+            other => other,
+        };
+        let old = self.values.get(var).cloned().unwrap_or(Value::Unknown);
+        let new = meet(&old, &value);
+        if new != old {
+            self.values.insert(var.clone(), new);
+            for idx in self.uses.get(var).cloned().unwrap_or_default() {
+                if self.reached.contains(&idx) {
+                    self.enqueue(idx);
+                }
+            }
+        }
+    }
+
+    /// Marks an edge as executable
+    fn take(&mut self, idx: NodeIndex, succ: NodeIndex) {
+        if self.edges.insert((idx, succ)) {
+            self.reached.insert(succ);
+            // Func nodes meet over one more caller
+            self.enqueue(succ);
+        }
+    }
+
+    pub(crate) fn propagate(&mut self, graph: &CFG) {
+        for idx in graph.nodes() {
+            let node = graph.get_node(idx);
+            for var in node.declared_vars() {
+                self.values.insert(var.clone(), Value::Unknown);
+            }
+            for var in node.referenced_vars() {
+                self.uses.entry(var.clone()).or_default().insert(idx);
+            }
+        }
+        let entry = graph.get_entry();
+        self.reached.insert(entry);
+        self.enqueue(entry);
+        while let Some(idx) = self.worklist.pop_front() {
+            self.visit(graph, idx);
+        }
+    }
+
+    fn visit(&mut self, graph: &CFG, idx: NodeIndex) {
+        let node = graph.get_node(idx);
+        if let Some(FuncNode { params }) = FuncNode::concrete(node) {
+            let preds = graph.preds(idx).collect::<Vec<_>>();
+            let called = idx != graph.get_entry()
+                && !preds.is_empty()
+                && preds
+                    .iter()
+                    .all(|pred| CallNode::downcastable(graph.get_node(*pred)));
+            for (i, param) in params.iter().enumerate() {
+                let value = if called {
+                    preds
+                        .iter()
+                        .filter(|pred| self.edges.contains(&(**pred, idx)))
+                        .filter_map(|pred| CallNode::concrete(graph.get_node(*pred)))
+                        .map(|CallNode { args }| {
+                            args.get(i).map_or(Value::Varying, |arg| self.value(arg))
+                        })
+                        .fold(Value::Unknown, |a, b| meet(&a, &b))
+                } else {
+                    Value::Varying
+                };
+                self.define(param, value);
+            }
+        } else if let Some(AssignNode { lvalue, rvalue }) = AssignNode::concrete(node) {
+            let value = self.eval(rvalue);
+            self.define(lvalue, value);
+        } else if let Some(BranchNode { cond }) = BranchNode::concrete(node) {
+            let taken = match self.eval(cond) {
+                Value::Unknown => return,
+                Value::Const(value) => Some(value != BigInt::default()),
+                Value::Varying => None,
+            };
+            for succ in graph.succs(idx).collect::<Vec<_>>() {
+                let condition = graph
+                    .get_edge(idx, succ)
+                    .and_then(|edge| edge.downcast_ref::<BranchEdge>())
+                    .map(|edge| edge.condition);
+                if taken.is_none() || condition.is_none() || condition == taken {
+                    self.take(idx, succ);
+                }
+            }
+            return;
+        } else {
+            for var in node.declared_vars() {
+                self.define(var, Value::Varying);
+            }
+        }
+        let call = CallNode::downcastable(node);
+        for succ in graph.succs(idx).collect::<Vec<_>>() {
+            self.take(idx, succ);
+            // The args of a call may have dropped since its edge was taken
+            if call {
+                self.enqueue(succ);
+            }
+        }
+    }
+
+    /// Replaces constant variables and folds constant subexpressions.
+    /// A constant keeps the type of its variable, which backends extend the same way
+    fn simplify(&self, expr: &mut Expr) {
+        match expr {
+            Expr::Var(var) => {
+                if let Value::Const(value) = self.value(var) {
+                    *expr = Expr::Int(IntExpr::sized(value, var.size, var.signed));
+                }
+                return;
+            }
+            Expr::Int(_) => return,
+            Expr::BinOp(left, _, right) => {
+                self.simplify(left);
+                self.simplify(right);
+            }
+            Expr::UnaryOp(_, operand) => self.simplify(operand),
+            Expr::Mux(cond, then, else_) => {
+                self.simplify(cond);
+                self.simplify(then);
+                self.simplify(else_);
+                if let Expr::Int(int) = cond.as_ref() {
+                    let taken = if int.value != BigInt::default() {
+                        then
+                    } else {
+                        else_
+                    };
+                    *expr = taken.as_ref().clone();
+                }
+                return;
+            }
+        }
+        if let Value::Const(value) = self.eval(expr) {
+            *expr = Expr::Int(IntExpr::new(value));
+        }
+    }
+
+    /// Rewrites the graph with the propagated values, returns whether it changed
+    fn rewrite(&self, graph: &mut CFG) -> bool {
+        let mut changed = false;
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            if !self.reached.contains(&idx) {
+                graph.rmv_node(idx);
+                changed = true;
+                continue;
+            }
+            for expr in graph.get_node_mut(idx).referenced_exprs_mut() {
+                let old = expr.clone();
+                self.simplify(expr);
+                changed |= *expr != old;
+            }
+        }
+        // Branches whose condition folded to a constant
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            let taken = match BranchNode::concrete(graph.get_node(idx)) {
+                Some(BranchNode {
+                    cond: Expr::Int(int),
+                }) => int.value != BigInt::default(),
+                _ => continue,
+            };
+            let succs = graph.succs(idx).collect::<Vec<_>>();
+            let untaken = succs
+                .iter()
+                .filter(|succ| {
+                    graph
+                        .get_edge(idx, **succ)
+                        .and_then(|edge| edge.downcast_ref::<BranchEdge>())
+                        .is_some_and(|edge| edge.condition != taken)
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            if succs.len() == 2 && succs[0] == succs[1] {
+                // Both sides lead to the same node
+                graph.rmv_edge(idx, succs[0]);
+            } else {
+                for succ in untaken {
+                    graph.rmv_edge(idx, succ);
+                }
+            }
+            graph.rmv_node_and_reattach(idx);
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn optimize(text: &str) -> CFG {
+        let mut graph = ssa(text);
+        preserving(&mut graph, INPUTS, PropagateConstants::transform);
+        graph
+    }
+
+    #[test]
+    fn fold() {
+        let graph = optimize(
+            "cfg fold
+entry %0
+
+%0: func(n)
+%1: j = (10 + 15)
+%2: k = ((j * 2) - n)
+%3: yield (k, (1 << 23), (1 << 40), ((-8) >> 1))
+%4: return ()
+",
+        );
+        let text = graph.to_text();
+        assert!(text.contains("(50 - n"));
+        assert!(text.contains("8388608"));
+        // Would not fit the 32 bits the backend computes with
+        assert!(text.contains("(1 << 40)"));
+        // Arithmetic shift in Verilog too
+        assert!(text.contains("-4") && !text.contains(">>"));
+    }
+
+    #[test]
+    fn branch() {
+        let graph = optimize(
+            "cfg branch
+entry %0
+
+%0: func(n)
+%1: x = 1
+    -> %2
+
+%2: if (x < 2)
+    true -> %3
+    false -> %4
+
+%3: yield (n)
+    -> %5
+
+%4: yield ((n + 1))
+    -> %5
+
+%5: return ()
+",
+        );
+        assert_eq!(count::<BranchNode>(&graph), 0);
+        assert_eq!(count::<YieldNode>(&graph), 1);
+    }
+
+    #[test]
+    fn phi() {
+        // `k` is the same on every iteration, `i` is not
+        let graph = optimize(
+            "cfg phi
+entry %0
+
+%0: func(n)
+%1: i = 0
+%2: k = 5
+    -> %3
+
+%3: if (i < n)
+    true -> %4
+    false -> %6
+
+%4: yield ((i + k))
+%5: i = (i + 1)
+    -> %3
+
+%6: if (k == 5)
+    true -> %7
+    false -> %8
+
+%7: return (k)
+
+%8: return (0)
+",
+        );
+        let text = graph.to_text();
+        assert!(text.contains("+ 5)"));
+        assert_eq!(count::<BranchNode>(&graph), 1);
+        assert_eq!(count::<ReturnNode>(&graph), 1);
+    }
+
+    #[test]
+    fn nested_loops() {
+        // `s` only varies once the inner loop runs, after `i` has varied
+        let text = "cfg nested_loops
+entry %0
+
+%0: func(n)
+%1: i = 0
+%2: s = 0
+    -> %3
+
+%3: if (i < n)
+    true -> %4
+    false -> %9
+
+%4: j = 0
+    -> %5
+
+%5: if (j < i)
+    true -> %6
+    false -> %8
+
+%6: s = (s + (i * j))
+%7: j = (j + 1)
+    -> %5
+
+%8: i = (i + 1)
+    -> %3
+
+%9: yield (s)
+%10: return ()
+";
+        let graph = optimize(text);
+        // Nothing is constant, so nothing is folded
+        assert_eq!(CFG::graph_diff(&graph, &ssa(text), false), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! Replaces multiplies, divides and modulos by constants with cheaper operators.
+//! Powers of two become shifts and masks, other multiplies shifts and adds,
+//! and other divides a signed multiply by a fixed-point reciprocal, see Granlund and Montgomery.
+//! Should be run after [super::InferWidths], reciprocals being exact for dividends
+//! as wide as the types of their leaves allow, or 32 bits.
+//! Dividends of unbounded range are left alone
+
+use tohdl_ir::{expr::*, graph::*};
+
+use super::infer_widths::{leaf_width, value_width};
+use crate::*;
+
+#[derive(Default)]
+pub struct ReduceStrength {
+    result: TransformResultType,
+    reduced: usize,
+}
+
+impl BasicTransform for ReduceStrength {
+    fn apply(&mut self, graph: &mut CFG) -> &TransformResultType {
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            for expr in graph.get_node_mut(idx).referenced_exprs_mut() {
+                self.reduce(expr);
+            }
+        }
+        if self.reduced == 0 {
+            self.result = TransformResultType::no_work();
+        }
+        self.result.count("reduced operators", self.reduced);
+        &self.result
+    }
+}
+
+fn int(value: i128) -> Expr {
+    Expr::Int(IntExpr::new(value))
+}
+
+fn bin(left: Expr, op: Operator, right: Expr) -> Expr {
+    Expr::BinOp(Box::new(left), op, Box::new(right))
+}
+
+fn neg(expr: Expr) -> Expr {
+    Expr::UnaryOp(UnaryOperator::Neg, Box::new(expr))
+}
+
+fn shl(expr: &Expr, shift: u32) -> Expr {
+    match shift {
+        0 => expr.clone(),
+        _ => bin(expr.clone(), Operator::LShift, int(shift.into())),
+    }
+}
+
+/// Constant no wider than the 32 bits operators are evaluated at,
+/// so that replacing it does not narrow the Verilog expression
+fn constant(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Int(int) if int.size <= 32 => int.to_i128(),
+        _ => None,
+    }
+}
+
+fn nonnegative(expr: &Expr) -> bool {
+    matches!(expr, Expr::Var(var) if !var.signed)
+}
+
+/// Signed width holding the values of `x`, both unbounded as in Python
+/// and wrapped around the width Verilog evaluates it at
+fn dividend_width(x: &Expr) -> Option<usize> {
+    let width = std::cmp::max(value_width(x)?, leaf_width(x));
+    Some(std::cmp::max(width, 32))
+}
+
+/// Digits of `value` in non-adjacent form, as signs and shifts from the lowest
+fn non_adjacent_form(mut value: i128) -> Vec<(bool, u32)> {
+    let mut digits = vec![];
+    let mut shift = 0;
+    while value != 0 {
+        if value % 2 != 0 {
+            let negative = value % 4 == 3;
+            value += if negative { 1 } else { -1 };
+            digits.push((negative, shift));
+        }
+        value /= 2;
+        shift += 1;
+    }
+    digits
+}
+
+/// `x * c` as shifts and adds
+fn multiply(x: &Expr, c: i128) -> Expr {
+    if c == 0 {
+        return int(0);
+    }
+    // The highest digit is positive
+    let mut digits = non_adjacent_form(c.abs()).into_iter().rev();
+    let (_, shift) = digits.next().unwrap();
+    let mut ret = shl(x, shift);
+    for (negative, shift) in digits {
+        let op = if negative {
+            Operator::Sub
+        } else {
+            Operator::Add
+        };
+        ret = bin(ret, op, shl(x, shift));
+    }
+    if c < 0 {
+        neg(ret)
+    } else {
+        ret
+    }
+}
+
+/// `x / c`, truncating
+fn divide(x: &Expr, c: i128) -> Option<Expr> {
+    let d = c.unsigned_abs();
+    if d == 0 {
+        return None;
+    }
+    let width = dividend_width(x)?;
+    // -1 if `x` is negative, 0 otherwise
+    let sign = bin(x.clone(), Operator::RShift, int(width as i128 - 1));
+    let quotient = if d == 1 {
+        x.clone()
+    } else if d.is_power_of_two() {
+        // Negative dividends are biased by d - 1 to round towards zero
+        let biased = if nonnegative(x) {
+            x.clone()
+        } else {
+            let bias = bin(sign, Operator::BitAnd, int(d as i128 - 1));
+            bin(x.clone(), Operator::Add, bias)
+        };
+        bin(biased, Operator::RShift, int(d.trailing_zeros().into()))
+    } else {
+        // floor(n * m / 2^s) - sign(n) == n / d for all n of the width
+        let shift = width + (d - 1).ilog2() as usize;
+        let m = (BigInt::from(1) << shift) / d + 1;
+        // Wide enough for Verilog to keep the whole product
+        let m = Expr::Int(IntExpr::sized(m, 2 * width + 1, true));
+        let high = bin(
+            bin(x.clone(), Operator::Mul, m),
+            Operator::RShift,
+            int(shift as i128),
+        );
+        if nonnegative(x) {
+            high
+        } else {
+            bin(high, Operator::Sub, sign)
+        }
+    };
+    Some(if c < 0 { neg(quotient) } else { quotient })
+}
+
+/// `x % c`, with the sign of `x`
+fn modulo(x: &Expr, c: i128) -> Option<Expr> {
+    let d = c.unsigned_abs();
+    if d == 0 {
+        return None;
+    }
+    if d == 1 {
+        return Some(int(0));
+    }
+    let d = d as i128;
+    if d.count_ones() == 1 && nonnegative(x) {
+        return Some(bin(x.clone(), Operator::BitAnd, int(d - 1)));
+    }
+    let quotient = divide(x, d)?;
+    Some(bin(x.clone(), Operator::Sub, multiply(&quotient, d)))
+}
+
+impl ReduceStrength {
+    fn reduce(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Var(_) | Expr::Int(_) => return,
+            Expr::BinOp(left, _, right) => {
+                self.reduce(left);
+                self.reduce(right);
+            }
+            Expr::UnaryOp(_, operand) => self.reduce(operand),
+            Expr::Mux(cond, then, else_) => {
+                self.reduce(cond);
+                self.reduce(then);
+                self.reduce(else_);
+            }
+        }
+        let Expr::BinOp(left, op, right) = &*expr else {
+            return;
+        };
+        let reduced = match (op, constant(left), constant(right)) {
+            (Operator::Mul, Some(c), None) => Some(multiply(right, c)),
+            (Operator::Mul, None, Some(c)) => Some(multiply(left, c)),
+            (Operator::Div, None, Some(c)) => divide(left, c),
+            (Operator::Mod, None, Some(c)) => modulo(left, c),
+            _ => None,
+        };
+        if let Some(reduced) = reduced {
+            *expr = reduced;
+            self.reduced += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimize::PropagateConstants;
+    use crate::tests::*;
+
+    #[test]
+    fn non_adjacent_form() {
+        assert_eq!(super::non_adjacent_form(7), vec![(true, 0), (false, 3)]);
+        assert_eq!(super::non_adjacent_form(10), vec![(false, 1), (false, 3)]);
+    }
+
+    #[test]
+    fn reduce() {
+        let mut graph = ssa("cfg reduce
+entry %0
+
+%0: func(n)
+%1: yield ((n * 8), (n * 7), ((-6) * n), (n * 0))
+%2: yield ((n / 8), (n % 8), (n / (-4)), (n % (-4)))
+%3: yield ((n / 10), (n % 10), (n / (-3)), (n % (-3)), ((n * 3) / 7))
+%4: return ()
+");
+        PropagateConstants::transform(&mut graph);
+        let limit = i32::MAX as i64;
+        let values = [
+            -limit - 1,
+            -1000,
+            -17,
+            -10,
+            -9,
+            -8,
+            -1,
+            0,
+            1,
+            7,
+            8,
+            9,
+            10,
+            12345,
+            limit,
+        ];
+        let inputs = values.iter().map(std::slice::from_ref).collect::<Vec<_>>();
+        let result = preserving(&mut graph, &inputs, ReduceStrength::transform);
+        let text = graph.to_text();
+        // println!("{}", text);
+        assert_eq!(result.counts["reduced operators"], 14);
+        assert!(!text.contains(" / ") && !text.contains(" % "));
+        assert!(!text.contains("* 8)") && !text.contains("* 7)"));
+    }
+}
@@ -6,12 +6,15 @@ mod braun_et_al;
 mod insert_call;
 mod insert_func;
 mod insert_phi;
+mod iterative_division;
 mod lower_to_fsm;
 mod make_ssa;
 mod nonblocking;
+mod pipeline_loops;
 mod explicit_return;
 mod fix_branch;
 mod rename_variables;
+mod schedule;
 
 pub use fix_branch::FixBranch;
 pub use explicit_return::ExplicitReturn;
@@ -19,7 +22,10 @@ pub use braun_et_al::BraunEtAl;
 pub use insert_call::InsertCallNodes;
 pub use insert_func::InsertFuncNodes;
 pub use insert_phi::InsertPhi;
+pub use iterative_division::IterativeDivision;
 pub use lower_to_fsm::LowerToFsm;
 pub use make_ssa::MakeSSA;
 pub use nonblocking::Nonblocking;
+pub use pipeline_loops::{PipelineLoops, Pipelining};
 pub use rename_variables::RenameVariables;
+pub use schedule::{DelayModel, Schedule, Timing};
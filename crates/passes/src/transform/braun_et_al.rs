@@ -109,7 +109,7 @@ impl BraunEtAl {
         let count = *self.var_counter.get(var).unwrap_or(&0);
         self.var_counter.insert(var.clone(), count + 1);
         let name = format!("{}.{}", var.name, count);
-        VarExpr::new(&name)
+        var.with_name(&name)
     }
 
     /// Adds a new phi variable
@@ -119,7 +119,7 @@ impl BraunEtAl {
 
         // println!("new phi {} {}", block, var);
         let name = format!("{}.{}", var.name, count);
-        let new_var = VarExpr::new(&name);
+        let new_var = var.with_name(&name);
 
         if let Some(FuncNode { params }) = FuncNode::concrete_mut(graph.get_node_mut(*block)) {
             params.push(new_var.clone())
@@ -0,0 +1,300 @@
+//! Lowers division and modulo by a non-constant into a restoring divider loop,
+//! which [super::LowerToFsm] turns into one state per quotient bit, instead of a combinational `/`.
+//! The loop runs on magnitudes, its result then takes the sign division truncating to zero gives.
+//! Should be ran after [super::BraunEtAl], each division being computed right before
+//! the node it is in, and after [crate::optimize::ReduceStrength] so that constant divisors are left alone
+
+use tohdl_ir::{expr::*, graph::*};
+
+use crate::optimize::leaf_width;
+use crate::*;
+
+#[derive(Default)]
+pub struct IterativeDivision {
+    result: TransformResultType,
+    count: usize,
+}
+
+impl BasicTransform for IterativeDivision {
+    fn apply(&mut self, graph: &mut CFG) -> &TransformResultType {
+        while let Some((idx, dividend, op, divisor, var)) = self.take_division(graph) {
+            self.expand(graph, idx, &dividend, &op, &divisor, var);
+        }
+        if self.count == 0 {
+            self.result = TransformResultType::no_work();
+        }
+        self.result.count("iterative divisions", self.count);
+        &self.result
+    }
+}
+
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Var(_) | Expr::Int(_) => vec![],
+        Expr::BinOp(left, _, right) => vec![left, right],
+        Expr::UnaryOp(_, operand) => vec![operand],
+        Expr::Mux(cond, then, else_) => vec![cond, then, else_],
+    }
+}
+
+fn children_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::Var(_) | Expr::Int(_) => vec![],
+        Expr::BinOp(left, _, right) => vec![left, right],
+        Expr::UnaryOp(_, operand) => vec![operand],
+        Expr::Mux(cond, then, else_) => vec![cond, then, else_],
+    }
+}
+
+/// Constant divisors are left to synthesis, or to [crate::optimize::ReduceStrength]
+fn is_division(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::BinOp(_, Operator::Div | Operator::Mod, right) if !matches!(**right, Expr::Int(_))
+    )
+}
+
+fn has_division(expr: &Expr) -> bool {
+    is_division(expr) || children(expr).into_iter().any(has_division)
+}
+
+/// Division with no division in its operands
+fn innermost_division(expr: &mut Expr) -> Option<&mut Expr> {
+    match children(expr).into_iter().position(has_division) {
+        Some(i) => innermost_division(children_mut(expr).swap_remove(i)),
+        None if is_division(expr) => Some(expr),
+        None => None,
+    }
+}
+
+/// Bits of the magnitude of `expr`, from the var's size,
+/// or as wide as the arithmetic it is computed with
+fn magnitude_width(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var(var) => var.size,
+        _ => std::cmp::max(leaf_width(expr), 32),
+    }
+}
+
+fn int(value: usize) -> Expr {
+    Expr::Int(IntExpr::new(value as i64))
+}
+
+fn bin(left: Expr, op: Operator, right: Expr) -> Expr {
+    Expr::BinOp(Box::new(left), op, Box::new(right))
+}
+
+fn mux(cond: Expr, then: Expr, else_: Expr) -> Expr {
+    Expr::Mux(Box::new(cond), Box::new(then), Box::new(else_))
+}
+
+fn negative(expr: &Expr) -> Expr {
+    bin(expr.clone(), Operator::Lt, int(0))
+}
+
+/// `-expr` if `cond`, `expr` otherwise
+fn negate_if(cond: Expr, expr: &Expr) -> Expr {
+    mux(
+        cond,
+        Expr::UnaryOp(UnaryOperator::Neg, Box::new(expr.clone())),
+        expr.clone(),
+    )
+}
+
+/// `|expr|` computed `size` bits wide, where the most negative value of
+/// the width `expr` is evaluated at does not wrap around
+fn magnitude(expr: &Expr, size: usize) -> Expr {
+    let zero = Expr::Int(IntExpr::sized(0, size, true));
+    mux(
+        negative(expr),
+        bin(zero, Operator::Sub, expr.clone()),
+        expr.clone(),
+    )
+}
+
+impl IterativeDivision {
+    /// Replaces the first division found with a new var, returning where it was
+    fn take_division(
+        &mut self,
+        graph: &mut CFG,
+    ) -> Option<(NodeIndex, Expr, Operator, Expr, VarExpr)> {
+        for idx in graph.nodes().collect::<Vec<_>>() {
+            for expr in graph.get_node_mut(idx).referenced_exprs_mut() {
+                let Some(division) = innermost_division(expr) else {
+                    continue;
+                };
+                let Expr::BinOp(dividend, op, divisor) = division.clone() else {
+                    unreachable!()
+                };
+                let width =
+                    std::cmp::max(magnitude_width(&dividend), magnitude_width(&divisor)) + 2;
+                let var = VarExpr::builder()
+                    .name(format!("__div{}", self.count))
+                    .size(width)
+                    .signed(true)
+                    .build();
+                *division = Expr::Var(var.clone());
+                return Some((idx, *dividend, op, *divisor, var));
+            }
+        }
+        None
+    }
+
+    /// Computes `var = dividend op divisor` before `idx`
+    fn expand(
+        &mut self,
+        graph: &mut CFG,
+        idx: NodeIndex,
+        dividend: &Expr,
+        op: &Operator,
+        divisor: &Expr,
+        var: VarExpr,
+    ) {
+        let bits = magnitude_width(dividend);
+        let new_var = |name: &str, size: usize| {
+            VarExpr::builder()
+                .name(format!("__div{}_{}", self.count, name))
+                .size(size)
+                .signed(true)
+                .build()
+        };
+        let n = new_var("n", var.size);
+        let d = new_var("d", var.size);
+        let [q0, q1, q2] = ["q.0", "q.1", "q.2"].map(|name| new_var(name, var.size));
+        let [r0, r1, r2, r3] = ["r.0", "r.1", "r.2", "r.3"].map(|name| new_var(name, var.size));
+        let [i0, i1, i2] = ["i.0", "i.1", "i.2"].map(|name| new_var(name, 32));
+        let t = new_var("t", var.size);
+        let assign = |lvalue: &VarExpr, rvalue: Expr| AssignNode {
+            lvalue: lvalue.clone(),
+            rvalue,
+        };
+        let v = |var: &VarExpr| Expr::Var(var.clone());
+
+        // Each insertion goes between the last one and `idx`
+        let entry = graph.entry == idx;
+        let first = graph.insert_node_before(
+            assign(&n, magnitude(dividend, n.size)),
+            idx,
+            NoneEdge.into(),
+        );
+        if entry {
+            graph.set_entry(first);
+        }
+        for node in [
+            assign(&d, magnitude(divisor, d.size)),
+            assign(&q0, int(0)),
+            assign(&r0, int(0)),
+            assign(&i0, int(bits)),
+        ] {
+            graph.insert_node_before(node, idx, NoneEdge.into());
+        }
+        graph.insert_node_before(
+            CallNode {
+                args: vec![q0, r0, i0],
+            },
+            idx,
+            NoneEdge.into(),
+        );
+        let header = graph.insert_node_before(
+            FuncNode {
+                params: vec![q1.clone(), r1.clone(), i1.clone()],
+            },
+            idx,
+            NoneEdge.into(),
+        );
+        let branch = graph.insert_node_before(
+            BranchNode {
+                cond: bin(v(&i1), Operator::Gt, int(0)),
+            },
+            idx,
+            BranchEdge::new(false).into(),
+        );
+        let result = match op {
+            Operator::Div => negate_if(
+                bin(negative(dividend), Operator::NotEq, negative(divisor)),
+                &v(&q1),
+            ),
+            _ => negate_if(negative(dividend), &v(&r1)),
+        };
+        graph.insert_node_before(assign(&var, result), idx, NoneEdge.into());
+
+        // Shifts in the next bit of the dividend, and subtracts the divisor if it fits
+        let fits = bin(v(&t), Operator::GtE, int(0));
+        let body: Vec<Box<dyn Node>> = vec![
+            assign(&i2, bin(v(&i1), Operator::Sub, int(1))).into(),
+            assign(
+                &r2,
+                bin(
+                    bin(v(&r1), Operator::LShift, int(1)),
+                    Operator::BitOr,
+                    bin(
+                        bin(v(&n), Operator::RShift, v(&i2)),
+                        Operator::BitAnd,
+                        int(1),
+                    ),
+                ),
+            )
+            .into(),
+            assign(&t, bin(v(&r2), Operator::Sub, v(&d))).into(),
+            assign(
+                &q2,
+                bin(
+                    bin(v(&q1), Operator::LShift, int(1)),
+                    Operator::BitOr,
+                    mux(fits.clone(), int(1), int(0)),
+                ),
+            )
+            .into(),
+            assign(&r3, mux(fits, v(&t), v(&r2))).into(),
+            CallNode {
+                args: vec![q2, r3, i2],
+            }
+            .into(),
+        ];
+        let mut last = branch;
+        for (i, node) in body.into_iter().enumerate() {
+            let node = graph.add_node_boxed(node);
+            let edge = match i {
+                0 => BranchEdge::new(true).into(),
+                _ => NoneEdge.into(),
+            };
+            graph.add_edge(last, node, edge);
+            last = node;
+        }
+        graph.add_edge(last, header, NoneEdge.into());
+        self.count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn divide() {
+        let mut graph = ssa("cfg divide
+entry %0
+
+%0: func(a, b)
+%1: yield ((a / b), (a % b), ((a / b) / (b + 1)))
+%2: return ((a / 3))
+");
+        let inputs: &[&[i64]] = &[
+            &[7, 2],
+            &[-7, 2],
+            &[7, -2],
+            &[-7, -2],
+            &[100, 7],
+            &[0, 5],
+            &[5, 9],
+            &[-2147483648, 3],
+            &[7, -2147483648],
+            &[-2147483648, -2147483648],
+        ];
+        let result = preserving(&mut graph, inputs, IterativeDivision::transform);
+        assert_eq!(result.counts["iterative divisions"], 4);
+        assert!(!graph.to_text().contains(" / b"));
+        assert!(graph.to_text().contains("(a / 3)"));
+    }
+}
@@ -16,7 +16,7 @@ pub struct LowerToFsm {
     // Maps idx (in original) to subgraph
     pub node_to_subgraph: BTreeMap<NodeIndex, usize>,
 
-    // Recommended breakpoints (e.g. header of loops)
+    // Recommended breakpoints (e.g. header of loops), added to those set before applying
     pub recommended_breakpoints: Vec<NodeIndex>,
 
     // Call nodes immediately before yield nodes
@@ -186,7 +186,9 @@ impl LowerToFsm {
                 let mut test_graph = reference_graph.clone();
                 test_graph.set_entry(successor);
 
-                let result = BraunEtAl::find_external_vars(&mut test_graph.clone(), successor);
+                // Sorted, as are the params the successor's state adds for them
+                let mut result = BraunEtAl::find_external_vars(&mut test_graph.clone(), successor);
+                result.sort();
                 // println!("extern vars result {:?}", result);
                 // println!("successor: {}", reference_graph.get_node(successor));
 
@@ -241,6 +243,19 @@ impl LowerToFsm {
         map
     }
 
+    /// Sorts the params that SSA added to the entry of a state for vars defined before it,
+    /// so that they are in the order calls to it pass them
+    fn sort_external_params(reference_graph: &CFG, src: NodeIndex, new_graph: &mut CFG) {
+        let count = FuncNode::concrete(reference_graph.get_node(src))
+            .map_or(0, |func| func.params.len());
+        let entry = new_graph.get_entry();
+        if let Some(FuncNode { params }) = FuncNode::concrete_mut(new_graph.get_node_mut(entry)) {
+            if params.len() > count {
+                params[count..].sort();
+            }
+        }
+    }
+
     /// Mark preds of yield nodes
     fn mark_call_before_term(&self, visited: &mut BTreeMap<NodeIndex, usize>) {
         for node in &self.call_node_before_yield {
@@ -261,7 +276,7 @@ impl BasicTransform for LowerToFsm {
 
         // println!("recommended_breakpoints: {:#?}", recommended_breakpoints);
 
-        self.recommended_breakpoints = recommended_breakpoints;
+        self.recommended_breakpoints.extend(recommended_breakpoints);
 
         self.call_node_before_yield = self.before_yield_nodes(graph);
 
@@ -287,6 +302,7 @@ impl BasicTransform for LowerToFsm {
             );
 
             transform::BraunEtAl::transform(&mut new_graph);
+            Self::sort_external_params(graph, node_idx, &mut new_graph);
 
             self.node_to_subgraph.insert(node_idx, self.subgraphs.len());
             self.subgraphs.push(new_graph);
@@ -374,4 +390,48 @@ mod tests {
             // subgraph.write_dot(format!("lower_to_fsm_{}.dot", i).as_str());
         }
     }
+
+    #[test]
+    fn external_var_order() {
+        // The loop's state reads `d` before `a` and `b`, but they are passed in sorted order
+        let mut graph = ssa(
+            "cfg order
+entry %0
+
+%0: func(a, b)
+%1: n = (a + 1)
+%2: d = (b + 1)
+%3: i = 0
+    -> %4
+
+%4: if (i < n)
+    true -> %5
+    false -> %6
+
+%5: yield (d)
+%7: i = (i + 1)
+    -> %4
+
+%6: return ((a + b))
+",
+        );
+        let mut lower = LowerToFsm::default();
+        lower.apply(&mut graph);
+
+        // Vars are renamed in each state, but keep their names before the first '.'
+        let names = |vars: &[VarExpr]| {
+            vars.iter()
+                .map(|var| var.name.split('.').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        for (i, subgraph) in lower.subgraphs.iter().enumerate() {
+            for (call, state) in lower.get_external_funcs(i) {
+                let CallNode { args } = CallNode::concrete(subgraph.get_node(call)).unwrap();
+                let target = &lower.subgraphs[state];
+                let FuncNode { params } =
+                    FuncNode::concrete(target.get_node(target.get_entry())).unwrap();
+                assert_eq!(names(args), names(params));
+            }
+        }
+    }
 }
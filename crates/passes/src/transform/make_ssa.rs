@@ -134,7 +134,7 @@ impl MakeSSA {
         self.var_counter.insert(var.clone(), count + 1);
 
         let name = format!("{}.{}", var.name, count);
-        let new_var = VarExpr::new(&name);
+        let new_var = var.with_name(&name);
 
         // Update var stack
         let stack = self.stacks.entry(var.clone()).or_default();
@@ -363,7 +363,7 @@ def even_fib():
         i = i + 1
     return 0
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
@@ -425,7 +425,7 @@ def even_fib():
         i = i + 1
     return 0
 "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let graph = visitor.get_graph();
 
@@ -486,7 +486,7 @@ def even_fib(b, d):
     y = b
     z = c
     "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
         let mut graph = visitor.get_graph();
 
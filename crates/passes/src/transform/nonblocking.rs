@@ -103,7 +103,7 @@ mod tests {
     //     b = a
     //     yield b
     // "#;
-    //         let visitor = tohdl_frontend::AstVisitor::from_text(code);
+    //         let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
 
     //         let graph = visitor.get_graph();
 
@@ -0,0 +1,512 @@
+//! Software pipelines innermost while loops, so that an iteration starts
+//! before the last one is done with its states.
+//! An iteration is split into two stages at its state breaks, and each state of the kernel
+//! runs a state of the last iteration's second stage before the same state of the next one's first.
+//! After [super::Nonblocking] both read what the registers were at the start of the cycle,
+//! so their paths are side by side, not chained. A yield stalling on `__ready` stalls the whole state,
+//! and so every iteration in flight with it.
+//! The first stage of the first iteration is the prologue, the rest of the second stage
+//! of the last iteration the epilogue, ran on the way out.
+//! The initiation interval is the requested one, raised until the next iteration
+//! does not depend on the second stage, and each kernel state has at most one yield.
+//! Should be ran after [super::Schedule], right before [super::LowerToFsm],
+//! which is to be given [Pipelining::breaks]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use tohdl_ir::{expr::*, graph::*};
+
+use crate::algorithms::loop_detector::{detect_nested_loops, Loop};
+use crate::*;
+
+#[derive(Debug, Clone)]
+pub struct Pipelining {
+    /// Initiation interval to aim for, in states between the starts of two iterations
+    pub ii: usize,
+    /// Call nodes to start a state after, those of [super::Timing::breaks] and those added
+    pub breaks: Vec<NodeIndex>,
+    /// Initiation interval achieved for each loop, by the header of its prologue,
+    /// as many states as an iteration has for loops left alone
+    pub achieved: BTreeMap<NodeIndex, usize>,
+}
+
+impl Pipelining {
+    pub fn new(ii: usize, breaks: Vec<NodeIndex>) -> Self {
+        Self {
+            ii,
+            breaks,
+            achieved: BTreeMap::new(),
+        }
+    }
+}
+
+/// Loop whose header only branches and whose body goes straight to its only latch
+struct Shape {
+    header: NodeIndex,
+    branch: NodeIndex,
+    /// Condition of the branch edge into the body
+    enters: bool,
+    body: NodeIndex,
+    exit: NodeIndex,
+    latch: NodeIndex,
+    /// Assign and yield nodes of each state of an iteration
+    segments: Vec<Vec<NodeIndex>>,
+    /// Nodes after the branch, up to and with the latch
+    members: Vec<NodeIndex>,
+}
+
+#[derive(Default)]
+pub struct PipelineLoops {
+    result: TransformResultType,
+}
+
+impl ContextfulTransfrom<Pipelining> for PipelineLoops {
+    fn apply_contextful(
+        &mut self,
+        graph: &mut CFG,
+        pipelining: &mut Pipelining,
+    ) -> &TransformResultType {
+        let mut breaks = pipelining.breaks.iter().copied().collect::<BTreeSet<_>>();
+        let mut pipelined = 0;
+        for l in detect_nested_loops(graph) {
+            let Some(shape) = Self::shape(graph, &l, &breaks) else {
+                continue;
+            };
+            let ii = Self::initiation_interval(graph, &shape, pipelining.ii);
+            if ii < shape.segments.len() {
+                Self::pipeline(graph, &shape, ii, &mut breaks);
+                pipelined += 1;
+            }
+            pipelining.achieved.insert(shape.header, ii);
+        }
+
+        if pipelined == 0 {
+            self.result = TransformResultType::no_work();
+        }
+        self.result.count("pipelined loops", pipelined);
+        self.result.count(
+            "initiation interval",
+            pipelining.achieved.values().copied().max().unwrap_or(0),
+        );
+        pipelining.breaks = breaks.into_iter().collect();
+        &self.result
+    }
+}
+
+/// `vars` with `tag` appended to their names
+fn tagged<'a>(
+    vars: impl IntoIterator<Item = &'a VarExpr>,
+    tag: &str,
+) -> BTreeMap<VarExpr, VarExpr> {
+    vars.into_iter()
+        .map(|var| (var.clone(), var.with_name(&format!("{}.{tag}", var.name))))
+        .collect()
+}
+
+fn renamed(vars: &[VarExpr], names: &BTreeMap<VarExpr, VarExpr>) -> Vec<VarExpr> {
+    vars.iter()
+        .map(|var| names.get(var).unwrap_or(var).clone())
+        .collect()
+}
+
+fn rename(node: &mut Box<dyn Node>, names: &BTreeMap<VarExpr, VarExpr>) {
+    for var in node.referenced_vars_mut() {
+        if let Some(name) = names.get(var) {
+            *var = name.clone();
+        }
+    }
+    for var in node.declared_vars_mut() {
+        if let Some(name) = names.get(var) {
+            *var = name.clone();
+        }
+    }
+}
+
+fn declared(graph: &CFG, segments: &[Vec<NodeIndex>]) -> BTreeSet<VarExpr> {
+    segments
+        .iter()
+        .flatten()
+        .flat_map(|idx| graph.get_node(*idx).declared_vars())
+        .cloned()
+        .collect()
+}
+
+fn yields(graph: &CFG, segment: &[NodeIndex]) -> usize {
+    segment
+        .iter()
+        .filter(|idx| YieldNode::downcastable(graph.get_node(**idx)))
+        .count()
+}
+
+/// Appends nodes one after the other
+struct Chain {
+    last: NodeIndex,
+    /// Edge to the next node appended
+    edge: Box<dyn Edge>,
+}
+
+impl Chain {
+    fn new(last: NodeIndex, edge: Box<dyn Edge>) -> Self {
+        Self { last, edge }
+    }
+
+    fn push(&mut self, graph: &mut CFG, node: Box<dyn Node>) -> NodeIndex {
+        let idx = graph.add_node_boxed(node);
+        let edge = std::mem::replace(&mut self.edge, NoneEdge.into());
+        graph.add_edge(self.last, idx, edge);
+        self.last = idx;
+        idx
+    }
+
+    fn copy(&mut self, graph: &mut CFG, nodes: &[NodeIndex], names: &BTreeMap<VarExpr, VarExpr>) {
+        for &idx in nodes {
+            let mut node = graph.get_node(idx).clone();
+            rename(&mut node, names);
+            self.push(graph, node);
+        }
+    }
+
+    /// Starts a new state, returning the call to break at
+    fn split(&mut self, graph: &mut CFG) -> NodeIndex {
+        let call = self.push(graph, CallNode { args: vec![] }.into());
+        self.push(graph, FuncNode { params: vec![] }.into());
+        call
+    }
+
+    fn end(self, graph: &mut CFG, target: NodeIndex) {
+        graph.add_edge(self.last, target, self.edge);
+    }
+}
+
+impl PipelineLoops {
+    fn shape(graph: &CFG, l: &Loop, breaks: &BTreeSet<NodeIndex>) -> Option<Shape> {
+        let single = |idxs: &[NodeIndex]| match idxs
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>()[..]
+        {
+            [idx] => Some(*idx),
+            _ => None,
+        };
+        let header = single(&l.header)?;
+        let latch = single(&l.latches)?;
+        let branch = single(&l.exiting)?;
+        let FuncNode { params } = FuncNode::concrete(graph.get_node(header))?;
+        let CallNode { args } = CallNode::concrete(graph.get_node(latch))?;
+        if params.len() != args.len()
+            || graph.succs(header).collect::<Vec<_>>() != vec![branch]
+            || !BranchNode::downcastable(graph.get_node(branch))
+        {
+            return None;
+        }
+        let (mut body, mut exit) = (None, None);
+        for succ in graph.succs(branch) {
+            let BranchEdge { condition } = graph.get_edge(branch, succ)?.downcast_ref()?;
+            if l.members.contains(&succ) {
+                body = Some((succ, *condition));
+            } else {
+                exit = Some(succ);
+            }
+        }
+        let ((body, enters), exit) = (body?, exit?);
+
+        // A state for each break, and before each yield but the first of a state
+        let mut segments = vec![vec![]];
+        let mut members = vec![];
+        let mut idx = body;
+        while idx != latch {
+            let node = graph.get_node(idx);
+            let succ = single(&graph.succs(idx).collect::<Vec<_>>())?;
+            if graph.preds(idx).count() != 1 {
+                return None;
+            }
+            members.push(idx);
+            if CallNode::downcastable(node) && breaks.contains(&idx) {
+                let FuncNode { params } = FuncNode::concrete(graph.get_node(succ))?;
+                if !params.is_empty() || graph.preds(succ).count() != 1 {
+                    return None;
+                }
+                members.push(succ);
+                segments.push(vec![]);
+                idx = single(&graph.succs(succ).collect::<Vec<_>>())?;
+                continue;
+            }
+            if YieldNode::downcastable(node) && yields(graph, segments.last().unwrap()) > 0 {
+                segments.push(vec![]);
+            } else if !AssignNode::downcastable(node) && !YieldNode::downcastable(node) {
+                return None;
+            }
+            segments.last_mut().unwrap().push(idx);
+            idx = succ;
+        }
+        members.push(latch);
+        // Nothing else, such as an inner loop, is in the loop
+        if members.len() + 2 != l.members.iter().collect::<BTreeSet<_>>().len() {
+            return None;
+        }
+
+        Some(Shape {
+            header,
+            branch,
+            enters,
+            body,
+            exit,
+            latch,
+            segments,
+            members,
+        })
+    }
+
+    /// Smallest initiation interval from `target` that two stages allow,
+    /// as many states as an iteration has if none does
+    fn initiation_interval(graph: &CFG, shape: &Shape, target: usize) -> usize {
+        let length = shape.segments.len();
+        let CallNode { args } = CallNode::concrete(graph.get_node(shape.latch)).unwrap();
+        (std::cmp::max(target, 1)..length)
+            .find(|&ii| {
+                let (first, second) = shape.segments.split_at(ii);
+                let defined = declared(graph, second);
+                second.len() <= ii
+                    && args.iter().all(|arg| !defined.contains(arg))
+                    && (0..ii).all(|c| {
+                        yields(graph, &first[c]) + second.get(c).map_or(0, |s| yields(graph, s))
+                            <= 1
+                    })
+            })
+            .unwrap_or(length)
+    }
+
+    fn pipeline(graph: &mut CFG, shape: &Shape, ii: usize, breaks: &mut BTreeSet<NodeIndex>) {
+        let params = FuncNode::concrete(graph.get_node(shape.header))
+            .unwrap()
+            .params
+            .clone();
+        let args = CallNode::concrete(graph.get_node(shape.latch))
+            .unwrap()
+            .args
+            .clone();
+        let (first, second) = shape.segments.split_at(ii);
+        let defined = declared(graph, first);
+        // What the second stage reads of the first, which the next iteration overwrites
+        let carried = second
+            .iter()
+            .flatten()
+            .flat_map(|idx| graph.get_node(*idx).referenced_vars())
+            .filter(|var| params.contains(var) || defined.contains(var))
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        // Each copy of a stage defines its own vars
+        let prologue = tagged(params.iter().chain(&defined), "pro");
+        let next = tagged(params.iter().chain(&defined), "ker");
+        let mut last = tagged(&carried, "in");
+        last.extend(tagged(&declared(graph, second), "ker"));
+        let mut epilogue = last.clone();
+        epilogue.extend(tagged(&declared(graph, &second[1..]), "epi"));
+
+        let mut cond = graph.get_node(shape.branch).clone();
+        rename(&mut cond, &next);
+        rename(graph.get_node_mut(shape.header), &prologue);
+        rename(graph.get_node_mut(shape.branch), &prologue);
+        graph.rmv_edge(shape.branch, shape.body);
+        graph.rmv_edge(shape.branch, shape.exit);
+        let join = graph.add_node(FuncNode {
+            params: params.clone(),
+        });
+
+        let mut chain = Chain::new(shape.branch, BranchEdge::new(shape.enters).into());
+        for (i, segment) in first.iter().enumerate() {
+            if i > 0 {
+                breaks.insert(chain.split(graph));
+            }
+            chain.copy(graph, segment, &prologue);
+        }
+        let kernel = graph.add_node(FuncNode {
+            params: [renamed(&params, &next), renamed(&carried, &last)].concat(),
+        });
+        let call = chain.push(
+            graph,
+            CallNode {
+                args: [renamed(&args, &prologue), renamed(&carried, &prologue)].concat(),
+            }
+            .into(),
+        );
+        breaks.insert(call);
+        chain.end(graph, kernel);
+
+        let mut out = Chain::new(shape.branch, BranchEdge::new(!shape.enters).into());
+        out.push(
+            graph,
+            CallNode {
+                args: renamed(&params, &prologue),
+            }
+            .into(),
+        );
+        out.end(graph, join);
+
+        // Older iteration first, so that the next does not overwrite what it reads
+        let mut chain = Chain::new(kernel, NoneEdge.into());
+        for (c, segment) in first.iter().enumerate() {
+            if c > 0 {
+                breaks.insert(chain.split(graph));
+            }
+            if let Some(segment) = second.get(c) {
+                chain.copy(graph, segment, &last);
+            }
+            if c == 0 {
+                let branch = chain.push(graph, cond.clone());
+                let mut out = Chain::new(branch, BranchEdge::new(!shape.enters).into());
+                for segment in &second[1..] {
+                    breaks.insert(out.split(graph));
+                    out.copy(graph, segment, &epilogue);
+                }
+                out.push(
+                    graph,
+                    CallNode {
+                        args: renamed(&params, &next),
+                    }
+                    .into(),
+                );
+                out.end(graph, join);
+                chain.edge = BranchEdge::new(shape.enters).into();
+            }
+            chain.copy(graph, segment, &next);
+        }
+        let latch = chain.push(
+            graph,
+            CallNode {
+                args: [renamed(&args, &next), renamed(&carried, &next)].concat(),
+            }
+            .into(),
+        );
+        breaks.insert(latch);
+        chain.end(graph, kernel);
+        graph.add_edge(join, shape.exit, NoneEdge.into());
+
+        for &idx in &shape.members {
+            breaks.remove(&idx);
+            graph.rmv_node(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+    use crate::transform::*;
+
+    fn pipeline(text: &str, period: usize, ii: usize) -> (CFG, Pipelining, TransformResultType) {
+        let mut graph = ssa(text);
+        let mut pipelining = Pipelining::new(ii, vec![]);
+        let inputs: &[&[i64]] = &[&[0], &[1], &[2], &[3], &[7]];
+        let result = preserving(&mut graph, inputs, |graph| {
+            let mut timing = Timing::new(period);
+            Schedule::transform_contextful(graph, &mut timing);
+            pipelining.breaks = timing.breaks;
+            PipelineLoops::transform_contextful(graph, &mut pipelining)
+        });
+        (graph, pipelining, result)
+    }
+
+    /// Two products in a row, each taking most of a period
+    const CUBES: &str = "cfg cubes
+entry %0
+
+%0: func(n)
+%1: i = 0
+    -> %2
+
+%2: if (i < n)
+    true -> %3
+    false -> %7
+
+%3: x = ((i * i) + 1)
+%4: i = (i + 1)
+%5: y = (x * x)
+%6: yield (y)
+    -> %2
+
+%7: return (i)
+";
+
+    #[test]
+    fn two_stages() {
+        let (graph, pipelining, result) = pipeline(CUBES, 36, 1);
+        assert_eq!(result.counts["pipelined loops"], 1);
+        assert_eq!(result.counts["initiation interval"], 1);
+        assert_eq!(pipelining.achieved.values().collect::<Vec<_>>(), vec![&1]);
+        // The kernel latch and the call into it, the break of the body being gone
+        assert_eq!(pipelining.breaks.len(), 2);
+        assert!(pipelining
+            .breaks
+            .iter()
+            .all(|call| graph.nodes().any(|idx| idx == *call)));
+
+        // Fits in a state, nothing to overlap
+        let (_, pipelining, result) = pipeline(CUBES, 1000, 1);
+        assert_eq!(result.counts["pipelined loops"], 0);
+        assert_eq!(pipelining.achieved.values().collect::<Vec<_>>(), vec![&1]);
+    }
+
+    #[test]
+    fn recurrence() {
+        // The next iteration needs what the second stage computes
+        let text = "cfg recurrence
+entry %0
+
+%0: func(n)
+%1: i = 1
+    -> %2
+
+%2: if (i < n)
+    true -> %3
+    false -> %6
+
+%3: x = ((i * i) + 1)
+%4: i = (x * i)
+%5: yield (i)
+    -> %2
+
+%6: return (i)
+";
+        let (_, pipelining, result) = pipeline(text, 36, 1);
+        assert_eq!(result.counts["pipelined loops"], 0);
+        assert_eq!(pipelining.achieved.values().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn epilogue() {
+        // Four states, the last two only overlapping the first two of the next iteration
+        let text = "cfg epilogue
+entry %0
+
+%0: func(n)
+%1: i = 0
+    -> %2
+
+%2: if (i < n)
+    true -> %3
+    false -> %9
+
+%3: x = ((i * i) + 1)
+%4: i = (i + 1)
+%5: y = (x * x)
+%6: z = ((y * y) + x)
+%7: w = ((z * z) + y)
+%8: yield (w, x)
+    -> %2
+
+%9: return (i)
+";
+        let (graph, pipelining, result) = pipeline(text, 36, 1);
+        assert_eq!(result.counts["pipelined loops"], 1);
+        assert_eq!(pipelining.achieved.values().collect::<Vec<_>>(), vec![&2]);
+        // One between the states of the prologue, and of the kernel, one in the epilogue
+        assert_eq!(pipelining.breaks.len(), 5);
+        assert_eq!(count::<YieldNode>(&graph), 2);
+    }
+}
@@ -0,0 +1,505 @@
+//! Schedules operators into states that meet a clock period.
+//! [super::LowerToFsm] only starts a state at loop latches and before all but the first yield,
+//! so any number of dependent operators may otherwise be chained in one clock cycle.
+//! Each state is unrolled as [super::LowerToFsm] will split it, and the latest time each var
+//! is valid is propagated through it in topological order, merging the paths reaching a node.
+//! The first node found to miss the period gets a state break before it:
+//! at the latch of the inner loop the state went around, if any, or a new call and func node.
+//! Breaks are then folded away again, latest first, wherever the states on both sides fit together.
+//! Only the breaks added here are folded. Folding states started otherwise is out of scope:
+//! those [super::LowerToFsm] starts itself end a loop iteration or hold an output,
+//! so merging one with its neighbour would change what happens in a cycle however short it is,
+//! and those given in [Timing::breaks] were asked for.
+//! Should be ran right before [super::LowerToFsm], which is to be given [Timing::breaks]
+
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+
+use petgraph::{algo::toposort, graphmap::DiGraphMap};
+use tohdl_ir::{expr::*, graph::*};
+
+use crate::algorithms::loop_detector::{detect_loops, detect_nested_loops};
+use crate::*;
+
+/// Delays of operators, in the unit of [Timing::period]
+#[derive(Debug, Clone)]
+pub struct DelayModel {
+    /// Operators not listed take no time. Shifts by a constant are wires and always free
+    pub binary: Vec<(Operator, usize)>,
+    pub unary: Vec<(UnaryOperator, usize)>,
+    pub mux: usize,
+}
+
+/// Roughly in gate delays, for 32 bit operands
+impl Default for DelayModel {
+    fn default() -> Self {
+        Self {
+            binary: vec![
+                (Operator::Add, 8),
+                (Operator::Sub, 8),
+                (Operator::Mul, 24),
+                (Operator::Div, 96),
+                (Operator::Mod, 96),
+                (Operator::Lt, 8),
+                (Operator::Gt, 8),
+                (Operator::GtE, 8),
+                (Operator::LtE, 8),
+                (Operator::Eq, 4),
+                (Operator::NotEq, 4),
+                (Operator::LShift, 6),
+                (Operator::RShift, 6),
+                (Operator::BitAnd, 1),
+                (Operator::BitOr, 1),
+                (Operator::BitXor, 1),
+                (Operator::And, 2),
+                (Operator::Or, 2),
+            ],
+            unary: vec![
+                (UnaryOperator::Not, 2),
+                (UnaryOperator::Neg, 8),
+                (UnaryOperator::Invert, 1),
+            ],
+            mux: 2,
+        }
+    }
+}
+
+impl DelayModel {
+    fn lookup<T: PartialEq>(delays: &[(T, usize)], op: &T) -> usize {
+        delays
+            .iter()
+            .find(|(other, _)| other == op)
+            .map_or(0, |(_, delay)| *delay)
+    }
+
+    /// When `expr` is valid, given when its vars are, vars not given being registers
+    pub fn arrival(&self, expr: &Expr, vars: &BTreeMap<VarExpr, usize>) -> usize {
+        match expr {
+            Expr::Var(var) => vars.get(var).copied().unwrap_or(0),
+            Expr::Int(_) => 0,
+            Expr::BinOp(left, op, right) => {
+                let delay = match (op, &**right) {
+                    (Operator::LShift | Operator::RShift, Expr::Int(_)) => 0,
+                    _ => Self::lookup(&self.binary, op),
+                };
+                std::cmp::max(self.arrival(left, vars), self.arrival(right, vars)) + delay
+            }
+            Expr::UnaryOp(op, operand) => {
+                self.arrival(operand, vars) + Self::lookup(&self.unary, op)
+            }
+            Expr::Mux(cond, then, else_) => {
+                [cond, then, else_]
+                    .into_iter()
+                    .map(|expr| self.arrival(expr, vars))
+                    .max()
+                    .unwrap()
+                    + self.mux
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub model: DelayModel,
+    /// Longest combinational path a state may have
+    pub period: usize,
+    /// Call nodes to start a state after, as found by [Schedule]
+    pub breaks: Vec<NodeIndex>,
+}
+
+impl Timing {
+    pub fn new(period: usize) -> Self {
+        Self {
+            model: DelayModel::default(),
+            period,
+            breaks: vec![],
+        }
+    }
+}
+
+/// Node of a state as [super::LowerToFsm] unrolls it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Point {
+    node: NodeIndex,
+    /// Last inner loop latch gone through
+    latch: Option<NodeIndex>,
+    /// Only one output per state
+    yielded: bool,
+}
+
+/// Latest over every path through a state reaching a [Point]
+#[derive(Clone, Default)]
+struct Arrivals {
+    /// When vars defined in the state are valid
+    vars: BTreeMap<VarExpr, usize>,
+    /// Calls gone through on every path, [super::LowerToFsm] breaks at the second visit
+    visited: BTreeSet<NodeIndex>,
+    /// When the args of the call just gone through are valid
+    passed: Vec<usize>,
+}
+
+impl Arrivals {
+    fn merge(&mut self, other: &Arrivals) {
+        for (var, arrival) in &other.vars {
+            let entry = self.vars.entry(var.clone()).or_default();
+            *entry = std::cmp::max(*entry, *arrival);
+        }
+        self.visited.retain(|call| other.visited.contains(call));
+        if self.passed.len() < other.passed.len() {
+            self.passed.resize(other.passed.len(), 0);
+        }
+        for (arrival, other) in self.passed.iter_mut().zip(&other.passed) {
+            *arrival = std::cmp::max(*arrival, *other);
+        }
+    }
+}
+
+/// First node found to miss the period
+struct Late {
+    node: NodeIndex,
+    latch: Option<NodeIndex>,
+}
+
+#[derive(Default)]
+pub struct Schedule {
+    result: TransformResultType,
+    /// Latches [super::LowerToFsm] always breaks at
+    latches: BTreeSet<NodeIndex>,
+    /// Latches of inner loops, which [super::LowerToFsm] goes around once before breaking
+    inner_latches: BTreeSet<NodeIndex>,
+    /// Nodes that miss the period even right after a break
+    slow: BTreeSet<NodeIndex>,
+}
+
+impl ContextfulTransfrom<Timing> for Schedule {
+    fn apply_contextful(&mut self, graph: &mut CFG, timing: &mut Timing) -> &TransformResultType {
+        self.latches = detect_loops(graph)
+            .into_iter()
+            .flat_map(|l| l.latches)
+            .collect();
+        self.inner_latches = detect_nested_loops(graph)
+            .into_iter()
+            .flat_map(|l| l.latches)
+            .filter(|latch| !self.latches.contains(latch))
+            .collect();
+
+        // Calls of the call and func nodes added, and the latches made breaks
+        let mut added = vec![];
+        let mut breaks = timing.breaks.iter().copied().collect::<BTreeSet<_>>();
+        while let Some(Late { node, latch }) = self.late(graph, timing, &breaks) {
+            let call = match latch {
+                Some(latch) => latch,
+                None => {
+                    let call =
+                        graph.insert_node_before(CallNode { args: vec![] }, node, NoneEdge.into());
+                    graph.insert_node_before(FuncNode { params: vec![] }, node, NoneEdge.into());
+                    call
+                }
+            };
+            breaks.insert(call);
+            added.push(call);
+        }
+
+        let mut folded = 0;
+        for &call in added.iter().rev() {
+            let mut trial = graph.clone();
+            let mut trial_breaks = breaks.clone();
+            trial_breaks.remove(&call);
+            if !self.inner_latches.contains(&call) {
+                let func = trial.succs(call).next().unwrap();
+                trial.rmv_node_and_reattach(call);
+                trial.rmv_node_and_reattach(func);
+            }
+            if self.late(&trial, timing, &trial_breaks).is_none() {
+                *graph = trial;
+                breaks = trial_breaks;
+                folded += 1;
+            }
+        }
+        self.slow.clear();
+        self.late(graph, timing, &breaks);
+
+        if added.len() == folded {
+            self.result = TransformResultType::no_work();
+        }
+        self.result.count("state breaks", added.len() - folded);
+        self.result.count("folded breaks", folded);
+        self.result.count("nodes over period", self.slow.len());
+        timing.breaks = breaks.into_iter().collect();
+        &self.result
+    }
+}
+
+impl Schedule {
+    /// Whether the state ends at `point`, at a call a new one starting after it,
+    /// at an output a new one starting with it
+    fn ends(
+        &self,
+        graph: &CFG,
+        point: &Point,
+        visited: &BTreeSet<NodeIndex>,
+        breaks: &BTreeSet<NodeIndex>,
+    ) -> bool {
+        let idx = point.node;
+        let node = graph.get_node(idx);
+        if CallNode::downcastable(node) {
+            breaks.contains(&idx) || self.latches.contains(&idx) || visited.contains(&idx)
+        } else {
+            point.yielded && (YieldNode::downcastable(node) || ReturnNode::downcastable(node))
+        }
+    }
+
+    /// Point the state goes to from `point` along its edge to `succ`
+    fn next(&self, graph: &CFG, point: &Point, succ: NodeIndex) -> Point {
+        let node = graph.get_node(point.node);
+        Point {
+            node: succ,
+            latch: match self.inner_latches.contains(&point.node) {
+                true => Some(point.node),
+                false => point.latch,
+            },
+            yielded: point.yielded
+                || YieldNode::downcastable(node)
+                || ReturnNode::downcastable(node),
+        }
+    }
+
+    /// Points of the state from `start` in topological order, as far as any path may go.
+    /// Every path to a point has gone through its latch, so going around once more ends it
+    fn unroll(&self, graph: &CFG, start: Point, breaks: &BTreeSet<NodeIndex>) -> Vec<Point> {
+        let mut unrolled = DiGraphMap::new();
+        unrolled.add_node(start);
+        let mut stack = vec![start];
+        while let Some(point) = stack.pop() {
+            let visited = point.latch.into_iter().collect();
+            if self.ends(graph, &point, &visited, breaks) {
+                continue;
+            }
+            for succ in graph.succs(point.node) {
+                let next = self.next(graph, &point, succ);
+                if !unrolled.contains_node(next) {
+                    stack.push(next);
+                }
+                unrolled.add_edge(point, next, ());
+            }
+        }
+        toposort(&unrolled, None).expect("states are acyclic once unrolled")
+    }
+
+    /// Walks every state, returning the first node to miss the period that a break would help
+    fn late(&mut self, graph: &CFG, timing: &Timing, breaks: &BTreeSet<NodeIndex>) -> Option<Late> {
+        let mut starts = vec![graph.get_entry()];
+        let mut seen = BTreeSet::new();
+        while let Some(start) = starts.pop() {
+            if !seen.insert(start) {
+                continue;
+            }
+            let start = Point {
+                node: start,
+                latch: None,
+                yielded: false,
+            };
+            let mut reached = BTreeMap::from([(start, Arrivals::default())]);
+            for point in self.unroll(graph, start, breaks) {
+                // Only reached on paths that ended earlier
+                let Some(mut arrivals) = reached.remove(&point) else {
+                    continue;
+                };
+                let idx = point.node;
+                let node = graph.get_node(idx);
+                if self.ends(graph, &point, &arrivals.visited, breaks) {
+                    match CallNode::downcastable(node) {
+                        true => starts.extend(graph.succs(idx)),
+                        false => starts.push(idx),
+                    }
+                    continue;
+                }
+                let exprs = if let Some(CallNode { args }) = CallNode::concrete(node) {
+                    arrivals.visited.insert(idx);
+                    arrivals.passed = args
+                        .iter()
+                        .map(|arg| arrivals.vars.get(arg).copied().unwrap_or(0))
+                        .collect();
+                    vec![]
+                } else if let Some(FuncNode { params }) = FuncNode::concrete(node) {
+                    for (param, arrival) in params.iter().zip(std::mem::take(&mut arrivals.passed))
+                    {
+                        arrivals.vars.insert(param.clone(), arrival);
+                    }
+                    vec![]
+                } else if let (Some(YieldNode { values }), _) | (_, Some(ReturnNode { values })) =
+                    (YieldNode::concrete(node), ReturnNode::concrete(node))
+                {
+                    values.iter().collect()
+                } else if let Some(AssignNode { rvalue, .. }) = AssignNode::concrete(node) {
+                    vec![rvalue]
+                } else if let Some(BranchNode { cond }) = BranchNode::concrete(node) {
+                    vec![cond]
+                } else {
+                    vec![]
+                };
+
+                for expr in exprs {
+                    if timing.model.arrival(expr, &arrivals.vars) <= timing.period {
+                        continue;
+                    }
+                    if timing.model.arrival(expr, &BTreeMap::new()) > timing.period {
+                        self.slow.insert(idx);
+                    } else {
+                        return Some(Late {
+                            node: idx,
+                            latch: point.latch,
+                        });
+                    }
+                }
+                if let Some(AssignNode { lvalue, rvalue }) = AssignNode::concrete(node) {
+                    let arrival = timing.model.arrival(rvalue, &arrivals.vars);
+                    arrivals.vars.insert(lvalue.clone(), arrival);
+                }
+                for succ in graph.succs(idx) {
+                    match reached.entry(self.next(graph, &point, succ)) {
+                        Entry::Vacant(entry) => {
+                            entry.insert(arrivals.clone());
+                        }
+                        Entry::Occupied(mut entry) => entry.get_mut().merge(&arrivals),
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn schedule(text: &str, period: usize) -> (CFG, Timing, TransformResultType) {
+        let mut graph = ssa(text);
+        let mut timing = Timing::new(period);
+        let inputs: &[&[i64]] = &[&[0, 2], &[1, 3], &[3, 5], &[7, 9]];
+        let result = preserving(&mut graph, inputs, |graph| {
+            Schedule::transform_contextful(graph, &mut timing)
+        });
+        (graph, timing, result)
+    }
+
+    const CHAIN: &str = "cfg chain
+entry %0
+
+%0: func(a, b)
+%1: x = (a * b)
+%2: y = ((x * a) + 1)
+%3: z = (y * b)
+%4: yield ((z + x))
+%5: return ()
+";
+
+    #[test]
+    fn chain() {
+        // Each product only fits with what it adds to after a break
+        let (graph, timing, result) = schedule(CHAIN, 40);
+        assert_eq!(result.counts["state breaks"], 2);
+        let starts = timing
+            .breaks
+            .iter()
+            .map(|call| {
+                let func = graph.succs(*call).next().unwrap();
+                graph
+                    .get_node(graph.succs(func).next().unwrap())
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        assert!(starts[0].starts_with("y"));
+        assert!(starts[1].starts_with("z"));
+
+        let (_, timing, _) = schedule(CHAIN, 1000);
+        assert!(timing.breaks.is_empty());
+
+        // Only the yield can be helped
+        let (_, timing, result) = schedule(CHAIN, 20);
+        assert_eq!(timing.breaks.len(), 1);
+        assert_eq!(result.counts["nodes over period"], 3);
+    }
+
+    #[test]
+    fn given_breaks() {
+        // Kept even where the states on both sides would fit together
+        let (mut graph, mut timing, _) = schedule(CHAIN, 40);
+        let given = timing.breaks.clone();
+        timing.period = 1000;
+        let result = Schedule::transform_contextful(&mut graph, &mut timing);
+        assert_eq!(result.counts["folded breaks"], 0);
+        assert_eq!(timing.breaks, given);
+    }
+
+    #[test]
+    fn inner_loop() {
+        // One iteration of the inner loop fits, the one more that would be chained does not
+        let text = "cfg inner_loop
+entry %0
+
+%0: func(a, b)
+%1: i = 0
+%2: s = 0
+    -> %3
+
+%3: if (i < a)
+    true -> %4
+    false -> %9
+
+%4: j = 0
+    -> %5
+
+%5: if (j < i)
+    true -> %6
+    false -> %8
+
+%6: s = (s + (i * j))
+%7: j = (j + 1)
+    -> %5
+
+%8: i = (i + 1)
+    -> %3
+
+%9: yield (s)
+%10: return ()
+";
+        let (graph, timing, result) = schedule(text, 36);
+        assert_eq!(timing.breaks.len(), 1);
+        assert_eq!(result.counts["state breaks"], 1);
+        // The latch, which calls the loop header with its params
+        let header = graph.succs(timing.breaks[0]).next().unwrap();
+        assert!(!FuncNode::concrete(graph.get_node(header))
+            .unwrap()
+            .params
+            .is_empty());
+
+        let (_, timing, _) = schedule(text, 40);
+        assert!(timing.breaks.is_empty());
+    }
+
+    #[test]
+    fn diamonds() {
+        // Paths through the state double with each branch, arrivals merge where they join
+        let mut text = "cfg diamonds\nentry %0\n\n%0: func(a, b)\n    -> %1\n\n".to_string();
+        for i in 0..40 {
+            let (branch, then, else_, join) = (3 * i + 1, 3 * i + 2, 3 * i + 3, 3 * i + 4);
+            text += &format!("%{branch}: if (a < {i})\n");
+            text += &format!("    true -> %{then}\n    false -> %{else_}\n\n");
+            text += &format!("%{then}: a = (a + b)\n    -> %{join}\n\n");
+            text += &format!("%{else_}: a = (a - b)\n    -> %{join}\n\n");
+        }
+        text += "%121: yield (a)\n%122: return ()\n";
+
+        // Each diamond adds one adder to the chain
+        let (_, timing, result) = schedule(&text, 100);
+        // println!("{:?}", timing.breaks);
+        assert!(!timing.breaks.is_empty());
+        assert_eq!(result.counts["state breaks"], timing.breaks.len());
+
+        let (_, timing, _) = schedule(&text, 1000);
+        assert!(timing.breaks.is_empty());
+    }
+}
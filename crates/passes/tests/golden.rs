@@ -0,0 +1,39 @@
+//! Pass tests written as text, see [`tohdl_ir::text`] for the format.
+//! Each case runs a pass on `golden/<case>.in.tohdl` and compares the result
+//! with `golden/<case>.out.tohdl`, set `TOHDL_BLESS=1` to overwrite the expected output.
+
+use std::path::PathBuf;
+
+use tohdl_ir::graph::CFG;
+use tohdl_passes::{optimize::*, transform::*, BasicTransform, TransformResultType};
+
+fn check(case: &str, transform: fn(&mut CFG) -> TransformResultType) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let input = std::fs::read_to_string(dir.join(format!("{}.in.tohdl", case))).unwrap();
+    let mut graph = CFG::from_text(&input).unwrap_or_else(|e| panic!("{}.in.tohdl {}", case, e));
+    transform(&mut graph);
+    let actual = graph.to_text();
+
+    let expected_path = dir.join(format!("{}.out.tohdl", case));
+    if std::env::var("TOHDL_BLESS").is_ok_and(|v| v == "1") {
+        std::fs::write(expected_path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(expected_path).unwrap();
+    assert_eq!(actual, expected, "{}", case);
+}
+
+#[test]
+fn braun_et_al_counter() {
+    check("braun_et_al_counter", BraunEtAl::transform);
+}
+
+#[test]
+fn infer_widths_counter() {
+    check("infer_widths_counter", InferWidths::transform);
+}
+
+#[test]
+fn remove_unread_vars() {
+    check("remove_unread_vars", RemoveUnreadVars::transform);
+}
@@ -1,13 +1,42 @@
 use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
 use pyo3::prelude::*;
 use tohdl_codegen::python::graph_to_python;
 use tohdl_codegen::verilog::{graph_to_verilog, Context};
+use tohdl_frontend::{CompileError, Span};
 use tohdl_ir::graph::{ExternalNode, Node, NodeIndex, CFG};
 use tohdl_passes::algorithms::inline_extern_func;
 use tohdl_passes::transform::{BraunEtAl, RenameVariables};
 use tohdl_passes::{BasicTransform, ContextfulTransfrom};
 
+create_exception!(
+    pytohdl,
+    TranslationError,
+    PyException,
+    "Base class of all errors raised while translating Python to HDL."
+);
+create_exception!(
+    pytohdl,
+    UnsupportedSyntaxError,
+    TranslationError,
+    "Python source uses syntax outside of the supported subset."
+);
+create_exception!(
+    pytohdl,
+    UnknownFunctionError,
+    TranslationError,
+    "A called function is not part of the translation context."
+);
+create_exception!(
+    pytohdl,
+    InternalCompilerError,
+    TranslationError,
+    "The compiler failed on input that it accepted, this is a bug."
+);
+
 /// Formats the sum of two numbers as string.
 #[pyfunction]
 fn sum_as_string(a: usize, b: usize) -> PyResult<String> {
@@ -21,9 +50,108 @@ fn pytohdl(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(translate, m)?)?;
     m.add_function(wrap_pyfunction!(python_to_python_fsm, m)?)?;
     m.add_class::<PyContext>()?;
+    m.add("TranslationError", m.py().get_type::<TranslationError>())?;
+    m.add(
+        "UnsupportedSyntaxError",
+        m.py().get_type::<UnsupportedSyntaxError>(),
+    )?;
+    m.add(
+        "UnknownFunctionError",
+        m.py().get_type::<UnknownFunctionError>(),
+    )?;
+    m.add(
+        "InternalCompilerError",
+        m.py().get_type::<InternalCompilerError>(),
+    )?;
     Ok(())
 }
 
+/// Failure to translate a `PyContext`, raised in Python as a `TranslationError` subclass
+#[derive(Debug, Clone)]
+pub enum TranslateError {
+    /// Frontend rejected the source of a function
+    Compile(Vec<CompileError>),
+    /// `function` calls `callee`, which is not in the context
+    UnknownFunction {
+        function: String,
+        callee: String,
+        span: Option<Span>,
+    },
+    /// A pass panicked on input the frontend accepted
+    Internal { function: String, message: String },
+}
+
+impl TranslateError {
+    /// Function name, line and column to attach to the Python exception
+    fn location(&self) -> (String, Option<usize>, Option<usize>) {
+        let from_span = |span: &Span| match span.line {
+            0 => (None, None),
+            line => (Some(line), Some(span.column)),
+        };
+        match self {
+            TranslateError::Compile(errors) => match errors.first() {
+                Some(error) => {
+                    let (line, column) = from_span(&error.span);
+                    (error.function.clone(), line, column)
+                }
+                None => (String::new(), None, None),
+            },
+            TranslateError::UnknownFunction { function, span, .. } => {
+                let (line, column) = span.as_ref().map(from_span).unwrap_or((None, None));
+                (function.clone(), line, column)
+            }
+            TranslateError::Internal { function, .. } => (function.clone(), None, None),
+        }
+    }
+}
+
+impl std::fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranslateError::Compile(errors) => {
+                let messages = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", messages.join("\n"))
+            }
+            TranslateError::UnknownFunction {
+                function,
+                callee,
+                span,
+            } => match span {
+                Some(span) => write!(
+                    f,
+                    "{}:{}: call to unknown function `{}`",
+                    function, span, callee
+                ),
+                None => write!(f, "{}: call to unknown function `{}`", function, callee),
+            },
+            TranslateError::Internal { function, message } => {
+                write!(f, "{}: internal compiler error: {}", function, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TranslateError {}
+
+impl From<TranslateError> for PyErr {
+    fn from(err: TranslateError) -> PyErr {
+        let (function, line, column) = err.location();
+        let message = err.to_string();
+        let pyerr = match err {
+            TranslateError::Compile(_) => UnsupportedSyntaxError::new_err(message),
+            TranslateError::UnknownFunction { .. } => UnknownFunctionError::new_err(message),
+            TranslateError::Internal { .. } => InternalCompilerError::new_err(message),
+        };
+        Python::with_gil(|py| {
+            let value = pyerr.value(py);
+            let _ = value.setattr("function", function);
+            let _ = value.setattr("line", line);
+            let _ = value.setattr("column", column);
+        });
+        pyerr
+    }
+}
+
 #[pyclass]
 pub struct PyContext {
     pub main: String,
@@ -33,50 +161,134 @@ pub struct PyContext {
 #[pymethods]
 impl PyContext {
     #[new]
-    fn new(main: String, functions: BTreeMap<String, String>) -> Self {
-        assert!(functions.contains_key(&main));
-        Self { main, functions }
+    fn new(main: String, functions: BTreeMap<String, String>) -> PyResult<Self> {
+        if !functions.contains_key(&main) {
+            return Err(TranslateError::UnknownFunction {
+                function: main.clone(),
+                callee: main,
+                span: None,
+            }
+            .into());
+        }
+        Ok(Self { main, functions })
     }
 }
 
 #[pyfunction]
-pub fn translate(context: &PyContext) -> String {
-    let visitor =
-        tohdl_frontend::AstVisitor::from_text(context.functions.get(&context.main).unwrap());
-    let mut graph = visitor.get_graph();
-    loop {
-        let externals = find_externals(&graph, &context);
-        if externals.len() == 0 {
-            break;
+pub fn translate(context: &PyContext) -> PyResult<String> {
+    Ok(try_translate(context)?)
+}
+
+/// Translates `context` to Verilog, reporting failures as a `TranslateError`
+pub fn try_translate(context: &PyContext) -> Result<String, TranslateError> {
+    let graph = try_inline(context)?;
+    catch_internal(&context.main, || graph_to_verilog(graph))
+}
+
+/// Parses the main function of `context` and inlines every function it calls
+pub fn try_inline(context: &PyContext) -> Result<CFG, TranslateError> {
+    catch_internal(&context.main, || {
+        let mut graph = parse_function(context, &context.main)?;
+        // Each inlined call gets its own variables, so nested generator instances
+        // of the same function don't share state
+        let mut instances = BTreeMap::<String, usize>::new();
+        loop {
+            let externals = find_externals(&graph, &context)?;
+            if externals.len() == 0 {
+                break;
+            }
+            for (idx, mut callee_graph, name) in externals {
+                let count = instances.entry(name.clone()).or_default();
+                let mut prefix = match *count {
+                    0 => name,
+                    _ => format!("{}{}", name, count),
+                };
+                *count += 1;
+                RenameVariables::transform_contextful(&mut callee_graph, &mut prefix);
+                inline_extern_func(idx, &mut graph, &callee_graph);
+            }
         }
-        for (idx, mut callee_graph, mut name) in externals {
-            RenameVariables::transform_contextful(&mut callee_graph, &mut name);
-            inline_extern_func(idx, &mut graph, &callee_graph);
+        Ok(graph)
+    })
+    .and_then(|result| result)
+}
+
+#[pyfunction]
+fn python_to_python_fsm(code: &str) -> PyResult<String> {
+    let result = catch_internal("", || {
+        let visitor =
+            tohdl_frontend::AstVisitor::from_text(code).map_err(TranslateError::Compile)?;
+        let graph = visitor.get_graph();
+        Ok(graph_to_python(graph))
+    })
+    .and_then(|result| result);
+    Ok(result?)
+}
+
+/// Parses the function called `name` and checks that everything it calls is in `context`
+fn parse_function(context: &PyContext, name: &str) -> Result<CFG, TranslateError> {
+    let python_code =
+        context
+            .functions
+            .get(name)
+            .ok_or_else(|| TranslateError::UnknownFunction {
+                function: context.main.clone(),
+                callee: name.to_owned(),
+                span: None,
+            })?;
+    let visitor = tohdl_frontend::AstVisitor::from_text(python_code).map_err(|mut errors| {
+        for error in &mut errors {
+            if error.function.is_empty() {
+                error.function = name.to_owned();
+            }
         }
+        TranslateError::Compile(errors)
+    })?;
+    if let Some((callee, span)) = visitor
+        .calls()
+        .iter()
+        .find(|(callee, _)| !context.functions.contains_key(callee))
+    {
+        return Err(TranslateError::UnknownFunction {
+            function: name.to_owned(),
+            callee: callee.clone(),
+            span: Some(*span),
+        });
     }
-    graph_to_verilog(graph)
+    Ok(visitor.get_graph())
 }
 
-#[pyfunction]
-fn python_to_python_fsm(code: &str) -> String {
-    let visitor = tohdl_frontend::AstVisitor::from_text(code);
-    let graph = visitor.get_graph();
-    graph_to_python(graph)
+/// Runs `f`, turning a panic into an internal compiler error for `function`
+fn catch_internal<T>(function: &str, f: impl FnOnce() -> T) -> Result<T, TranslateError> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_owned()
+        };
+        TranslateError::Internal {
+            function: function.to_owned(),
+            message,
+        }
+    })
 }
 
 /// Finds external function calls and generate their respective graphs
-pub fn find_externals(graph: &CFG, context: &PyContext) -> Vec<(NodeIndex, CFG, String)> {
+pub fn find_externals(
+    graph: &CFG,
+    context: &PyContext,
+) -> Result<Vec<(NodeIndex, CFG, String)>, TranslateError> {
     let mut ret = vec![];
     for node in graph.nodes() {
         if let Some(n) = ExternalNode::concrete(graph.get_node(node)) {
             let name = &n.name;
-            let python_code = context.functions.get(name).expect(&format!("{}", n.name));
-            let visitor = tohdl_frontend::AstVisitor::from_text(python_code);
-            let graph = visitor.get_graph();
+            let graph = parse_function(context, name)?;
             ret.push((node, graph, name.clone()));
         }
     }
-    ret
+    Ok(ret)
 }
 
 mod tests {
@@ -124,7 +336,7 @@ def floating_point_add(a_sign, a_exponent, a_mantissa, b_sign, b_exponent, b_man
     yield c_sign
     yield c_sign
         "#;
-        let visitor = tohdl_frontend::AstVisitor::from_text(code);
+        let visitor = tohdl_frontend::AstVisitor::from_text(code).unwrap();
         let graph = visitor.get_graph();
         let verilog = graph_to_verilog(graph);
 
@@ -134,4 +346,40 @@ def floating_point_add(a_sign, a_exponent, a_mantissa, b_sign, b_exponent, b_man
         // let mut file = File::create("output.sv").unwrap();
         // write!(file, "{}", verilog).unwrap();
     }
+
+    #[test]
+    pub fn unknown_function() {
+        let context = PyContext {
+            main: "caller".into(),
+            functions: BTreeMap::from([(
+                "caller".into(),
+                "def caller(n):\n    x = missing(n)\n    return x\n".into(),
+            )]),
+        };
+        match try_translate(&context) {
+            Err(TranslateError::UnknownFunction {
+                function,
+                callee,
+                span,
+            }) => {
+                assert_eq!(function, "caller");
+                assert_eq!(callee, "missing");
+                assert_eq!(span.unwrap().line, 2);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn unsupported_syntax() {
+        let context = PyContext {
+            main: "func".into(),
+            functions: BTreeMap::from([(
+                "func".into(),
+                "def func(n):\n    return n ** 2\n".into(),
+            )]),
+        };
+        let err = try_translate(&context).unwrap_err();
+        assert_eq!(err.location(), ("func".to_owned(), Some(2), Some(12)));
+    }
 }
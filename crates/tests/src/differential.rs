@@ -0,0 +1,483 @@
+//! Differential testing, runs a Python generator under Python itself as the reference,
+//! then through the IR interpreter, the Python FSM from `graph_to_python`
+//! and the Verilog from `graph_to_verilog`, and compares what each yields and returns.
+//! Comparisons yield 0 or 1, as in hardware, rather than `False` or `True`.
+//!
+//! Python is run with the local `python3` (or `$PYTHON`),
+//! Verilog with `iverilog` and `vvp` as installed by `extern/iverilog_setup.sh`
+//! (or `$IVERILOG` and `$VVP`). Only single functions without external calls are supported.
+//! The tests using it are ignored by default and fail when a tool is missing,
+//! `cargo test -p tohdl-tests --test differential -- --ignored` runs them.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use tohdl_codegen::{
+    python::graph_to_python,
+    verilog::{graph_to_verilog, lower},
+};
+use tohdl_ir::{expr::*, graph::CFG, interpret::interpret};
+
+/// Cycles the Verilog simulation may take before it is considered stuck
+const MAX_CYCLES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The original function run by Python
+    Reference,
+    Interpreter,
+    Python,
+    Verilog,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Reference => write!(f, "reference"),
+            Backend::Interpreter => write!(f, "interpreter"),
+            Backend::Python => write!(f, "python"),
+            Backend::Verilog => write!(f, "verilog"),
+        }
+    }
+}
+
+/// Values produced by one backend
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Trace {
+    pub yields: Vec<Vec<BigInt>>,
+    pub returned: Vec<BigInt>,
+    /// Clock cycle each yield was seen at, only for Verilog
+    pub cycles: Vec<usize>,
+    /// Clock cycle the return value was seen at, only for Verilog
+    pub done_cycle: Option<usize>,
+}
+
+/// First place a backend disagrees with the reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub backend: Backend,
+    pub inputs: Vec<BigInt>,
+    /// Position of the yield, `None` for the return value
+    pub index: Option<usize>,
+    pub cycle: Option<usize>,
+    pub expected: Option<Vec<BigInt>>,
+    pub found: Option<Vec<BigInt>>,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on inputs {:?} diverges at ",
+            self.backend, self.inputs
+        )?;
+        match self.index {
+            Some(index) => write!(f, "yield {}", index)?,
+            None => write!(f, "return")?,
+        }
+        if let Some(cycle) = self.cycle {
+            write!(f, " (cycle {})", cycle)?;
+        }
+        write!(f, ": expected {:?}, found {:?}", self.expected, self.found)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HarnessError {
+    /// Tool is not installed
+    Unavailable(String),
+    /// Backend failed to produce a trace
+    Failed {
+        backend: Backend,
+        inputs: Vec<BigInt>,
+        message: String,
+    },
+    Diverged(Divergence),
+}
+
+impl std::fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HarnessError::Unavailable(tool) => write!(f, "{} is not available", tool),
+            HarnessError::Failed {
+                backend,
+                inputs,
+                message,
+            } => write!(f, "{} failed on inputs {:?}: {}", backend, inputs, message),
+            HarnessError::Diverged(divergence) => write!(f, "{}", divergence),
+        }
+    }
+}
+
+impl std::error::Error for HarnessError {}
+
+/// Runs every backend on every set of inputs, stopping at the first failure or divergence
+pub fn check(source: &str, inputs: &[Vec<i64>]) -> Result<(), HarnessError> {
+    let graph = tohdl_frontend::AstVisitor::from_text(source)
+        .map_err(|e| HarnessError::Failed {
+            backend: Backend::Interpreter,
+            inputs: vec![],
+            message: e
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })?
+        .get_graph();
+    let main = graph.name.clone();
+    let python = graph_to_python(graph.clone());
+    let verilog = graph_to_verilog(graph.clone());
+    let module = Module::new(&graph, &verilog);
+
+    for inputs in inputs {
+        let inputs = inputs.iter().map(|i| BigInt::from(*i)).collect::<Vec<_>>();
+        let failed = |backend| {
+            let inputs = inputs.clone();
+            move |failure| match failure {
+                Failure::Unavailable(tool) => HarnessError::Unavailable(tool),
+                Failure::Message(message) => HarnessError::Failed {
+                    backend,
+                    inputs,
+                    message,
+                },
+            }
+        };
+        let expected = run_python(source, &main, &inputs).map_err(failed(Backend::Reference))?;
+        let actual = interpret(&graph, &inputs)
+            .map(|execution| Trace {
+                yields: execution.yields,
+                returned: execution.returned,
+                ..Default::default()
+            })
+            .map_err(|e| Failure::Message(e.to_string()))
+            .map_err(failed(Backend::Interpreter))?;
+        compare(Backend::Interpreter, &inputs, &expected, &actual)?;
+        let actual = run_python(&python, "func0", &inputs).map_err(failed(Backend::Python))?;
+        compare(Backend::Python, &inputs, &expected, &actual)?;
+        let actual = module
+            .run(&verilog, &inputs)
+            .map_err(failed(Backend::Verilog))?;
+        compare(Backend::Verilog, &inputs, &expected, &actual)?;
+    }
+    Ok(())
+}
+
+/// Compares a trace with the reference, Verilog outputs past the length of the
+/// expected values are ignored since every yield drives all output ports
+fn compare(
+    backend: Backend,
+    inputs: &[BigInt],
+    expected: &Trace,
+    actual: &Trace,
+) -> Result<(), HarnessError> {
+    let truncate = |values: &Vec<BigInt>, len: usize| -> Vec<BigInt> {
+        match backend {
+            Backend::Verilog => values.iter().take(len).cloned().collect(),
+            _ => values.clone(),
+        }
+    };
+    let count = std::cmp::max(expected.yields.len(), actual.yields.len());
+    for i in 0..count {
+        let want = expected.yields.get(i);
+        let got = actual
+            .yields
+            .get(i)
+            .map(|values| truncate(values, want.map_or(values.len(), |w| w.len())));
+        if want != got.as_ref() {
+            return Err(HarnessError::Diverged(Divergence {
+                backend,
+                inputs: inputs.to_vec(),
+                index: Some(i),
+                cycle: actual.cycles.get(i).copied().or(actual.done_cycle),
+                expected: want.cloned(),
+                found: got,
+            }));
+        }
+    }
+    let returned = truncate(&actual.returned, expected.returned.len());
+    if returned != expected.returned {
+        return Err(HarnessError::Diverged(Divergence {
+            backend,
+            inputs: inputs.to_vec(),
+            index: None,
+            cycle: actual.done_cycle,
+            expected: Some(expected.returned.clone()),
+            found: Some(returned),
+        }));
+    }
+    Ok(())
+}
+
+fn tool(variable: &str, default: &str) -> String {
+    std::env::var(variable).unwrap_or_else(|_| default.to_string())
+}
+
+/// Why a backend produced no trace
+enum Failure {
+    /// Tool is not installed
+    Unavailable(String),
+    Message(String),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::Message(message)
+    }
+}
+
+/// Runs a command and returns its stdout
+fn run(command: &mut Command) -> Result<String, Failure> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let output = command.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Failure::Unavailable(program.clone()),
+        _ => Failure::Message(format!("{}: {}", program, e)),
+    })?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        return Err(Failure::Message(format!(
+            "{} exited with {}\n{}{}",
+            program,
+            output.status,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(stdout)
+}
+
+fn parse_values(words: &[&str]) -> Result<Vec<BigInt>, String> {
+    words
+        .iter()
+        .map(|word| {
+            word.parse::<BigInt>()
+                .map_err(|_| format!("`{}` is not an integer", word))
+        })
+        .collect()
+}
+
+/// Lines written by the Python driver and the testbench,
+/// `yield <cycle> <values>` and `return <cycle> <values>`
+fn parse_trace(output: &str) -> Result<Trace, String> {
+    let mut trace = Trace::default();
+    for line in output.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            ["yield", cycle, ref values @ ..] => {
+                trace.yields.push(parse_values(values)?);
+                trace.cycles.extend(cycle.parse::<usize>().ok());
+            }
+            ["return", cycle, ref values @ ..] => {
+                trace.returned = parse_values(values)?;
+                trace.done_cycle = cycle.parse::<usize>().ok();
+                return Ok(trace);
+            }
+            ["timeout"] => return Err(format!("no return within {} cycles", MAX_CYCLES)),
+            _ => {}
+        }
+    }
+    Err(format!("no return value in output\n{}", output))
+}
+
+/// Runs `main`, which need not be a generator
+const PYTHON_DRIVER: &str = r#"
+import inspect
+import sys
+
+def values(value):
+    if value is None:
+        return []
+    if not isinstance(value, tuple):
+        value = (value,)
+    return [int(v) for v in value]
+
+gen = main(*[int(arg) for arg in sys.argv[1:]])
+if not inspect.isgenerator(gen):
+    print("return -", *values(gen))
+    sys.exit()
+while True:
+    try:
+        value = next(gen)
+    except StopIteration as stop:
+        print("return -", *values(stop.value))
+        break
+    print("yield -", *values(value))
+"#;
+
+/// Runs the function `main` of `code`, whose type annotations are left unevaluated
+fn run_python(code: &str, main: &str, inputs: &[BigInt]) -> Result<Trace, Failure> {
+    let script = format!(
+        "from __future__ import annotations\n{}\nmain = {}\n{}",
+        code, main, PYTHON_DRIVER
+    );
+    let output = run(Command::new(tool("PYTHON", "python3"))
+        .arg("-c")
+        .arg(script)
+        .args(inputs.iter().map(|i| i.to_string())))?;
+    Ok(parse_trace(&output)?)
+}
+
+/// Ports of the generated module
+#[derive(Debug, Clone)]
+struct Module {
+    name: String,
+    /// Function inputs by name and width
+    inputs: Vec<(String, usize)>,
+    /// Output ports by width and signedness
+    outputs: Vec<IntType>,
+}
+
+impl Module {
+    /// Reads input widths from the module header,
+    /// output types come from the same lowering `graph_to_verilog` runs
+    fn new(graph: &CFG, verilog: &str) -> Self {
+        let io = lower(graph.clone()).context.io;
+
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        let header = verilog
+            .lines()
+            .skip_while(|line| !line.trim_start().starts_with("module"))
+            .skip(1)
+            .take_while(|line| !line.trim_start().starts_with(");"));
+        for line in header {
+            let words = line
+                .trim()
+                .trim_end_matches(',')
+                .split_whitespace()
+                .collect::<Vec<_>>();
+            let (Some(&direction), Some(&name)) = (words.first(), words.last()) else {
+                continue;
+            };
+            let size = words
+                .iter()
+                .find_map(|word| {
+                    word.strip_prefix('[')?
+                        .split_once(':')?
+                        .0
+                        .parse::<usize>()
+                        .ok()
+                })
+                .map_or(1, |high| high + 1);
+            if name.starts_with("__output_") {
+                outputs.push(io.output_type(outputs.len()));
+            } else if direction == "input" && !name.starts_with("__") {
+                inputs.push((name.to_string(), size));
+            }
+        }
+        Self {
+            name: graph.name.clone(),
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Testbench that starts the module once with `ready` held high
+    /// and prints each yield and the return value with the cycle it was seen at
+    fn testbench(&self, inputs: &[BigInt]) -> String {
+        let mut tb = String::from("module tb;\n");
+        tb.push_str("    reg __clock = 0;\n    reg __reset = 0;\n    reg __start = 0;\n");
+        tb.push_str("    reg __ready = 1;\n    wire __valid;\n    wire __done;\n");
+        tb.push_str("    integer cycle = 0;\n");
+        for ((name, size), value) in self.inputs.iter().zip(inputs) {
+            let magnitude = if value < &BigInt::default() {
+                format!("-{}'sd{}", size, -value)
+            } else {
+                format!("{}'d{}", size, value)
+            };
+            tb.push_str(&format!(
+                "    reg [{}:0] in_{} = {};\n",
+                size - 1,
+                name,
+                magnitude
+            ));
+        }
+        for (i, output) in self.outputs.iter().enumerate() {
+            tb.push_str(&format!(
+                "    wire [{}:0] __output_{};\n",
+                output.size - 1,
+                i
+            ));
+        }
+        let ports = self
+            .inputs
+            .iter()
+            .map(|(name, _)| format!(".{}(in_{})", name, name))
+            .chain(
+                [
+                    "__clock", "__reset", "__start", "__ready", "__valid", "__done",
+                ]
+                .iter()
+                .map(|name| format!(".{}({})", name, name)),
+            )
+            .chain((0..self.outputs.len()).map(|i| format!(".__output_{}(__output_{})", i, i)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tb.push_str(&format!("    {} dut({});\n", self.name, ports));
+        tb.push_str("    always #5 __clock = !__clock;\n");
+        let outputs = (0..self.outputs.len())
+            .map(|i| format!(", __output_{}", i))
+            .collect::<String>();
+        let formats = " %0d".repeat(self.outputs.len());
+        tb.push_str(&format!(
+            "    initial begin
+        __start = 1;
+        @(posedge __clock);
+        #1 __start = 0;
+        repeat ({max}) begin
+            @(posedge __clock);
+            #1 cycle = cycle + 1;
+            if (__done) begin
+                $display(\"return %0d{formats}\", cycle{outputs});
+                $finish;
+            end else if (__valid) begin
+                $display(\"yield %0d{formats}\", cycle{outputs});
+            end
+        end
+        $display(\"timeout\");
+        $finish;
+    end
+endmodule
+",
+            max = MAX_CYCLES,
+            formats = formats,
+            outputs = outputs
+        ));
+        tb
+    }
+
+    fn run(&self, verilog: &str, inputs: &[BigInt]) -> Result<Trace, Failure> {
+        let dir = scratch_dir(&self.name);
+        let design = dir.join("design.sv");
+        let tb = dir.join("tb.sv");
+        let sim = dir.join("sim.vvp");
+        std::fs::write(&design, verilog).map_err(|e| e.to_string())?;
+        std::fs::write(&tb, self.testbench(inputs)).map_err(|e| e.to_string())?;
+        let result = run(Command::new(tool("IVERILOG", "iverilog"))
+            .args(["-g2012", "-o"])
+            .args([&sim, &tb, &design]))
+        .and_then(|_| run(Command::new(tool("VVP", "vvp")).arg("-n").arg(&sim)));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut trace = parse_trace(&result?)?;
+        // Ports hold raw bits, reinterpret them with the output types
+        for values in trace.yields.iter_mut().chain([&mut trace.returned]) {
+            for (value, output) in values.iter_mut().zip(&self.outputs) {
+                *value = IntExpr::sized(value.clone(), output.size, output.signed).value;
+            }
+        }
+        Ok(trace)
+    }
+}
+
+/// Unique directory under the system temp dir
+fn scratch_dir(name: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "tohdl_{}_{}_{}",
+        name,
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,7 +1,29 @@
-use tohdl_ir::graph::CFG;
+pub mod differential;
+
+use pytohdl::PyContext;
+use tohdl_ir::{
+    expr::BigInt,
+    graph::CFG,
+    interpret::{interpret, Execution},
+};
 
 fn code_to_graph(code: &str) -> CFG {
-    tohdl_frontend::AstVisitor::from_text(code).get_graph()
+    tohdl_frontend::AstVisitor::from_text(code).unwrap().get_graph()
+}
+
+pub fn ints(values: &[i64]) -> Vec<BigInt> {
+    values.iter().map(|value| BigInt::from(*value)).collect()
+}
+
+/// Yields of one value each
+pub fn singles(values: &[i64]) -> Vec<Vec<BigInt>> {
+    values.iter().map(|value| ints(&[*value])).collect()
+}
+
+/// Runs the main function of `context` in the interpreter, with what it calls inlined
+pub fn interpret_context(context: &PyContext, inputs: &[i64]) -> Execution {
+    let graph = pytohdl::try_inline(context).unwrap();
+    interpret(&graph, &ints(inputs)).unwrap()
 }
 
 pub fn aug_assign_str() -> &'static str {
@@ -187,3 +209,136 @@ def callee(a: int, b: int) -> int:
     return 5
     "#
 }
+
+pub fn for_range_str() -> &'static str {
+    r#"
+def for_range(n, step):
+    total = 0
+    for i in range(n):
+        total += i
+        yield total
+    for i in range(n, 0, -2):
+        yield i
+    for i in range(0, n, step):
+        if i > 10:
+            yield i
+    return total
+"#
+}
+
+pub fn for_range_graph() -> CFG {
+    code_to_graph(for_range_str())
+}
+
+pub fn hrange_str() -> &'static str {
+    r#"
+def hrange(base, limit, step):
+    i = base
+    while i < limit:
+        yield i
+        i += step
+"#
+}
+
+pub fn dup_range_str() -> &'static str {
+    r#"
+def dup_range(base, limit, step):
+    for value in hrange(base, limit, step):
+        yield value
+        yield value + 1
+    return 0
+"#
+}
+
+pub fn dup_range_graph() -> CFG {
+    code_to_graph(dup_range_str())
+}
+
+pub fn nested_range_str() -> &'static str {
+    r#"
+def nested_range(n):
+    one = 1
+    for i in hrange(one, n, one):
+        for j in hrange(one, i, one):
+            yield j
+    yield from hrange(one, n, one)
+"#
+}
+
+pub fn break_continue_str() -> &'static str {
+    r#"
+def break_continue(n):
+    i = 0
+    while i < n:
+        i += 1
+        if i % 3 == 0:
+            continue
+        j = 0
+        while 1:
+            j += 1
+            if j > i:
+                break
+            if j % 2 == 0:
+                continue
+            yield j
+        if i > 100:
+            break
+        yield i
+    return i
+"#
+}
+
+pub fn break_continue_graph() -> CFG {
+    code_to_graph(break_continue_str())
+}
+
+pub fn tuple_fib_str() -> &'static str {
+    r#"
+def tuple_fib(n):
+    a, b = 0, 1
+    i = 0
+    while i < n:
+        yield i, a
+        a, b = b, a + b
+        i += 1
+    q, r = divmod_10(a)
+    return q, r
+"#
+}
+
+pub fn tuple_fib_graph() -> CFG {
+    code_to_graph(tuple_fib_str())
+}
+
+pub fn divmod_10_str() -> &'static str {
+    r#"
+def divmod_10(n):
+    return n // 10, n % 10
+"#
+}
+
+pub fn floor_divmod_str() -> &'static str {
+    r#"
+def floor_divmod(a, b):
+    return a // b, a % b
+"#
+}
+
+pub fn digits_str() -> &'static str {
+    r#"
+def digits(n, base):
+    while n > 0:
+        yield n % base
+        n = n // base
+    return n
+"#
+}
+
+pub fn comparisons_str() -> &'static str {
+    r#"
+def comparisons(a, b):
+    yield a < b, a <= b, a == b, a != b
+    yield a > 0 and b > 0, not a
+    return a > b
+"#
+}
@@ -0,0 +1,66 @@
+use tohdl_tests::differential::check;
+
+/// Fails on divergence, and when python3 or iverilog is not installed
+fn differential(source: &str, inputs: &[Vec<i64>]) {
+    if let Err(e) = check(source, inputs) {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+#[ignore = "needs python3 and iverilog"]
+fn for_range() {
+    differential(
+        tohdl_tests::for_range_str(),
+        &[vec![0, 1], vec![5, 1], vec![15, 4]],
+    );
+}
+
+#[test]
+#[ignore = "needs python3 and iverilog"]
+fn hrange() {
+    differential(
+        tohdl_tests::hrange_str(),
+        &[vec![0, 10, 3], vec![-5, 5, 2], vec![3, 3, 1]],
+    );
+}
+
+#[test]
+#[ignore = "needs python3 and iverilog"]
+fn break_continue() {
+    differential(tohdl_tests::break_continue_str(), &[vec![0], vec![7]]);
+}
+
+#[test]
+#[ignore = "needs python3 and iverilog"]
+fn floor_divmod() {
+    differential(
+        tohdl_tests::floor_divmod_str(),
+        &[
+            vec![7, 2],
+            vec![-7, 2],
+            vec![7, -2],
+            vec![-7, -2],
+            vec![-6, 3],
+            vec![0, -5],
+        ],
+    );
+}
+
+#[test]
+#[ignore = "needs python3 and iverilog"]
+fn digits() {
+    differential(
+        tohdl_tests::digits_str(),
+        &[vec![0, 10], vec![1234, 10], vec![255, 16], vec![-5, 10]],
+    );
+}
+
+#[test]
+#[ignore = "needs python3 and iverilog"]
+fn comparisons() {
+    differential(
+        tohdl_tests::comparisons_str(),
+        &[vec![1, 2], vec![2, 2], vec![-3, 2], vec![0, -1]],
+    );
+}
@@ -1,12 +1,12 @@
 use std::collections::BTreeMap;
 
-use pytohdl::{find_externals, translate, PyContext};
+use pytohdl::{find_externals, try_translate, PyContext};
 use tohdl_codegen::verilog::graph_to_verilog;
 use tohdl_ir::graph::CFG;
 use tohdl_passes::{
     algorithms::inline_extern_func, manager::PassManager, transform::{BraunEtAl, InsertCallNodes, InsertFuncNodes}, BasicTransform
 };
-use tohdl_tests::{aug_assign_str, binary_to_7_seg_str, callee_str, div_10_str, fib_to_7_seg_str, func_call_str, mod_10_str, return_literal_str, seven_seg_str};
+use tohdl_tests::{ints, interpret_context, singles, aug_assign_str, binary_to_7_seg_str, callee_str, div_10_str, fib_to_7_seg_str, func_call_str, mod_10_str, return_literal_str, seven_seg_str};
 
 fn aug_assign_graph() -> CFG {
     let mut graph = tohdl_tests::aug_assign_graph();
@@ -37,7 +37,7 @@ fn func_call() {
         ])
         .into(),
     };
-    let code = translate(&pycontext);
+    let code = try_translate(&pycontext).unwrap();
 }
 
 #[test]
@@ -53,7 +53,7 @@ fn fib_to_7_seg() {
         ])
         .into(),
     };
-    let code = translate(&pycontext);
+    let code = try_translate(&pycontext).unwrap();
 }
 
 #[test]
@@ -66,6 +66,96 @@ fn caller_callee() {
         ])
         .into(),
     };
-    let code = translate(&pycontext);
+    let code = try_translate(&pycontext).unwrap();
     println!("{code}")
 }
+
+#[test]
+fn iterate_generator() {
+    let pycontext = PyContext {
+        main: "dup_range".into(),
+        functions: BTreeMap::from([
+            ("dup_range".into(), tohdl_tests::dup_range_str().into()),
+            ("hrange".into(), tohdl_tests::hrange_str().into()),
+        ])
+        .into(),
+    };
+    try_translate(&pycontext).unwrap();
+    let execution = interpret_context(&pycontext, &[2, 9, 3]);
+    assert_eq!(execution.yields, singles(&[2, 3, 5, 6, 8, 9]));
+    assert_eq!(execution.returned, ints(&[0]));
+}
+
+#[test]
+fn nested_generators() {
+    let pycontext = PyContext {
+        main: "nested_range".into(),
+        functions: BTreeMap::from([
+            ("nested_range".into(), tohdl_tests::nested_range_str().into()),
+            ("hrange".into(), tohdl_tests::hrange_str().into()),
+        ])
+        .into(),
+    };
+    try_translate(&pycontext).unwrap();
+    let execution = interpret_context(&pycontext, &[4]);
+    assert_eq!(execution.yields, singles(&[1, 1, 2, 1, 2, 3]));
+}
+
+#[test]
+fn tuple_return() {
+    let pycontext = PyContext {
+        main: "tuple_fib".into(),
+        functions: BTreeMap::from([
+            ("tuple_fib".into(), tohdl_tests::tuple_fib_str().into()),
+            ("divmod_10".into(), tohdl_tests::divmod_10_str().into()),
+        ])
+        .into(),
+    };
+    try_translate(&pycontext).unwrap();
+    let execution = interpret_context(&pycontext, &[8]);
+    let fib = [0, 1, 1, 2, 3, 5, 8, 13];
+    let yields = (0..8).map(|i| ints(&[i, fib[i as usize]])).collect::<Vec<_>>();
+    assert_eq!(execution.yields, yields);
+    assert_eq!(execution.returned, ints(&[2, 1]));
+}
+
+#[test]
+fn floor_division() {
+    // Python rounds `//` and `%` towards negative infinity
+    let pycontext = PyContext {
+        main: "divmod_10".into(),
+        functions: BTreeMap::from([("divmod_10".into(), tohdl_tests::divmod_10_str().into())])
+            .into(),
+    };
+    try_translate(&pycontext).unwrap();
+    for (n, q, r) in [
+        (27, 2, 7),
+        (-27, -3, 3),
+        (-30, -3, 0),
+        (-7, -1, 3),
+        (0, 0, 0),
+    ] {
+        assert_eq!(interpret_context(&pycontext, &[n]).returned, ints(&[q, r]));
+    }
+
+    let pycontext = PyContext {
+        main: "floor_divmod".into(),
+        functions: BTreeMap::from([(
+            "floor_divmod".into(),
+            tohdl_tests::floor_divmod_str().into(),
+        )])
+        .into(),
+    };
+    for (a, b, q, r) in [
+        (7, 2, 3, 1),
+        (-7, 2, -4, 1),
+        (7, -2, -4, -1),
+        (-7, -2, 3, -1),
+        (-6, 3, -2, 0),
+    ] {
+        assert_eq!(
+            interpret_context(&pycontext, &[a, b]).returned,
+            ints(&[q, r])
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use pytohdl::{translate, PyContext};
+use pytohdl::{try_translate, PyContext};
 use tohdl_tests::mod_10_str;
 
 fn if_no_else_str() -> &'static str {
@@ -30,6 +30,6 @@ fn if_no_else() {
         ])
         .into(),
     };
-    let code = translate(&pycontext);
+    let code = try_translate(&pycontext).unwrap();
     println!("{code}")
 }
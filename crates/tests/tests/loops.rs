@@ -1,12 +1,12 @@
 use std::collections::BTreeMap;
 
-use pytohdl::{find_externals, translate, PyContext};
+use pytohdl::{find_externals, try_translate, PyContext};
 use tohdl_codegen::verilog::graph_to_verilog;
 use tohdl_ir::graph::CFG;
 use tohdl_passes::{
     algorithms::inline_extern_func, manager::PassManager, transform::{BraunEtAl, InsertCallNodes, InsertFuncNodes}, BasicTransform
 };
-use tohdl_tests::{aug_assign_str, binary_to_7_seg_str, div_10_str, fib_to_7_seg_str, func_call_str, mod_10_str, return_literal_str, seven_seg_str, while_loop_graph};
+use tohdl_tests::{ints, interpret_context, singles, aug_assign_str, binary_to_7_seg_str, div_10_str, fib_to_7_seg_str, func_call_str, mod_10_str, return_literal_str, seven_seg_str, while_loop_graph};
 
 #[test]
 fn loops() {
@@ -23,5 +23,39 @@ fn loops() {
         ])
         .into(),
     };
-    let code = translate(&pycontext);
+    let code = try_translate(&pycontext).unwrap();
+}
+
+#[test]
+fn for_range() {
+    let pycontext = PyContext {
+        main: "for_range".into(),
+        functions: BTreeMap::from([("for_range".into(), tohdl_tests::for_range_str().into())])
+            .into(),
+    };
+    try_translate(&pycontext).unwrap();
+    let execution = interpret_context(&pycontext, &[15, 4]);
+    let totals = [0, 1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 66, 78, 91, 105];
+    let yields = [&totals[..], &[15, 13, 11, 9, 7, 5, 3, 1], &[12]].concat();
+    assert_eq!(execution.yields, singles(&yields));
+    assert_eq!(execution.returned, ints(&[105]));
+}
+
+#[test]
+fn break_continue() {
+    let pycontext = PyContext {
+        main: "break_continue".into(),
+        functions: BTreeMap::from([(
+            "break_continue".into(),
+            tohdl_tests::break_continue_str().into(),
+        )])
+        .into(),
+    };
+    try_translate(&pycontext).unwrap();
+    let execution = interpret_context(&pycontext, &[7]);
+    assert_eq!(
+        execution.yields,
+        singles(&[1, 1, 1, 2, 1, 3, 4, 1, 3, 5, 5, 1, 3, 5, 7, 7])
+    );
+    assert_eq!(execution.returned, ints(&[7]));
 }
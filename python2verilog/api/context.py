@@ -66,8 +66,7 @@ def context_to_verilog(context: ir.Context, config: CodegenConfig) -> tuple[str,
         )
     except AssertionError:
         module_str = ver_code_gen.get_module_str()
-    except BaseException as e:  # pylint: disable=broad-exception-caught
-        assert "pyo3_runtime" in str(e.__class__), str(e)
+    except pytohdl.TranslationError as e:  # pylint: disable=no-member
         module_str = ver_code_gen.get_module_str()
         logging.info(
             "Failed to use Rust backend, falling back to Python backend with error: %s",
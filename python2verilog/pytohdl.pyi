@@ -3,6 +3,32 @@
 Python to Verilog translater based on ToHDL
 """
 
+from typing import Optional
+
+class TranslationError(Exception):
+    """
+    Base class of all errors raised while translating Python to HDL
+    """
+
+    function: str
+    line: Optional[int]
+    column: Optional[int]
+
+class UnsupportedSyntaxError(TranslationError):
+    """
+    Python source uses syntax outside of the supported subset
+    """
+
+class UnknownFunctionError(TranslationError):
+    """
+    A called function is not part of the translation context
+    """
+
+class InternalCompilerError(TranslationError):
+    """
+    The compiler failed on input that it accepted, this is a bug
+    """
+
 class PyContext:
     """
     Context for a Python function and all of its called upon functions
@@ -11,3 +37,4 @@ class PyContext:
     def __init__(self, main: str, functions: dict[str, str]): ...
 
 def translate(context: PyContext) -> str: ...
+def python_to_python_fsm(code: str) -> str: ...
@@ -0,0 +1,21 @@
+"""
+Integer type annotations understood by the Rust backend
+
+``u<N>`` is an unsigned ``N``-bit integer and ``i<N>`` is a signed ``N``-bit integer,
+e.g. ``def func(a: u8, b: i12) -> u16``.
+They are plain ``int`` at runtime, so annotated generators still run as Python.
+"""
+
+import re
+
+u1 = u2 = u4 = u8 = u16 = u32 = u64 = int
+i2 = i4 = i8 = i16 = i32 = i64 = int
+
+
+def __getattr__(name: str):
+    """
+    Any other width, e.g. ``types.u12``
+    """
+    if re.fullmatch(r"[ui][1-9][0-9]*", name):
+        return int
+    raise AttributeError(f"module {__name__!r} has no attribute {name!r}")
@@ -0,0 +1,31 @@
+import unittest
+
+from python2verilog import pytohdl
+
+
+class TestTranslationErrors(unittest.TestCase):
+    def test_unsupported_syntax(self):
+        code = """
+def func(n):
+    return n ** 2
+"""
+        with self.assertRaises(pytohdl.UnsupportedSyntaxError) as ctx:
+            pytohdl.translate(pytohdl.PyContext("func", {"func": code}))
+        self.assertEqual(ctx.exception.function, "func")
+        self.assertEqual(ctx.exception.line, 3)
+        self.assertEqual(ctx.exception.column, 12)
+
+    def test_unknown_function(self):
+        code = """
+def func(n):
+    x = missing(n)
+    return x
+"""
+        with self.assertRaises(pytohdl.UnknownFunctionError) as ctx:
+            pytohdl.translate(pytohdl.PyContext("func", {"func": code}))
+        self.assertEqual(ctx.exception.function, "func")
+        self.assertEqual(ctx.exception.line, 3)
+
+    def test_base_class(self):
+        with self.assertRaises(pytohdl.TranslationError):
+            pytohdl.python_to_python_fsm("def func(n):\n    return 1.5\n")